@@ -1,5 +1,36 @@
 pub struct Code;
 
+pub static COMP: &'static [(&'static str, &'static str)] = &[
+    ("0", "0101010"),
+    ("1", "0111111"),
+    ("-1", "0111010"),
+    ("D", "0001100"),
+    ("A", "0110000"),
+    ("!D", "0001101"),
+    ("!A", "0110001"),
+    ("-D", "0001111"),
+    ("-A", "0110011"),
+    ("D+1", "0011111"),
+    ("A+1", "0110111"),
+    ("D-1", "0001110"),
+    ("A-1", "0110010"),
+    ("D+A", "0000010"),
+    ("D-A", "0010011"),
+    ("A-D", "0000111"),
+    ("D&A", "0000000"),
+    ("D|A", "0010101"),
+    ("M", "1110000"),
+    ("!M", "1110001"),
+    ("-M", "1110011"),
+    ("M+1", "1110111"),
+    ("M-1", "1110010"),
+    ("D+M", "1000010"),
+    ("D-M", "1010011"),
+    ("M-D", "1000111"),
+    ("D&M", "1000000"),
+    ("D|M", "1010101"),
+];
+
 impl Code {
     pub fn dest(mnemonic: &str) -> &'static str {
         let d1 = mnemonic.contains('A');
@@ -21,39 +52,10 @@ impl Code {
             mnemonic => mnemonic
         };
 
-        let code = match canonical {
-            "0" => "0101010",
-            "1" => "0111111",
-            "-1" => "0111010",
-            "D" => "0001100",
-            "A" => "0110000",
-            "!D" => "0001101",
-            "!A" => "0110001",
-            "-D" => "0001111",
-            "-A" => "0110011",
-            "D+1" => "0011111",
-            "A+1" => "0110111",
-            "D-1" => "0001110",
-            "A-1" => "0110010",
-            "D+A" => "0000010",
-            "D-A" => "0010011",
-            "A-D" => "0000111",
-            "D&A" => "0000000",
-            "D|A" => "0010101",
-            "M" => "1110000",
-            "!M" => "1110001",
-            "-M" => "1110011",
-            "M+1" => "1110111",
-            "M-1" => "1110010",
-            "D+M" => "1000010",
-            "D-M" => "1010011",
-            "M-D" => "1000111",
-            "D&M" => "1000000",
-            "D|M" => "1010101",
-            mnemonic => return Err(format!("Unknown mnemonic: {}", mnemonic))
-        };
-
-        Ok(code)
+        COMP.iter()
+            .find(|&&(m, _)| m == canonical)
+            .map(|&(_, code)| code)
+            .ok_or_else(|| format!("Unknown mnemonic: {}", mnemonic))
     }
 
     pub fn jump(mnemonic: &str) -> &'static str {
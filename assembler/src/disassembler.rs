@@ -0,0 +1,45 @@
+use code::COMP;
+
+/// Translate a single machine instruction back into Hack assembly.
+/// C-instructions with an unknown computation are shown as raw bits.
+pub fn disassemble(instruction: u16) -> String {
+    if instruction & (1 << 15) == 0 {
+        return format!("@{}", instruction)
+    }
+
+    let comp_bits = format!("{:07b}", (instruction >> 6) & 0x7F);
+    let comp = match COMP.iter().find(|&&(_, code)| code == comp_bits) {
+        Some(&(mnemonic, _)) => mnemonic.to_string(),
+        None => format!("?{}", comp_bits)
+    };
+
+    let bit = |i: u16| instruction & (1 << i) != 0;
+
+    let dest = [(5, 'A'), (3, 'M'), (4, 'D')].iter()
+                                             .filter(|&&(i, _)| bit(i))
+                                             .map(|&(_, c)| c)
+                                             .collect::<String>();
+
+    let jump = match instruction & 0b111 {
+        0b000 => "",
+        0b001 => "JGT",
+        0b010 => "JEQ",
+        0b011 => "JGE",
+        0b100 => "JLT",
+        0b101 => "JNE",
+        0b110 => "JLE",
+        _ => "JMP",
+    };
+
+    let mut out = String::new();
+    if !dest.is_empty() {
+        out.push_str(&dest);
+        out.push('=');
+    }
+    out.push_str(&comp);
+    if !jump.is_empty() {
+        out.push(';');
+        out.push_str(jump);
+    }
+    out
+}
@@ -1,8 +1,10 @@
 pub use code::Code;
+pub use disassembler::disassemble;
 pub use parser::{Command, Parser};
 pub use symbol_table::SymbolTable;
 
 mod code;
+mod disassembler;
 mod parser;
 mod symbol_table;
 
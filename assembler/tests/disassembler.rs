@@ -0,0 +1,32 @@
+extern crate hack_assembler;
+
+#[macro_use] mod macros;
+
+check! {
+    disassemble for {
+        address     0b0000000000000000 => "@0",
+        max_address 0b0111111111111111 => "@32767",
+        comp_only   0b1110101010000000 => "0",
+        dest        0b1111110000010000 => "D=M",
+        all_dests   0b1110111111111000 => "AMD=1",
+        jump        0b1110101010000111 => "0;JMP",
+        dest_jump   0b1110001100010101 => "D=D;JNE",
+        a_bit       0b1111000010001000 => "M=D+M",
+        unknown     0b1110111110000000 => "?0111110"
+    } do |instruction, asm| {
+        assert_eq!(disassemble(instruction), asm)
+    }
+}
+
+#[test]
+fn roundtrips_programs() {
+    use hack_assembler::*;
+
+    let program = "@17\nD=A\n@SCREEN\nM=D\nAM=M-1\nD;JGT\n0;JMP\nMD=!M\n";
+    let binary = assemble(program).unwrap();
+    let disassembled = binary.lines()
+                             .map(|l| disassemble(u16::from_str_radix(l, 2).unwrap()))
+                             .collect::<Vec<_>>();
+
+    assert_eq!(disassembled, ["@17", "D=A", "@16384", "M=D", "AM=M-1", "D;JGT", "0;JMP", "MD=!M"]);
+}
@@ -24,5 +24,5 @@ else
         arg1="${dir}/$1"
     fi
 #   echo Running "$arg1"
-    ./target/release/hack_simulator --runner "$arg1"
+    ./target/release/hack test "$arg1"
 fi
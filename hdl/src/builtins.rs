@@ -0,0 +1,358 @@
+use chip::Word;
+use parser::{ChipDef, Pin};
+
+/// A chip implemented in Rust, its pins' values passed in the order the
+/// pins are declared
+pub trait Builtin {
+    /// Set the outputs from the inputs not clocked and the state
+    fn eval(&mut self, inputs: &[Word], outputs: &mut [Word]);
+
+    /// The first half of a clock cycle: latch what the state will become
+    fn tick(&mut self, _inputs: &[Word]) {}
+
+    /// The second half of a clock cycle: change the state to what was latched
+    fn tock(&mut self) {}
+
+    /// The registers or words of memory holding the state
+    fn memory(&self) -> Option<&[Word]> {
+        None
+    }
+
+    fn memory_mut(&mut self) -> Option<&mut [Word]> {
+        None
+    }
+
+    /// The address of the word written on the last clock cycle
+    fn written(&self) -> Option<usize> {
+        None
+    }
+}
+
+pub struct BuiltinDef {
+    pub name: &'static str,
+    pub inputs: &'static [(&'static str, usize)],
+    pub outputs: &'static [(&'static str, usize)],
+    /// The inputs only read on the clock, which don't change the outputs
+    /// straight away
+    pub clocked: &'static [&'static str],
+    pub new: fn() -> Box<Builtin>,
+}
+
+impl BuiltinDef {
+    pub fn def(&self) -> ChipDef {
+        let pins = |pins: &[(&str, usize)]| pins.iter().map(|&(name, width)| Pin { name: name.to_string(), width: width })
+                                                .collect();
+        ChipDef {
+            name: self.name.to_string(),
+            inputs: pins(self.inputs),
+            outputs: pins(self.outputs),
+            parts: vec![],
+            builtin: Some(self.name.to_string()),
+            clocked: self.clocked.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+}
+
+struct Logic(fn(&[Word], &mut [Word]));
+
+impl Builtin for Logic {
+    fn eval(&mut self, inputs: &[Word], outputs: &mut [Word]) {
+        (self.0)(inputs, outputs)
+    }
+}
+
+// A register, like Bit or Register, or the program counter
+struct Register {
+    value: [Word; 1],
+    next: Option<Word>,
+    counter: bool,
+}
+
+impl Builtin for Register {
+    fn eval(&mut self, _: &[Word], outputs: &mut [Word]) {
+        outputs[0] = self.value[0];
+    }
+
+    fn tick(&mut self, inputs: &[Word]) {
+        // A counter has `inc` and `reset` inputs too
+        let value = self.value[0];
+        self.next = Some(if self.counter && inputs[3] != 0 {
+            0
+        } else if inputs[1] != 0 {
+            inputs[0]
+        } else if self.counter && inputs[2] != 0 {
+            value.wrapping_add(1)
+        } else {
+            value
+        });
+    }
+
+    fn tock(&mut self) {
+        if let Some(next) = self.next.take() {
+            self.value[0] = next;
+        }
+    }
+
+    fn memory(&self) -> Option<&[Word]> {
+        Some(&self.value)
+    }
+
+    fn memory_mut(&mut self) -> Option<&mut [Word]> {
+        Some(&mut self.value)
+    }
+}
+
+fn register() -> Box<Builtin> {
+    Box::new(Register { value: [0], next: None, counter: false })
+}
+
+// RAM, the screen's memory or all of the computer's, with `in`, `load` and
+// `address` inputs
+struct Ram {
+    words: Vec<Word>,
+    // Past the end are read-only words, the keyboard for Memory
+    writable: usize,
+    write: Option<(usize, Word)>,
+    written: Option<usize>,
+}
+
+impl Builtin for Ram {
+    fn eval(&mut self, inputs: &[Word], outputs: &mut [Word]) {
+        outputs[0] = self.words.get(inputs[2] as usize).cloned().unwrap_or(0);
+    }
+
+    fn tick(&mut self, inputs: &[Word]) {
+        let address = inputs[2] as usize;
+        self.write = if inputs[1] != 0 && address < self.writable { Some((address, inputs[0])) } else { None };
+    }
+
+    fn tock(&mut self) {
+        self.written = self.write.take().map(|(address, value)| {
+            self.words[address] = value;
+            address
+        });
+    }
+
+    fn memory(&self) -> Option<&[Word]> {
+        Some(&self.words)
+    }
+
+    fn memory_mut(&mut self) -> Option<&mut [Word]> {
+        Some(&mut self.words)
+    }
+
+    fn written(&self) -> Option<usize> {
+        self.written
+    }
+}
+
+fn ram(size: usize, writable: usize) -> Box<Builtin> {
+    Box::new(Ram { words: vec![0; size], writable: writable, write: None, written: None })
+}
+
+// The keyboard, with the key pressed set through its memory
+struct Keyboard([Word; 1]);
+
+impl Builtin for Keyboard {
+    fn eval(&mut self, _: &[Word], outputs: &mut [Word]) {
+        outputs[0] = self.0[0];
+    }
+
+    fn memory(&self) -> Option<&[Word]> {
+        Some(&self.0)
+    }
+
+    fn memory_mut(&mut self) -> Option<&mut [Word]> {
+        Some(&mut self.0)
+    }
+}
+
+// The instruction memory, with the program loaded through its memory
+struct Rom(Vec<Word>);
+
+impl Builtin for Rom {
+    fn eval(&mut self, inputs: &[Word], outputs: &mut [Word]) {
+        outputs[0] = self.0[inputs[0] as usize];
+    }
+
+    fn memory(&self) -> Option<&[Word]> {
+        Some(&self.0)
+    }
+
+    fn memory_mut(&mut self) -> Option<&mut [Word]> {
+        Some(&mut self.0)
+    }
+}
+
+fn alu(inputs: &[Word], outputs: &mut [Word]) {
+    let (zx, nx, zy, ny, f, no) = (inputs[2], inputs[3], inputs[4], inputs[5], inputs[6], inputs[7]);
+    let x = if zx != 0 { 0 } else { inputs[0] };
+    let x = if nx != 0 { !x } else { x };
+    let y = if zy != 0 { 0 } else { inputs[1] };
+    let y = if ny != 0 { !y } else { y };
+    let out = if f != 0 { x.wrapping_add(y) } else { x & y };
+    let out = if no != 0 { !out } else { out };
+    outputs[0] = out;
+    outputs[1] = (out == 0) as Word;
+    outputs[2] = out >> 15;
+}
+
+// Outputs wider than 1 bit are given all of `value`, which the simulator cuts
+// down to the width of the pin
+fn dmux(outputs: &mut [Word], value: Word, sel: Word) {
+    for (i, output) in outputs.iter_mut().enumerate() {
+        *output = if i == sel as usize { value } else { 0 };
+    }
+}
+
+static PIN: &'static [(&'static str, usize)] = &[("in", 1)];
+static BUS: &'static [(&'static str, usize)] = &[("in", 16)];
+static OUT: &'static [(&'static str, usize)] = &[("out", 1)];
+static OUT16: &'static [(&'static str, usize)] = &[("out", 16)];
+static AB: &'static [(&'static str, usize)] = &[("a", 1), ("b", 1)];
+static AB16: &'static [(&'static str, usize)] = &[("a", 16), ("b", 16)];
+static ABCD: &'static [(&'static str, usize)] = &[("a", 1), ("b", 1), ("c", 1), ("d", 1)];
+static ATOH: &'static [(&'static str, usize)] = &[("a", 1), ("b", 1), ("c", 1), ("d", 1),
+                                                  ("e", 1), ("f", 1), ("g", 1), ("h", 1)];
+static SUM: &'static [(&'static str, usize)] = &[("sum", 1), ("carry", 1)];
+static REGISTER: &'static [(&'static str, usize)] = &[("in", 16), ("load", 1)];
+static LOADED: &'static [&'static str] = &["in", "load"];
+
+macro_rules! ram {
+    ($name:expr, $bits:expr) => {
+        BuiltinDef {
+            name: $name,
+            inputs: &[("in", 16), ("load", 1), ("address", $bits)],
+            outputs: OUT16,
+            clocked: LOADED,
+            new: || ram(1 << $bits, 1 << $bits),
+        }
+    }
+}
+
+macro_rules! logic {
+    ($name:expr, $inputs:expr, $outputs:expr, $function:expr) => {
+        BuiltinDef { name: $name, inputs: $inputs, outputs: $outputs, clocked: &[], new: || Box::new(Logic($function)) }
+    }
+}
+
+static BUILTINS: &'static [BuiltinDef] = &[
+    logic!("Not", PIN, OUT, |i, o| o[0] = !i[0]),
+    logic!("And", AB, OUT, |i, o| o[0] = i[0] & i[1]),
+    logic!("Or", AB, OUT, |i, o| o[0] = i[0] | i[1]),
+    logic!("Xor", AB, OUT, |i, o| o[0] = i[0] ^ i[1]),
+    logic!("Mux", &[("a", 1), ("b", 1), ("sel", 1)], OUT, |i, o| o[0] = i[i[2] as usize]),
+    logic!("DMux", &[("in", 1), ("sel", 1)], AB, |i, o| dmux(o, i[0], i[1])),
+    logic!("Not16", BUS, OUT16, |i, o| o[0] = !i[0]),
+    logic!("And16", AB16, OUT16, |i, o| o[0] = i[0] & i[1]),
+    logic!("Or16", AB16, OUT16, |i, o| o[0] = i[0] | i[1]),
+    logic!("Mux16", &[("a", 16), ("b", 16), ("sel", 1)], OUT16, |i, o| o[0] = i[i[2] as usize]),
+    logic!("Or8Way", &[("in", 8)], OUT, |i, o| o[0] = (i[0] != 0) as Word),
+    logic!("Mux4Way16", &[("a", 16), ("b", 16), ("c", 16), ("d", 16), ("sel", 2)], OUT16,
+           |i, o| o[0] = i[i[4] as usize]),
+    logic!("Mux8Way16", &[("a", 16), ("b", 16), ("c", 16), ("d", 16), ("e", 16), ("f", 16), ("g", 16), ("h", 16),
+                          ("sel", 3)], OUT16,
+           |i, o| o[0] = i[i[8] as usize]),
+    logic!("DMux4Way", &[("in", 1), ("sel", 2)], ABCD, |i, o| dmux(o, i[0], i[1])),
+    logic!("DMux8Way", &[("in", 1), ("sel", 3)], ATOH, |i, o| dmux(o, i[0], i[1])),
+    logic!("HalfAdder", AB, SUM, |i, o| {
+        o[0] = i[0] ^ i[1];
+        o[1] = i[0] & i[1];
+    }),
+    logic!("FullAdder", &[("a", 1), ("b", 1), ("c", 1)], SUM, |i, o| {
+        let sum = i[0] + i[1] + i[2];
+        o[0] = sum & 1;
+        o[1] = sum >> 1;
+    }),
+    logic!("Add16", AB16, OUT16, |i, o| o[0] = i[0].wrapping_add(i[1])),
+    logic!("Inc16", BUS, OUT16, |i, o| o[0] = i[0].wrapping_add(1)),
+    logic!("ALU", &[("x", 16), ("y", 16), ("zx", 1), ("nx", 1), ("zy", 1), ("ny", 1), ("f", 1), ("no", 1)],
+           &[("out", 16), ("zr", 1), ("ng", 1)], alu),
+    BuiltinDef { name: "Bit", inputs: &[("in", 1), ("load", 1)], outputs: OUT, clocked: LOADED, new: register },
+    BuiltinDef { name: "Register", inputs: REGISTER, outputs: OUT16, clocked: LOADED, new: register },
+    // The CPU's registers, told apart so their values can be followed
+    BuiltinDef { name: "ARegister", inputs: REGISTER, outputs: OUT16, clocked: LOADED, new: register },
+    BuiltinDef { name: "DRegister", inputs: REGISTER, outputs: OUT16, clocked: LOADED, new: register },
+    BuiltinDef {
+        name: "PC",
+        inputs: &[("in", 16), ("load", 1), ("inc", 1), ("reset", 1)],
+        outputs: OUT16,
+        clocked: &["in", "load", "inc", "reset"],
+        new: || Box::new(Register { value: [0], next: None, counter: true }),
+    },
+    ram!("RAM8", 3),
+    ram!("RAM64", 6),
+    ram!("RAM512", 9),
+    ram!("RAM4K", 12),
+    ram!("RAM16K", 14),
+    ram!("Screen", 13),
+    BuiltinDef { name: "Keyboard", inputs: &[], outputs: OUT16, clocked: &[], new: || Box::new(Keyboard([0])) },
+    BuiltinDef {
+        name: "ROM32K",
+        inputs: &[("address", 15)],
+        outputs: OUT16,
+        clocked: &[],
+        new: || Box::new(Rom(vec![0; 1 << 15])),
+    },
+    // RAM16K, Screen and Keyboard at their addresses in the computer
+    BuiltinDef {
+        name: "Memory",
+        inputs: &[("in", 16), ("load", 1), ("address", 15)],
+        outputs: OUT16,
+        clocked: LOADED,
+        new: || ram(0x6001, 0x6000),
+    },
+];
+
+/// The built-in chip called `name`
+pub fn builtin(name: &str) -> Option<&'static BuiltinDef> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(name: &str, inputs: &[Word]) -> Vec<Word> {
+        let def = builtin(name).unwrap();
+        let mut outputs = vec![0; def.outputs.len()];
+        (def.new)().eval(inputs, &mut outputs);
+        outputs
+    }
+
+    #[test]
+    fn logic() {
+        assert_eq!(eval("Mux4Way16", &[10, 11, 12, 13, 2]), vec![12]);
+        assert_eq!(eval("DMux4Way", &[1, 3]), vec![0, 0, 0, 1]);
+        assert_eq!(eval("FullAdder", &[1, 1, 1]), vec![1, 1]);
+        // x - y as !(!x + y)
+        assert_eq!(eval("ALU", &[7, 9, 0, 1, 0, 0, 1, 1]), vec![0xFFFE, 0, 1]);
+        assert_eq!(eval("ALU", &[7, 7, 0, 1, 0, 0, 1, 1]), vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn memory() {
+        let mut ram = (builtin("RAM8").unwrap().new)();
+        let mut out = [0];
+        ram.tick(&[42, 1, 5]);
+        ram.eval(&[0, 0, 5], &mut out);
+        assert_eq!((out[0], ram.written()), (0, None));
+        ram.tock();
+        ram.eval(&[0, 0, 5], &mut out);
+        assert_eq!((out[0], ram.written()), (42, Some(5)));
+
+        let mut memory = (builtin("Memory").unwrap().new)();
+        memory.tick(&[42, 1, 0x6000]);
+        memory.tock();
+        assert_eq!(memory.written(), None);
+
+        let mut pc = (builtin("PC").unwrap().new)();
+        for &(inputs, out) in &[([0, 0, 1, 0], 1), ([9, 1, 1, 0], 9), ([0, 0, 1, 0], 10), ([9, 1, 1, 1], 0)] {
+            let mut outputs = [0];
+            pc.tick(&inputs);
+            pc.tock();
+            pc.eval(&inputs, &mut outputs);
+            assert_eq!(outputs[0], out);
+        }
+    }
+}
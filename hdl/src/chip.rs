@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use builtins::{self, Builtin, BuiltinDef};
+use parser::{parse, ChipDef, Pin, PinRef, Value};
+
+pub type Word = u16;
+
+// Wires are single bits, merged into nets when connected
+type Wire = usize;
+const FALSE: Wire = 0;
+const TRUE: Wire = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind { Input, Output, Internal }
+
+// Flattens a chip and its parts into Nand gates, DFFs and built-in chips
+struct Builder<'a> {
+    find: &'a mut FnMut(&str) -> Result<Option<String>, String>,
+    defs: HashMap<String, Rc<ChipDef>>,
+    // Chips being built, to catch those built from themselves
+    building: Vec<String>,
+    names: Vec<String>,
+    parent: Vec<Wire>,
+    driven: Vec<bool>,
+    nands: Vec<(Wire, Wire, Wire)>,
+    dffs: Vec<(Wire, Wire)>,
+    // Built-in chips with the wires of their inputs and outputs
+    blocks: Vec<(&'static BuiltinDef, Vec<Vec<Wire>>, Vec<Vec<Wire>>)>,
+}
+
+fn primitive(name: &str) -> Option<ChipDef> {
+    let pin = |name: &str| Pin { name: name.to_string(), width: 1 };
+    let (inputs, outputs) = match name {
+        "Nand" => (vec![pin("a"), pin("b")], vec![pin("out")]),
+        "DFF" => (vec![pin("in")], vec![pin("out")]),
+        _ => return None,
+    };
+    Some(ChipDef { name: name.to_string(), inputs: inputs, outputs: outputs, parts: vec![], builtin: None, clocked: vec![] })
+}
+
+// Parse the HDL of the chip `name`, checking it's what it says it is
+fn parse_file(name: &str, source: &str) -> Result<ChipDef, String> {
+    let def = try!(parse(source).map_err(|e| format!("{}.hdl: {}", name, e)));
+    if def.name != name {
+        return Err(format!("{}.hdl: The chip is called '{}'", name, def.name))
+    }
+    if let Some(ref chip) = def.builtin {
+        let builtin = match builtins::builtin(chip) {
+            Some(builtin) => builtin.def(),
+            None => return Err(format!("{}.hdl: Unknown built-in chip: '{}'", name, chip)),
+        };
+        if builtin.inputs != def.inputs || builtin.outputs != def.outputs {
+            return Err(format!("{}.hdl: The pins aren't those of the built-in {}", name, chip))
+        }
+    }
+    Ok(def)
+}
+
+// The wires of `pin`, or of some of its bits
+fn slice(wires: &[Wire], pin: &PinRef) -> Result<Vec<Wire>, String> {
+    match pin.bits {
+        None => Ok(wires.to_vec()),
+        Some((_, last)) if last >= wires.len() => {
+            Err(format!("'{}' is out of range, '{}' is {} bits wide", pin, pin.name, wires.len()))
+        }
+        Some((first, last)) => Ok(wires[first..last + 1].to_vec()),
+    }
+}
+
+impl<'a> Builder<'a> {
+    fn wire(&mut self, name: String, driven: bool) -> Wire {
+        self.names.push(name);
+        self.parent.push(self.parent.len());
+        self.driven.push(driven);
+        self.parent.len() - 1
+    }
+
+    fn wires(&mut self, name: &str, width: usize, driven: bool) -> Vec<Wire> {
+        if width == 1 {
+            return vec![self.wire(name.to_string(), driven)]
+        }
+        (0..width).map(|bit| self.wire(format!("{}[{}]", name, bit), driven)).collect()
+    }
+
+    fn root(&mut self, wire: Wire) -> Wire {
+        let mut root = wire;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut wire = wire;
+        while self.parent[wire] != root {
+            let next = self.parent[wire];
+            self.parent[wire] = root;
+            wire = next;
+        }
+        root
+    }
+
+    // Join two wires into one net, which only one of them may drive
+    fn connect(&mut self, a: Wire, b: Wire) -> bool {
+        let (a, b) = (self.root(a), self.root(b));
+        if a == b {
+            return true
+        }
+        if self.driven[a] && self.driven[b] {
+            return false
+        }
+        self.parent[b] = a;
+        self.driven[a] = self.driven[a] || self.driven[b];
+        true
+    }
+
+    fn def(&mut self, name: &str) -> Result<Option<Rc<ChipDef>>, String> {
+        if let Some(def) = self.defs.get(name) {
+            return Ok(Some(def.clone()))
+        }
+        // Chips without HDL of their own are built in, if there's such a chip
+        let def = match primitive(name) {
+            Some(def) => def,
+            None => match try!((self.find)(name)) {
+                Some(source) => try!(parse_file(name, &source)),
+                None => match builtins::builtin(name) {
+                    Some(builtin) => builtin.def(),
+                    None => return Ok(None),
+                },
+            },
+        };
+
+        let def = Rc::new(def);
+        self.defs.insert(name.to_string(), def.clone());
+        Ok(Some(def))
+    }
+
+    // Build the parts of `def`, given the wires of its inputs, giving the
+    // wires of all its pins
+    fn instantiate(&mut self, def: &ChipDef, path: &str, inputs: Vec<Vec<Wire>>)
+                   -> Result<HashMap<String, (Kind, Vec<Wire>)>, String> {
+        let mut pins = HashMap::new();
+        for (pin, wires) in def.inputs.iter().zip(inputs) {
+            pins.insert(pin.name.clone(), (Kind::Input, wires));
+        }
+        // Only the gates and built-in chips drive their outputs themselves
+        let driven = primitive(&def.name).is_some() || def.builtin.is_some();
+        for pin in &def.outputs {
+            let wires = self.wires(&format!("{}.{}", path, pin.name), pin.width, driven);
+            pins.insert(pin.name.clone(), (Kind::Output, wires));
+        }
+
+        match &def.name[..] {
+            "Nand" => {
+                let (a, b, out) = (pins["a"].1[0], pins["b"].1[0], pins["out"].1[0]);
+                self.nands.push((a, b, out));
+                return Ok(pins)
+            }
+            "DFF" => {
+                let (input, out) = (pins["in"].1[0], pins["out"].1[0]);
+                self.dffs.push((input, out));
+                return Ok(pins)
+            }
+            _ => {}
+        }
+        if let Some(ref chip) = def.builtin {
+            let inputs = def.inputs.iter().map(|pin| pins[&pin.name].1.clone()).collect();
+            let outputs = def.outputs.iter().map(|pin| pins[&pin.name].1.clone()).collect();
+            self.blocks.push((builtins::builtin(chip).unwrap(), inputs, outputs));
+            return Ok(pins)
+        }
+
+        if self.building.iter().any(|name| *name == def.name) {
+            return Err(format!("{}.hdl: The chip is built from itself", def.name))
+        }
+        self.building.push(def.name.clone());
+
+        // The parts' definitions, and the widths of internal pins from the
+        // part outputs driving them
+        let mut part_defs = vec![];
+        for part in &def.parts {
+            let error = |message: String| format!("{}.hdl: line {}: {}", def.name, part.line, message);
+            let part_def = match try!(self.def(&part.chip)) {
+                Some(part_def) => part_def,
+                None => return Err(error(format!("Unknown chip: '{}'", part.chip))),
+            };
+
+            for connection in &part.connections {
+                let pin = &connection.pin;
+                let width = match part_def.input(&pin.name).or(part_def.output(&pin.name)) {
+                    Some(part_pin) => {
+                        try!(slice(&vec![FALSE; part_pin.width], pin).map_err(&error));
+                        pin.width(part_pin.width)
+                    }
+                    None => return Err(error(format!("'{}' has no pin '{}'", part.chip, pin.name))),
+                };
+                if part_def.output(&pin.name).is_none() {
+                    continue
+                }
+
+                let target = match connection.value {
+                    Value::Pin(ref target) => target,
+                    Value::Const(_) => return Err(error(format!("The output '{}' can't be connected to a constant", pin))),
+                };
+                match pins.get(&target.name).map(|&(kind, _)| kind) {
+                    Some(Kind::Input) => return Err(error(format!("The input pin '{}' can't be driven by a part", target.name))),
+                    Some(Kind::Output) => continue,
+                    _ => {}
+                }
+                if target.bits.is_some() {
+                    return Err(error(format!("The internal pin '{}' can't be subscripted", target.name)))
+                }
+                if !pins.contains_key(&target.name) {
+                    let wires = self.wires(&format!("{}.{}", path, target.name), width, false);
+                    pins.insert(target.name.clone(), (Kind::Internal, wires));
+                }
+            }
+            part_defs.push(part_def);
+        }
+
+        for (part, part_def) in def.parts.iter().zip(part_defs) {
+            let error = |message: String| format!("{}.hdl: line {}: {}", def.name, part.line, message);
+            let width_error = |pin: &PinRef, width: usize, value: &PinRef, value_width: usize| {
+                error(format!("'{}' of '{}' is {} bits wide, but '{}' is {}", pin, part.chip, width, value, value_width))
+            };
+
+            // Unconnected inputs are false
+            let mut inputs: Vec<Vec<Wire>> = part_def.inputs.iter().map(|pin| vec![FALSE; pin.width]).collect();
+            for connection in &part.connections {
+                let pin = &connection.pin;
+                let index = match part_def.inputs.iter().position(|input| input.name == pin.name) {
+                    Some(index) => index,
+                    None => continue,
+                };
+                let width = pin.width(inputs[index].len());
+                let wires = match connection.value {
+                    Value::Const(value) => vec![if value { TRUE } else { FALSE }; width],
+                    Value::Pin(ref value) => match pins.get(&value.name) {
+                        None => return Err(error(format!("Unknown pin: '{}'", value.name))),
+                        Some(&(Kind::Output, _)) => {
+                            return Err(error(format!("The output pin '{}' can't be used as an input", value.name)))
+                        }
+                        Some(&(_, ref wires)) => {
+                            let wires = try!(slice(wires, value).map_err(&error));
+                            if wires.len() != width {
+                                return Err(width_error(pin, width, value, wires.len()))
+                            }
+                            wires
+                        }
+                    },
+                };
+                let first = pin.bits.map_or(0, |(first, _)| first);
+                for (bit, wire) in wires.into_iter().enumerate() {
+                    inputs[index][first + bit] = wire;
+                }
+            }
+
+            let part_path = format!("{}/{}:{}", path, part.chip, part.line);
+            let outputs = try!(self.instantiate(&part_def, &part_path, inputs));
+            for connection in &part.connections {
+                let pin = &connection.pin;
+                let target = match (part_def.output(&pin.name), &connection.value) {
+                    (Some(_), &Value::Pin(ref target)) => target,
+                    _ => continue,
+                };
+                let sources = try!(slice(&outputs[&pin.name].1, pin).map_err(&error));
+                let targets = try!(slice(&pins[&target.name].1, target).map_err(&error));
+                if sources.len() != targets.len() {
+                    return Err(width_error(pin, sources.len(), target, targets.len()))
+                }
+                for (&source, &target_wire) in sources.iter().zip(&targets) {
+                    if !self.connect(target_wire, source) {
+                        return Err(error(format!("'{}' is driven by more than one part", target)))
+                    }
+                }
+            }
+        }
+
+        self.building.pop();
+        Ok(pins)
+    }
+}
+
+enum Gate {
+    Nand(usize, usize, usize),
+    Block(usize),
+}
+
+// A built-in chip and the nets of its pins
+struct Block {
+    name: &'static str,
+    chip: Box<Builtin>,
+    clocked: bool,
+    inputs: Vec<Vec<usize>>,
+    outputs: Vec<Vec<usize>>,
+}
+
+fn read(values: &[bool], nets: &[usize]) -> Word {
+    nets.iter().enumerate().fold(0, |value, (bit, &net)| value | (values[net] as Word) << bit)
+}
+
+fn write(values: &mut [bool], nets: &[usize], value: Word) {
+    for (bit, &net) in nets.iter().enumerate() {
+        values[net] = value & 1 << bit != 0;
+    }
+}
+
+/// A chip flattened into Nand gates, DFFs and built-in chips, ready to
+/// simulate
+pub struct Chip {
+    pub name: String,
+    pub inputs: Vec<Pin>,
+    pub outputs: Vec<Pin>,
+    // The nets of each bit of the chip's pins, internal ones included
+    pins: HashMap<String, Vec<usize>>,
+    values: Vec<bool>,
+    // Combinational gates and built-in chips in the order they're evaluated
+    gates: Vec<Gate>,
+    blocks: Vec<Block>,
+    dffs: Vec<(usize, usize)>,
+    // What each DFF latched on the last tick
+    latched: Vec<bool>,
+}
+
+impl Chip {
+    /// Build the chip `name`, getting the HDL of it and its parts from
+    /// `find`, which gives `None` for chips it doesn't know
+    pub fn new(name: &str, find: &mut FnMut(&str) -> Result<Option<String>, String>) -> Result<Chip, String> {
+        let mut builder = Builder {
+            find: find,
+            defs: HashMap::new(),
+            building: vec![],
+            names: vec![],
+            parent: vec![],
+            driven: vec![],
+            nands: vec![],
+            dffs: vec![],
+            blocks: vec![],
+        };
+        builder.wire("false".to_string(), true);
+        builder.wire("true".to_string(), true);
+
+        let def = match try!(builder.def(name)) {
+            Some(def) => def,
+            None => return Err(format!("Unknown chip: '{}'", name)),
+        };
+        let inputs: Vec<Vec<Wire>> = def.inputs.iter()
+                                                .map(|pin| builder.wires(&format!("{}.{}", name, pin.name), pin.width, true))
+                                                .collect();
+        let pins = try!(builder.instantiate(&def, name, inputs));
+
+        // Number the nets, naming each after its shortest wire name, which
+        // is the one nearest to the top of the chip
+        let mut nets = vec![usize::max_value(); builder.parent.len()];
+        let mut names: Vec<String> = vec![];
+        for wire in 0..builder.parent.len() {
+            let root = builder.root(wire);
+            if nets[root] == usize::max_value() {
+                nets[root] = names.len();
+                names.push(builder.names[wire].clone());
+            }
+            nets[wire] = nets[root];
+            if builder.names[wire].len() < names[nets[wire]].len() {
+                names[nets[wire]] = builder.names[wire].clone();
+            }
+        }
+
+        // The nets each gate reads and drives, the Nands followed by the
+        // built-in chips, which don't read clocked inputs until the clock
+        let mut reads: Vec<Vec<usize>> = vec![];
+        let mut drives: Vec<Vec<usize>> = vec![];
+        for &(a, b, out) in &builder.nands {
+            reads.push(vec![nets[a], nets[b]]);
+            drives.push(vec![nets[out]]);
+        }
+        for &(builtin, ref inputs, ref outputs) in &builder.blocks {
+            reads.push(builtin.inputs.iter().zip(inputs)
+                                     .filter(|&(&(pin, _), _)| !builtin.clocked.contains(&pin))
+                                     .flat_map(|(_, wires)| wires.iter().map(|&wire| nets[wire]))
+                                     .collect());
+            drives.push(outputs.iter().flat_map(|wires| wires.iter().map(|&wire| nets[wire])).collect());
+        }
+
+        // Order the gates so each comes after those driving its inputs
+        let mut driver = vec![None; names.len()];
+        for (i, outputs) in drives.iter().enumerate() {
+            for &net in outputs {
+                driver[net] = Some(i);
+            }
+        }
+        let mut users = vec![vec![]; reads.len()];
+        let mut waiting = vec![0; reads.len()];
+        for (i, inputs) in reads.iter().enumerate() {
+            for &net in inputs {
+                if let Some(gate) = driver[net] {
+                    users[gate].push(i);
+                    waiting[i] += 1;
+                }
+            }
+        }
+        let mut ready: Vec<usize> = (0..waiting.len()).filter(|&i| waiting[i] == 0).collect();
+        let mut order = vec![];
+        while let Some(gate) = ready.pop() {
+            order.push(gate);
+            for &user in &users[gate] {
+                waiting[user] -= 1;
+                if waiting[user] == 0 {
+                    ready.push(user);
+                }
+            }
+        }
+        if order.len() < reads.len() {
+            let mut looped: Vec<&str> = (0..waiting.len()).filter(|&i| waiting[i] > 0)
+                                                          .flat_map(|i| drives[i].iter().map(|&net| &names[net][..]))
+                                                          .collect();
+            looped.sort_by_key(|name| (name.len(), *name));
+            return Err(format!("{}: Combinational loop through '{}'", name, looped[0]))
+        }
+
+        let mut values = vec![false; names.len()];
+        values[nets[TRUE]] = true;
+        Ok(Chip {
+            name: name.to_string(),
+            inputs: def.inputs.clone(),
+            outputs: def.outputs.clone(),
+            pins: pins.into_iter().map(|(name, (_, wires))| (name, wires.iter().map(|&w| nets[w]).collect())).collect(),
+            values: values,
+            gates: order.into_iter().map(|i| match builder.nands.get(i) {
+                Some(&(a, b, out)) => Gate::Nand(nets[a], nets[b], nets[out]),
+                None => Gate::Block(i - builder.nands.len()),
+            }).collect(),
+            blocks: builder.blocks.iter().map(|&(builtin, ref inputs, ref outputs)| {
+                let nets = |pins: &Vec<Vec<Wire>>| pins.iter().map(|wires| wires.iter().map(|&w| nets[w]).collect())
+                                                       .collect();
+                Block {
+                    name: builtin.name,
+                    chip: (builtin.new)(),
+                    clocked: !builtin.clocked.is_empty(),
+                    inputs: nets(inputs),
+                    outputs: nets(outputs),
+                }
+            }).collect(),
+            dffs: builder.dffs.iter().map(|&(input, out)| (nets[input], nets[out])).collect(),
+            latched: vec![false; builder.dffs.len()],
+        })
+    }
+
+    /// Does the chip have state, changing on the clock?
+    pub fn clocked(&self) -> bool {
+        !self.dffs.is_empty() || self.blocks.iter().any(|block| block.clocked)
+    }
+
+    /// The number of Nand gates and DFFs the chip is made of, leaving out
+    /// built-in chips
+    pub fn size(&self) -> (usize, usize) {
+        let nands = self.gates.iter().filter(|gate| match **gate { Gate::Nand(..) => true, _ => false }).count();
+        (nands, self.dffs.len())
+    }
+
+    /// The width of a pin of the chip, internal pins included
+    pub fn width(&self, pin: &str) -> Option<usize> {
+        self.pins.get(pin).map(|nets| nets.len())
+    }
+
+    /// The value of a pin, as of the last evaluation
+    pub fn get(&self, pin: &str) -> Result<Word, String> {
+        let nets = try!(self.pins.get(pin).ok_or(format!("Unknown pin: '{}'", pin)));
+        Ok(read(&self.values, nets))
+    }
+
+    /// Set an input pin, taking as many bits of `value` as it's wide
+    pub fn set(&mut self, pin: &str, value: Word) -> Result<(), String> {
+        if !self.inputs.iter().any(|input| input.name == pin) {
+            return Err(format!("'{}' isn't an input pin of {}", pin, self.name))
+        }
+        write(&mut self.values, &self.pins[pin], value);
+        Ok(())
+    }
+
+    /// The state of the first built-in part called `chip`, the value of a
+    /// register or the words of a memory
+    pub fn memory(&self, chip: &str) -> Option<&[Word]> {
+        self.blocks.iter().find(|block| block.name == chip).and_then(|block| block.chip.memory())
+    }
+
+    /// Change the state of a built-in part, taking effect on the next
+    /// evaluation
+    pub fn memory_mut(&mut self, chip: &str) -> Option<&mut [Word]> {
+        self.blocks.iter_mut().find(|block| block.name == chip).and_then(|block| block.chip.memory_mut())
+    }
+
+    /// The address the first built-in part called `chip` wrote to on the last
+    /// clock cycle, if it did
+    pub fn written(&self, chip: &str) -> Option<usize> {
+        self.blocks.iter().find(|block| block.name == chip).and_then(|block| block.chip.written())
+    }
+
+    /// Propagate the inputs and the state through the gates
+    pub fn eval(&mut self) {
+        let Chip { ref gates, ref mut blocks, ref mut values, .. } = *self;
+        for gate in gates {
+            match *gate {
+                Gate::Nand(a, b, out) => values[out] = !(values[a] && values[b]),
+                Gate::Block(i) => {
+                    let block = &mut blocks[i];
+                    let inputs: Vec<Word> = block.inputs.iter().map(|nets| read(values, nets)).collect();
+                    let mut outputs = vec![0; block.outputs.len()];
+                    block.chip.eval(&inputs, &mut outputs);
+                    for (nets, &value) in block.outputs.iter().zip(&outputs) {
+                        write(values, nets, value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The first half of a clock cycle: DFFs and built-in chips latch their
+    /// inputs
+    pub fn tick(&mut self) {
+        self.eval();
+        for (latched, &(input, _)) in self.latched.iter_mut().zip(&self.dffs) {
+            *latched = self.values[input];
+        }
+        let Chip { ref mut blocks, ref values, .. } = *self;
+        for block in blocks {
+            let inputs: Vec<Word> = block.inputs.iter().map(|nets| read(values, nets)).collect();
+            block.chip.tick(&inputs);
+        }
+    }
+
+    /// The second half of a clock cycle: DFFs output what they latched and
+    /// built-in chips change their state
+    pub fn tock(&mut self) {
+        for (&latched, &(_, out)) in self.latched.iter().zip(&self.dffs) {
+            self.values[out] = latched;
+        }
+        for block in &mut self.blocks {
+            block.chip.tock();
+        }
+        self.eval();
+    }
+}
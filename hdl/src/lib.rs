@@ -0,0 +1,28 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+pub use chip::{Chip, Word};
+pub use parser::{parse, ChipDef, Connection, Part, Pin, PinRef, Value};
+
+mod builtins;
+mod chip;
+mod parser;
+
+/// Build the chip in a .hdl file, finding the chips its parts use in .hdl
+/// files next to it, or built in
+pub fn load_path<P: AsRef<Path>>(path: P) -> Result<Chip, String> {
+    let path = path.as_ref();
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let name = try!(path.file_stem().ok_or(format!("Not a chip: '{}'", path.display()))).to_string_lossy();
+
+    Chip::new(&name, &mut |chip| {
+        let file = dir.join(format!("{}.hdl", chip));
+        let mut source = String::new();
+        match File::open(&file).and_then(|mut f| f.read_to_string(&mut source)) {
+            Ok(_) => Ok(Some(source)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read '{}': {}", file.display(), e)),
+        }
+    })
+}
@@ -0,0 +1,360 @@
+use std::fmt;
+
+/// A pin of a chip's interface, with its width in bits
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pin {
+    pub name: String,
+    pub width: usize,
+}
+
+/// A pin as written in a connection, `a`, `a[3]` or `a[0..7]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinRef {
+    pub name: String,
+    /// The first and last bits, both included
+    pub bits: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Pin(PinRef),
+    Const(bool),
+}
+
+/// `pin=value` in a part, the pin belonging to the part
+#[derive(Debug, Clone, PartialEq)]
+pub struct Connection {
+    pub pin: PinRef,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    pub chip: String,
+    pub line: usize,
+    pub connections: Vec<Connection>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChipDef {
+    pub name: String,
+    pub inputs: Vec<Pin>,
+    pub outputs: Vec<Pin>,
+    pub parts: Vec<Part>,
+    /// The chip implemented in Rust that `BUILTIN` names instead of parts
+    pub builtin: Option<String>,
+    /// The inputs `CLOCKED` says only change the outputs on the clock
+    pub clocked: Vec<String>,
+}
+
+impl PinRef {
+    /// The number of bits referred to, given the width of the whole pin
+    pub fn width(&self, pin_width: usize) -> usize {
+        self.bits.map_or(pin_width, |(first, last)| last - first + 1)
+    }
+}
+
+impl fmt::Display for PinRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bits {
+            None => write!(f, "{}", self.name),
+            Some((first, last)) if first == last => write!(f, "{}[{}]", self.name, first),
+            Some((first, last)) => write!(f, "{}[{}..{}]", self.name, first, last),
+        }
+    }
+}
+
+impl ChipDef {
+    pub fn input(&self, name: &str) -> Option<&Pin> {
+        self.inputs.iter().find(|pin| pin.name == name)
+    }
+
+    pub fn output(&self, name: &str) -> Option<&Pin> {
+        self.outputs.iter().find(|pin| pin.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token<'a> {
+    Word(&'a str),
+    Number(usize),
+    Symbol(&'static str),
+}
+
+impl<'a> fmt::Display for Token<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Number(n) => write!(f, "'{}'", n),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+        }
+    }
+}
+
+static SYMBOLS: &'static [&'static str] = &["..", "{", "}", "(", ")", "[", "]", ";", ",", "=", ":"];
+
+// Split HDL into tokens and the lines they're on, skipping comments
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut rest = source;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '\n' {
+            line += 1;
+            rest = &rest[1..];
+        } else if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if rest.starts_with("//") {
+            rest = &rest[rest.find('\n').unwrap_or(rest.len())..];
+        } else if rest.starts_with("/*") {
+            let end = try!(rest.find("*/").ok_or(format!("line {}: Unterminated comment", line)));
+            line += rest[..end].matches('\n').count();
+            rest = &rest[end + 2..];
+        } else if let Some(&symbol) = SYMBOLS.iter().find(|&&symbol| rest.starts_with(symbol)) {
+            tokens.push((Token::Symbol(symbol), line));
+            rest = &rest[symbol.len()..];
+        } else if c.is_alphanumeric() || c == '_' {
+            let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            let word = &rest[..end];
+            let token = if c.is_digit(10) {
+                Token::Number(try!(word.parse().map_err(|_| format!("line {}: Invalid number: '{}'", line, word))))
+            } else {
+                Token::Word(word)
+            };
+            tokens.push((token, line));
+            rest = &rest[end..];
+        } else {
+            return Err(format!("line {}: Unexpected character: '{}'", line, c))
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token<'a>, usize)>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.position).map(|&(ref token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.position).or(self.tokens.last()).map_or(1, |&(_, line)| line)
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, String> {
+        match self.peek() {
+            Some(token) => Err(format!("line {}: Expected {}, found {}", self.line(), expected, token)),
+            None => Err(format!("line {}: Expected {}, found the end of the file", self.line(), expected)),
+        }
+    }
+
+    fn accept(&mut self, symbol: &str) -> bool {
+        let found = match self.peek() {
+            Some(&Token::Symbol(found)) => found == symbol,
+            Some(&Token::Word(found)) => found == symbol,
+            _ => false,
+        };
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        if !self.accept(symbol) {
+            return self.error(&format!("'{}'", symbol))
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<&'a str, String> {
+        match self.peek() {
+            Some(&Token::Word(word)) => {
+                self.position += 1;
+                Ok(word)
+            }
+            _ => self.error("a name"),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, String> {
+        match self.peek() {
+            Some(&Token::Number(n)) => {
+                self.position += 1;
+                Ok(n)
+            }
+            _ => self.error("a number"),
+        }
+    }
+
+    // `IN a, b[16];`, the keyword already read
+    fn pins(&mut self) -> Result<Vec<Pin>, String> {
+        let mut pins = vec![];
+        if self.accept(";") {
+            return Ok(pins)
+        }
+        loop {
+            let name = try!(self.name());
+            let mut width = 1;
+            if self.accept("[") {
+                width = try!(self.number());
+                if width == 0 || width > 16 {
+                    return Err(format!("line {}: Pins are 1 to 16 bits wide, '{}' is {}", self.line(), name, width))
+                }
+                try!(self.expect("]"));
+            }
+            pins.push(Pin { name: name.to_string(), width: width });
+            if !self.accept(",") {
+                break
+            }
+        }
+        try!(self.expect(";"));
+        Ok(pins)
+    }
+
+    fn pin_ref(&mut self) -> Result<PinRef, String> {
+        let name = try!(self.name()).to_string();
+        let mut bits = None;
+        if self.accept("[") {
+            let first = try!(self.number());
+            let last = if self.accept("..") { try!(self.number()) } else { first };
+            if last < first || last >= 16 {
+                return Err(format!("line {}: Invalid bits: {}[{}..{}]", self.line(), name, first, last))
+            }
+            try!(self.expect("]"));
+            bits = Some((first, last));
+        }
+        Ok(PinRef { name: name, bits: bits })
+    }
+
+    fn part(&mut self) -> Result<Part, String> {
+        let line = self.line();
+        let chip = try!(self.name()).to_string();
+        try!(self.expect("("));
+        let mut connections = vec![];
+        loop {
+            let pin = try!(self.pin_ref());
+            try!(self.expect("="));
+            let value = match self.peek() {
+                Some(&Token::Word("true")) => Value::Const(true),
+                Some(&Token::Word("false")) => Value::Const(false),
+                _ => Value::Pin(try!(self.pin_ref())),
+            };
+            if let Value::Const(_) = value {
+                self.position += 1;
+            }
+            connections.push(Connection { pin: pin, value: value });
+            if !self.accept(",") {
+                break
+            }
+        }
+        try!(self.expect(")"));
+        try!(self.expect(";"));
+        Ok(Part { chip: chip, line: line, connections: connections })
+    }
+}
+
+/// Parse the HDL definition of a chip
+pub fn parse(source: &str) -> Result<ChipDef, String> {
+    let mut parser = Parser { tokens: try!(tokenize(source)), position: 0 };
+    try!(parser.expect("CHIP"));
+    let name = try!(parser.name()).to_string();
+    try!(parser.expect("{"));
+
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    if parser.accept("IN") {
+        inputs = try!(parser.pins());
+    }
+    if parser.accept("OUT") {
+        outputs = try!(parser.pins());
+    }
+    for pin in outputs.iter() {
+        if inputs.iter().any(|input| input.name == pin.name) {
+            return Err(format!("line {}: Pin declared twice: '{}'", parser.line(), pin.name))
+        }
+    }
+
+    let mut builtin = None;
+    let mut clocked = vec![];
+    let mut parts = vec![];
+    if parser.accept("BUILTIN") {
+        builtin = Some(try!(parser.name()).to_string());
+        try!(parser.expect(";"));
+        if parser.accept("CLOCKED") {
+            loop {
+                clocked.push(try!(parser.name()).to_string());
+                if !parser.accept(",") {
+                    break
+                }
+            }
+            try!(parser.expect(";"));
+        }
+        try!(parser.expect("}"));
+    } else {
+        try!(parser.expect("PARTS"));
+        try!(parser.expect(":"));
+        while !parser.accept("}") {
+            if parser.peek().is_none() {
+                return parser.error("'}'")
+            }
+            parts.push(try!(parser.part()));
+        }
+    }
+    if parser.peek().is_some() {
+        return parser.error("the end of the file")
+    }
+
+    Ok(ChipDef { name: name, inputs: inputs, outputs: outputs, parts: parts, builtin: builtin, clocked: clocked })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chip() {
+        let chip = parse("/** A multiplexor */\nCHIP Mux4 {\n    IN a[4], b[4], sel;\n    OUT out[4];\n\n\
+                          PARTS:\n    // Each half\n    Mux(a=a[0..1], b=b[0], sel=sel, out=out[3]);\n\
+                          Not(in=true, out=x);\n}\n").unwrap();
+        assert_eq!(chip.name, "Mux4");
+        assert_eq!(chip.inputs, vec![Pin { name: "a".to_string(), width: 4 }, Pin { name: "b".to_string(), width: 4 },
+                                     Pin { name: "sel".to_string(), width: 1 }]);
+        assert_eq!(chip.output("out").map(|pin| pin.width), Some(4));
+        assert_eq!(chip.parts.len(), 2);
+
+        let part = &chip.parts[0];
+        assert_eq!((&part.chip[..], part.line), ("Mux", 8));
+        let connections: Vec<String> = part.connections.iter().map(|c| match c.value {
+            Value::Pin(ref value) => format!("{}={}", c.pin, value),
+            Value::Const(value) => format!("{}={}", c.pin, value),
+        }).collect();
+        assert_eq!(connections, vec!["a=a[0..1]", "b=b[0]", "sel=sel", "out=out[3]"]);
+        assert_eq!(chip.parts[1].connections[0].value, Value::Const(true));
+        assert_eq!(chip.builtin, None);
+
+        let chip = parse("CHIP Register {\n    IN in[16], load;\n    OUT out[16];\n    BUILTIN Register;\n\
+                          CLOCKED in, load;\n}").unwrap();
+        assert_eq!((chip.builtin, chip.clocked, chip.parts),
+                   (Some("Register".to_string()), vec!["in".to_string(), "load".to_string()], vec![]));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("CHIP Foo {\n  IN a;\n  OUT out;\n  PARTS:\n  Not(in=a out=out);\n}").unwrap_err(),
+                   "line 5: Expected ')', found 'out'");
+        assert_eq!(parse("CHIP Foo {\n  IN a[17];").unwrap_err(), "line 2: Pins are 1 to 16 bits wide, 'a' is 17");
+        assert_eq!(parse("CHIP Foo { IN a; OUT a; PARTS: }").unwrap_err(), "line 1: Pin declared twice: 'a'");
+        assert_eq!(parse("CHIP Foo { IN a; OUT b; PARTS: Not(in=a[3..1], out=b);").unwrap_err(),
+                   "line 1: Invalid bits: a[3..1]");
+        assert_eq!(parse("CHIP Foo { IN a; BUILTIN Foo; PARTS: }").unwrap_err(), "line 1: Expected '}', found 'PARTS'");
+        assert_eq!(parse("CHIP Foo { IN a; PARTS:\n").unwrap_err(), "line 1: Expected '}', found the end of the file");
+        assert_eq!(parse("/* open").unwrap_err(), "line 1: Unterminated comment");
+        assert_eq!(parse("CHIP Foo { IN a$; }").unwrap_err(), "line 1: Unexpected character: '$'");
+    }
+}
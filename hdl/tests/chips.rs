@@ -0,0 +1,271 @@
+extern crate hack_hdl;
+
+use std::fs::File;
+use std::io::Read;
+
+use hack_hdl::*;
+
+#[macro_use] mod macros;
+
+fn load(name: &str) -> Chip {
+    load_path(format!("tests/data/{}.hdl", name)).unwrap()
+}
+
+// Build a chip from sources given here, with the chips in tests/data as parts
+fn build(name: &str, files: &[(&str, &str)]) -> Result<Chip, String> {
+    Chip::new(name, &mut |chip| {
+        if let Some(&(_, source)) = files.iter().find(|&&(file, _)| file == chip) {
+            return Ok(Some(source.to_string()))
+        }
+        let mut source = String::new();
+        Ok(File::open(format!("tests/data/{}.hdl", chip)).and_then(|mut f| f.read_to_string(&mut source))
+                                                          .ok().map(|_| source))
+    })
+}
+
+fn eval(chip: &mut Chip, inputs: &[(&str, u16)]) {
+    for &(pin, value) in inputs {
+        chip.set(pin, value).unwrap();
+    }
+    chip.eval();
+}
+
+check! {
+    gates for {
+        not     ("Not", &["in"]) => |i: &[u16]| !i[0] & 1,
+        and     ("And", &["a", "b"]) => |i: &[u16]| i[0] & i[1],
+        or      ("Or", &["a", "b"]) => |i: &[u16]| i[0] | i[1],
+        xor     ("Xor", &["a", "b"]) => |i: &[u16]| i[0] ^ i[1],
+        mux     ("Mux", &["a", "b", "sel"]) => |i: &[u16]| if i[2] == 0 { i[0] } else { i[1] },
+        or8way  ("Or8Way", &["in"]) => |i: &[u16]| (i[0] != 0) as u16
+    } do |chip, expected| {
+        let (name, pins): (&str, &[&str]) = chip;
+        let expected: fn(&[u16]) -> u16 = expected;
+        let mut chip = load(name);
+        assert!(!chip.clocked());
+        let widths: Vec<usize> = pins.iter().map(|pin| chip.width(pin).unwrap()).collect();
+        let bits: usize = widths.iter().sum();
+
+        for combination in 0..1u32 << bits {
+            let mut shift = 0;
+            let inputs: Vec<u16> = widths.iter().map(|&width| {
+                let value = (combination >> shift) as u16 & ((1 << width) - 1) as u16;
+                shift += width;
+                value
+            }).collect();
+            let named: Vec<(&str, u16)> = pins.iter().cloned().zip(inputs.iter().cloned()).collect();
+            eval(&mut chip, &named);
+            assert_eq!((&inputs, chip.get("out").unwrap()), (&inputs, expected(&inputs)));
+        }
+    }
+}
+
+static WORDS: &'static [u16] = &[0, 1, 2, 0x7FFF, 0x8000, 0xFFFF, 0x1234, 0xABCD, 17, 0xFF00];
+
+#[test]
+fn dmux() {
+    let mut chip = load("DMux8Way");
+    for sel in 0..8 {
+        eval(&mut chip, &[("in", 1), ("sel", sel)]);
+        let outputs: Vec<u16> = ["a", "b", "c", "d", "e", "f", "g", "h"].iter().map(|pin| chip.get(pin).unwrap()).collect();
+        let expected: Vec<u16> = (0..8).map(|i| (i == sel) as u16).collect();
+        assert_eq!(outputs, expected);
+    }
+}
+
+#[test]
+fn buses() {
+    let mut chip = load("Mux8Way16");
+    for sel in 0..8 {
+        let inputs = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        for (i, pin) in inputs.iter().enumerate() {
+            chip.set(pin, WORDS[i]).unwrap();
+        }
+        eval(&mut chip, &[("sel", sel)]);
+        assert_eq!(chip.get("out"), Ok(WORDS[sel as usize]));
+    }
+
+    let mut chip = load("Inc16");
+    for &x in WORDS {
+        eval(&mut chip, &[("in", x)]);
+        assert_eq!(chip.get("out"), Ok(x.wrapping_add(1)));
+    }
+}
+
+#[test]
+fn alu() {
+    // The functions the Hack CPU uses, as zx nx zy ny f no
+    let functions: &[(u16, fn(u16, u16) -> u16)] = &[
+        (0b101010, |_, _| 0), (0b111111, |_, _| 1), (0b111010, |_, _| 0xFFFF),
+        (0b001100, |x, _| x), (0b110000, |_, y| y), (0b001101, |x, _| !x), (0b001111, |x, _| x.wrapping_neg()),
+        (0b011111, |x, _| x.wrapping_add(1)), (0b110010, |_, y| y.wrapping_sub(1)),
+        (0b000010, |x, y| x.wrapping_add(y)), (0b010011, |x, y| x.wrapping_sub(y)),
+        (0b000111, |x, y| y.wrapping_sub(x)), (0b000000, |x, y| x & y), (0b010101, |x, y| x | y),
+    ];
+
+    let mut chip = load("ALU");
+    for &(bits, function) in functions {
+        for &x in WORDS {
+            for &y in WORDS {
+                let controls = ["no", "f", "ny", "zy", "nx", "zx"];
+                for (i, pin) in controls.iter().enumerate() {
+                    chip.set(pin, bits >> i & 1).unwrap();
+                }
+                eval(&mut chip, &[("x", x), ("y", y)]);
+                let out = function(x, y);
+                assert_eq!((bits, x, y, chip.get("out"), chip.get("zr"), chip.get("ng")),
+                           (bits, x, y, Ok(out), Ok((out == 0) as u16), Ok(out >> 15)));
+            }
+        }
+    }
+}
+
+#[test]
+fn registers() {
+    let mut chip = load("Bit");
+    assert!(chip.clocked());
+    eval(&mut chip, &[("in", 1), ("load", 1)]);
+    chip.tick();
+    assert_eq!(chip.get("out"), Ok(0));
+    chip.tock();
+    assert_eq!(chip.get("out"), Ok(1));
+    eval(&mut chip, &[("in", 0), ("load", 0)]);
+    chip.tick();
+    chip.tock();
+    assert_eq!(chip.get("out"), Ok(1));
+
+    let mut chip = load("RAM8");
+    for address in 0..8 {
+        eval(&mut chip, &[("in", WORDS[address as usize]), ("load", 1), ("address", address)]);
+        chip.tick();
+        chip.tock();
+    }
+    for address in 0..8 {
+        eval(&mut chip, &[("in", 0), ("load", 0), ("address", address)]);
+        assert_eq!(chip.get("out"), Ok(WORDS[address as usize]));
+    }
+    assert_eq!(chip.size().1, 8 * 16);
+}
+
+#[test]
+fn counter() {
+    let mut chip = load("PC");
+    let cycles: &[(u16, u16, u16, u16, u16)] = &[
+        // in, load, inc, reset, out after the cycle
+        (0, 0, 1, 0, 1),
+        (0, 0, 1, 0, 2),
+        (1000, 1, 1, 0, 1000),
+        (0, 0, 0, 0, 1000),
+        (0, 0, 1, 0, 1001),
+        (77, 1, 1, 1, 0),
+        (0xFFFF, 1, 0, 0, 0xFFFF),
+        (0, 0, 1, 0, 0),
+    ];
+    for &(input, load, inc, reset, out) in cycles {
+        eval(&mut chip, &[("in", input), ("load", load), ("inc", inc), ("reset", reset)]);
+        chip.tick();
+        chip.tock();
+        assert_eq!(chip.get("out"), Ok(out));
+    }
+}
+
+#[test]
+fn internal_pins() {
+    let mut chip = load("Xor");
+    eval(&mut chip, &[("a", 1), ("b", 0)]);
+    assert_eq!((chip.get("nand"), chip.get("or")), (Ok(1), Ok(1)));
+    assert_eq!(chip.set("out", 1), Err("'out' isn't an input pin of Xor".to_string()));
+    assert_eq!(chip.get("nope"), Err("Unknown pin: 'nope'".to_string()));
+}
+
+#[test]
+fn errors() {
+    let error = |source: &str| build("Foo", &[("Foo", source)]).err().unwrap();
+    assert_eq!(error("CHIP Foo {\n  IN a;\n  OUT out;\n  PARTS:\n  Not(in=a, out=x);\n  Not(in=x, out=a);\n}"),
+               "Foo.hdl: line 6: The input pin 'a' can't be driven by a part");
+    assert_eq!(error("CHIP Foo {\n  IN a;\n  OUT out;\n  PARTS:\n  Not(in=b, out=out);\n}"),
+               "Foo.hdl: line 5: Unknown pin: 'b'");
+    assert_eq!(error("CHIP Foo {\n  IN a;\n  OUT out;\n  PARTS:\n  Not(input=a, out=out);\n}"),
+               "Foo.hdl: line 5: 'Not' has no pin 'input'");
+    assert_eq!(error("CHIP Foo {\n  IN a[8];\n  OUT out;\n  PARTS:\n  Not16(in=a, out[0]=out);\n}"),
+               "Foo.hdl: line 5: 'in' of 'Not16' is 16 bits wide, but 'a' is 8");
+    assert_eq!(error("CHIP Foo {\n  IN a[8];\n  OUT out;\n  PARTS:\n  Or8Way(in=a[4..11], out=out);\n}"),
+               "Foo.hdl: line 5: 'a[4..11]' is out of range, 'a' is 8 bits wide");
+    assert_eq!(error("CHIP Foo {\n  IN a;\n  OUT out;\n  PARTS:\n  Not(in=a, out=out);\n  Not(in=a, out=out);\n}"),
+               "Foo.hdl: line 6: 'out' is driven by more than one part");
+    assert_eq!(error("CHIP Foo {\n  IN a;\n  OUT out;\n  PARTS:\n  Not(in=a, out=out);\n  Not(in=out, out=x);\n}"),
+               "Foo.hdl: line 6: The output pin 'out' can't be used as an input");
+    assert_eq!(error("CHIP Foo {\n  IN a;\n  OUT out;\n  PARTS:\n  Gate(in=a, out=out);\n}"),
+               "Foo.hdl: line 5: Unknown chip: 'Gate'");
+    assert_eq!(error("CHIP Foo {\n  IN a;\n  OUT out;\n  PARTS:\n  Foo(a=a, out=out);\n}"),
+               "Foo.hdl: The chip is built from itself");
+    assert_eq!(error("CHIP Bar { PARTS: }"), "Foo.hdl: The chip is called 'Bar'");
+    assert_eq!(build("Foo", &[("Foo", "CHIP Foo { PARTS: Broken(a=true); }"), ("Broken", "CHIP Broken {")]).err().unwrap(),
+               "Broken.hdl: line 1: Expected 'PARTS', found the end of the file");
+    assert_eq!(build("Nope", &[]).err().unwrap(), "Unknown chip: 'Nope'");
+}
+
+#[test]
+fn combinational_loops() {
+    let error = build("Loop", &[("Loop", "CHIP Loop {\n  IN a;\n  OUT out;\n  PARTS:\n\
+                                          And(a=a, b=x, out=y);\n  Not(in=y, out=x, out=out);\n}")]).err();
+    assert_eq!(error, Some("Loop: Combinational loop through 'Loop.x'".to_string()));
+
+    // Through a DFF is fine
+    let mut chip = build("Toggle", &[("Toggle", "CHIP Toggle {\n  OUT out;\n  PARTS:\n\
+                                                 Not(in=state, out=next);\n  DFF(in=next, out=state, out=out);\n}")])
+                       .unwrap();
+    let mut outputs = vec![];
+    for _ in 0..4 {
+        chip.tick();
+        chip.tock();
+        outputs.push(chip.get("out").unwrap());
+    }
+    assert_eq!(outputs, vec![1, 0, 1, 0]);
+}
+
+#[test]
+fn builtins() {
+    // A counter from the built-in Register instead of the one in tests/data,
+    // and RAM16K, which isn't there
+    let counter = "CHIP Counter {\n  OUT out[16];\n  PARTS:\n  Register(in=next, load=true, out=out, out=value);\n\
+                   Inc16(in=value, out=next);\n}";
+    let mut chip = build("Counter", &[("Counter", counter), ("Register", "CHIP Register {\n  IN in[16], load;\n\
+                                                                       OUT out[16];\n  BUILTIN Register;\n\
+                                                                       CLOCKED in, load;\n}")]).unwrap();
+    assert!(chip.clocked());
+    assert_eq!(chip.size().1, 0);
+    for _ in 0..3 {
+        chip.tick();
+        chip.tock();
+    }
+    assert_eq!((chip.get("out"), chip.memory("Register").map(|words| words[0])), (Ok(3), Some(3)));
+    chip.memory_mut("Register").unwrap()[0] = 0xFFFF;
+    chip.eval();
+    assert_eq!(chip.get("out"), Ok(0xFFFF));
+
+    let mut chip = build("Big", &[("Big", "CHIP Big {\n  IN in[16], load, address[14];\n  OUT out[16];\n  PARTS:\n\
+                                           RAM16K(in=in, load=load, address=address, out=out);\n}")]).unwrap();
+    eval(&mut chip, &[("in", 1234), ("load", 1), ("address", 9999)]);
+    chip.tick();
+    chip.tock();
+    assert_eq!((chip.get("out"), chip.written("RAM16K")), (Ok(1234), Some(9999)));
+    eval(&mut chip, &[("load", 0), ("address", 9998)]);
+    chip.tick();
+    chip.tock();
+    assert_eq!((chip.get("out"), chip.written("RAM16K")), (Ok(0), None));
+    assert_eq!(chip.memory("RAM16K").map(|words| words[9999]), Some(1234));
+
+    // A loop through a built-in chip's clocked inputs is fine, but not
+    // through the others
+    assert!(build("Loop", &[("Loop", "CHIP Loop {\n  OUT out[16];\n  PARTS:\n\
+                                      RAM64(in=x, load=true, out=x, out=out);\n}")]).is_ok());
+    let error = build("Loop", &[("Loop", "CHIP Loop {\n  OUT out[16];\n  PARTS:\n\
+                                          RAM64(in=x, load=true, address=x[0..5], out=x, out=out);\n}")]).err();
+    assert_eq!(error, Some("Loop: Combinational loop through 'Loop.x[0]'".to_string()));
+
+    let error = |source: &str| build("Foo", &[("Foo", source)]).err().unwrap();
+    assert_eq!(error("CHIP Foo {\n  IN in;\n  OUT out;\n  BUILTIN Gate;\n}"), "Foo.hdl: Unknown built-in chip: 'Gate'");
+    assert_eq!(error("CHIP Foo {\n  IN in;\n  OUT out;\n  BUILTIN Not16;\n}"),
+               "Foo.hdl: The pins aren't those of the built-in Not16");
+}
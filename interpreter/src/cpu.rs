@@ -7,10 +7,11 @@ pub type ProgramCounter = Word;
 pub struct Cpu {
     pub ram: Ram,
     pub pc: ProgramCounter,
+    pub ra: Register,
+    pub rd: Register,
 
     rom: Rom,
-    ra: Register,
-    rd: Register,
+    last_write: Option<Word>,
 }
 
 impl Cpu {
@@ -21,34 +22,62 @@ impl Cpu {
             pc: 0,
             ra: 0,
             rd: 0,
+            last_write: None,
         }
     }
 
-    pub fn step(&mut self) {
+    /// Restart execution from the first instruction with cleared memory
+    pub fn reset(&mut self) {
+        self.ram = Ram::new();
+        self.pc = 0;
+        self.ra = 0;
+        self.rd = 0;
+        self.last_write = None;
+    }
+
+    pub fn rom(&self) -> &Rom {
+        &self.rom
+    }
+
+    /// The RAM address written by the last executed instruction, if any
+    pub fn last_write(&self) -> Option<Word> {
+        self.last_write
+    }
+
+    /// Execute the next instruction. One that would read or write outside
+    /// of memory, or run outside of ROM, fails without changing anything.
+    pub fn step(&mut self) -> Result<(), String> {
         // Destructure for easier field access
-        let Cpu { ref mut ram, ref mut ra, ref mut rd, ref mut pc, .. } = *self;
+        let Cpu { ref mut ram, ref mut ra, ref mut rd, ref mut pc, ref mut last_write, .. } = *self;
 
         // Fetch
-        let raw_instruction = self.rom[*pc as usize];
+        let raw_instruction = match self.rom.get(*pc as usize) {
+            Some(&instruction) => instruction,
+            None => return Err(format!("PC {} is outside of ROM", pc)),
+        };
 
         // Decode
         let instruction = Instruction::new(raw_instruction);
         debug!("current instruction: {:?}", instruction);
 
         // Execute
+        *last_write = None;
         match instruction {
             Instruction::A(ins) => *ra = ins.address(),
             Instruction::C(ins) => {
-                let jump = ins.execute(ram, ra, rd);
+                let written = if ins.writes_memory() { Some(*ra) } else { None };
+                let jump = try!(ins.execute(ram, ra, rd).map_err(|e| format!("ROM[{}] {}", pc, e)));
+                *last_write = written;
                 if jump {
                     *pc = *ra;
-                    return
+                    return Ok(())
                 }
             }
         }
 
         // Set the program counter to the next instruction
-        *pc += 1;
+        *pc = pc.wrapping_add(1);
+        Ok(())
     }
 }
 
@@ -92,7 +121,7 @@ mod smoke {
                     }
                 )*
 
-                $cpu.step();
+                $cpu.step().unwrap();
             }
 
             $cpu
@@ -313,10 +342,10 @@ mod smoke {
                                           1001110001010000";);
             assert_eq!(cpu.ram[0], 0);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[0], 1);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[0], 1);
             assert_eq!(cpu.rd, 0xFFFE);
         }
@@ -350,10 +379,10 @@ mod smoke {
                                           1001110011010000";);
             assert_eq!(cpu.ram[0], 0);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[0], 1);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[0], 1);
             assert_eq!(cpu.rd as i16, -1);
         }
@@ -387,10 +416,10 @@ mod smoke {
                                           1001110111010000";);
             assert_eq!(cpu.ram[0], 0);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[0], 1);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[0], 1);
             assert_eq!(cpu.rd, 2);
         }
@@ -424,10 +453,10 @@ mod smoke {
                                           1001110010010000";);
             assert_eq!(cpu.ram[0], 0);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[0] as i16, -1);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[0] as i16, -1);
             assert_eq!(cpu.rd as i16, -2);
         }
@@ -452,15 +481,15 @@ mod smoke {
                                           1001000010010000";);
             assert_eq!(cpu.ram[0], 0);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 0);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 5);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 5);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 5);
@@ -487,19 +516,19 @@ mod smoke {
                                           1001010011010000";);
             assert_eq!(cpu.ram[0], 0);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 0);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5] as i16, -5);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5] as i16, -5);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 5);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5] as i16, -5);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 10);
@@ -526,19 +555,19 @@ mod smoke {
                                           1001000111010000";);
             assert_eq!(cpu.ram[0], 0);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 0);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 5);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 5);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 5);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 5);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 0);
@@ -565,19 +594,19 @@ mod smoke {
                                           1001000000100000";);
             assert_eq!(cpu.ram[0], 0);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 0);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 0);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 5);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 1);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 5);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[5], 1);
             assert_eq!(cpu.ra, 1);
             assert_eq!(cpu.rd, 5);
@@ -604,19 +633,19 @@ mod smoke {
                                           1001010101100000";);
             assert_eq!(cpu.ram[0], 0);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[4], 0);
             assert_eq!(cpu.ra, 4);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[4], 0);
             assert_eq!(cpu.ra, 4);
             assert_eq!(cpu.rd, 4);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[4], 1);
             assert_eq!(cpu.ra, 4);
             assert_eq!(cpu.rd, 4);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[4], 1);
             assert_eq!(cpu.ra, 5);
             assert_eq!(cpu.rd, 4);
@@ -658,11 +687,11 @@ mod smoke {
             assert_eq!(cpu.ram[32], 0);
             assert_eq!(cpu.rd, 0);
             assert_eq!(cpu.ra, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[32], 0);
             assert_eq!(cpu.ra, 32);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[32], 1);
             assert_eq!(cpu.ra, 32);
             assert_eq!(cpu.rd, 0);
@@ -689,11 +718,11 @@ mod smoke {
             assert_eq!(cpu.ram[32], 0);
             assert_eq!(cpu.ra, 0);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[32], 0);
             assert_eq!(cpu.ra, 32);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[32], 1);
             assert_eq!(cpu.ra, 1);
             assert_eq!(cpu.rd, 0);
@@ -708,11 +737,11 @@ mod smoke {
             assert_eq!(cpu.ram[32], 0);
             assert_eq!(cpu.ra, 0);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[32], 0);
             assert_eq!(cpu.ra, 32);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[32], 1);
             assert_eq!(cpu.rd, 1);
             // Ensure ra is not affected
@@ -728,11 +757,11 @@ mod smoke {
             assert_eq!(cpu.ram[32], 0);
             assert_eq!(cpu.ra, 0);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[32], 0);
             assert_eq!(cpu.ra, 32);
             assert_eq!(cpu.rd, 0);
-            cpu.step();
+            cpu.step().unwrap();
             assert_eq!(cpu.ram[32], 1);
             assert_eq!(cpu.rd, 1);
             assert_eq!(cpu.ra, 1);
@@ -0,0 +1,223 @@
+//! Text panels describing the state of a `Machine`, shared by the simulator
+//! frontends. Each panel is a list of lines made of styled spans, so a
+//! frontend only has to decide how a style looks.
+
+use hack_assembler::disassemble;
+use machine::Machine;
+use screen::SCREEN_ADDR;
+
+// Pointers of the VM standard mapping
+const SP: usize = 0;
+const LCL: usize = 1;
+const ARG: usize = 2;
+pub const STACK_ADDR: usize = 256;
+
+// Writes stay flagged as recent for this many updates
+const FLASH_UPDATES: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Normal,
+    Heading,
+    /// The instruction about to execute
+    Current,
+    /// A RAM cell that was written recently
+    Recent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+pub type Line = Vec<Span>;
+
+fn span<S: Into<String>>(text: S, style: Style) -> Span {
+    Span { text: text.into(), style: style }
+}
+
+fn line<S: Into<String>>(text: S, style: Style) -> Line {
+    vec![span(text, style)]
+}
+
+/// A panel title
+pub fn heading(text: &str) -> Line {
+    line(text, Style::Heading)
+}
+
+/// The text of a line without its styles
+pub fn text(line: &Line) -> String {
+    line.iter().map(|s| &s.text[..]).collect()
+}
+
+/// PC, A and D registers and the cycle count
+pub fn registers(machine: &Machine) -> Vec<Line> {
+    let cpu = &machine.cpu;
+    vec![
+        line(format!("PC {:>6}", cpu.pc), Style::Normal),
+        line(format!("A  {:>6}  {:04X}", cpu.ra as i16, cpu.ra), Style::Normal),
+        line(format!("D  {:>6}  {:04X}", cpu.rd as i16, cpu.rd), Style::Normal),
+        line(format!("Cycles {}", machine.cycles()), Style::Normal),
+    ]
+}
+
+/// `rows` lines of disassembled ROM, scrolled to keep the PC centered
+pub fn disassembly(machine: &Machine, rows: usize) -> Vec<Line> {
+    let cpu = &machine.cpu;
+    let rom = cpu.rom();
+    let pc = cpu.pc as usize;
+    let start = pc.saturating_sub(rows / 2).min(rom.len().saturating_sub(rows));
+    let end = (start + rows).min(rom.len());
+
+    (start..end).map(|address| {
+        if address == pc {
+            line(format!("> {:5}  {}", address, disassemble(rom[address])), Style::Current)
+        } else {
+            line(format!("  {:5}  {}", address, disassemble(rom[address])), Style::Normal)
+        }
+    }).collect()
+}
+
+/// Up to `rows` words from the top of the stack down, labelled with the
+/// segments and saved call frame that LCL and ARG point into
+pub fn stack(machine: &Machine, rows: usize) -> Vec<Line> {
+    let ram = &machine.cpu.ram;
+    let (sp, lcl, arg) = (ram[SP] as usize, ram[LCL] as usize, ram[ARG] as usize);
+    if sp <= STACK_ADDR || sp >= SCREEN_ADDR {
+        return vec![line(format!("SP {} (empty)", sp), Style::Normal)]
+    }
+
+    let mut lines = vec![line(format!("SP {}  LCL {}  ARG {}", sp, lcl, arg), Style::Normal)];
+    let bottom = sp.saturating_sub(rows.saturating_sub(1)).max(STACK_ADDR);
+    for address in (bottom..sp).rev() {
+        let label = stack_label(address, lcl, arg);
+        lines.push(vec![
+            span(format!("{:5} {:>6}", address, ram[address] as i16), recent_style(machine, address)),
+            span(label, Style::Normal),
+        ]);
+    }
+
+    lines
+}
+
+fn stack_label(address: usize, lcl: usize, arg: usize) -> String {
+    const FRAME: [&'static str; 5] = ["return", "saved LCL", "saved ARG", "saved THIS", "saved THAT"];
+
+    if address == lcl {
+        "  <- LCL".to_string()
+    } else if address == arg {
+        "  <- ARG".to_string()
+    } else if address < lcl && address + FRAME.len() >= lcl && lcl >= STACK_ADDR + FRAME.len() {
+        format!("  {}", FRAME[address + FRAME.len() - lcl])
+    } else {
+        String::new()
+    }
+}
+
+/// `rows` rows of `per_row` RAM words in hex, starting from the row holding `start`
+pub fn ram(machine: &Machine, start: usize, rows: usize, per_row: usize) -> Vec<Line> {
+    let ram = &machine.cpu.ram;
+    let start = start - start % per_row;
+
+    (0..rows).map(|row| start + row * per_row)
+             .take_while(|&address| address < ram.len())
+             .map(|address| {
+        let mut line = line(format!("{:5}:", address), Style::Heading);
+        for address in address..(address + per_row).min(ram.len()) {
+            line.push(span(format!(" {:04X}", ram[address]), recent_style(machine, address)));
+        }
+        line
+    }).collect()
+}
+
+/// The RAM address below the screen that was written last, for a RAM view
+/// to follow
+pub fn last_written(machine: &Machine) -> Option<usize> {
+    (0..SCREEN_ADDR).filter_map(|address| machine.written_at(address).map(|c| (c, address)))
+                    .max()
+                    .map(|(_, address)| address)
+}
+
+fn recent_style(machine: &Machine, address: usize) -> Style {
+    let window = machine.cycles_per_update() * FLASH_UPDATES;
+    match machine.written_at(address) {
+        Some(cycle) if machine.cycles() - cycle <= window => Style::Recent,
+        _ => Style::Normal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use machine::Machine;
+    use memory::Rom;
+
+    // @5, D=A, @300, M=D, @7, 0;JMP, then padding
+    fn machine() -> Machine {
+        let program = "0000000000000101\n\
+                       1110110000010000\n\
+                       0000000100101100\n\
+                       1110001100001000\n\
+                       0000000000000111\n\
+                       1110101010000111\n\
+                       0000000000000000\n\
+                       1110101010000111\n";
+        Machine::new(Rom::from_str(program).unwrap())
+    }
+
+    #[test]
+    fn disassembly_follows_pc() {
+        let mut machine = machine();
+        machine.run(2);
+
+        let lines: Vec<_> = disassembly(&machine, 4).iter().map(text).collect();
+        assert_eq!(lines, ["      0  @5", "      1  D=A", ">     2  @300", "      3  M=D"]);
+        assert_eq!(disassembly(&machine, 4)[2][0].style, Style::Current);
+
+        machine.run(4);
+        let lines: Vec<_> = disassembly(&machine, 4).iter().map(text).collect();
+        assert_eq!(lines, ["      5  0;JMP", "      6  @0", ">     7  0;JMP", "      8  @0"]);
+    }
+
+    #[test]
+    fn ram_flags_recent_writes() {
+        let mut machine = machine();
+        machine.run(4);
+
+        let lines = ram(&machine, 300, 1, 4);
+        assert_eq!(text(&lines[0]), "  300: 0005 0000 0000 0000");
+
+        let lines = ram(&machine, 299, 2, 8);
+        assert_eq!(text(&lines[0]), "  296: 0000 0000 0000 0000 0005 0000 0000 0000");
+        assert_eq!(lines[0][5].style, Style::Recent);
+        assert_eq!(lines[0][4].style, Style::Normal);
+        assert_eq!(last_written(&machine), Some(300));
+    }
+
+    #[test]
+    fn stack_frames() {
+        let mut machine = machine();
+        {
+            let ram = &mut machine.cpu.ram;
+            ram[SP] = 264;
+            ram[LCL] = 263;
+            ram[ARG] = 256;
+        }
+
+        let lines: Vec<_> = stack(&machine, 10).iter().map(text).collect();
+        assert_eq!(lines, ["SP 264  LCL 263  ARG 256",
+                           "  263      0  <- LCL",
+                           "  262      0  saved THAT",
+                           "  261      0  saved THIS",
+                           "  260      0  saved ARG",
+                           "  259      0  saved LCL",
+                           "  258      0  return",
+                           "  257      0",
+                           "  256      0  <- ARG"]);
+
+        assert_eq!(stack(&machine, 3).len(), 3);
+        machine.cpu.ram[SP] = 256;
+        assert_eq!(text(&stack(&machine, 3)[0]), "SP 256 (empty)");
+    }
+}
@@ -0,0 +1,145 @@
+use image::{Rgba, RgbaImage};
+use screen::{Screen, WIDTH, HEIGHT};
+
+pub type Color = [u8; 3];
+
+static COLOR_NAMES: &'static [(&'static str, Color)] = &[
+    ("black", [0, 0, 0]),
+    ("white", [255, 255, 255]),
+    ("green", [51, 255, 102]),
+    ("amber", [255, 176, 0]),
+    ("blue", [64, 128, 255]),
+    ("gray", [128, 128, 128]),
+];
+
+/// How the screen is drawn into an image: its colors, scale and effects
+#[derive(Debug, Clone, PartialEq)]
+pub struct Display {
+    /// Size in image pixels of a screen pixel
+    pub scale: usize,
+    pub foreground: Color,
+    pub background: Color,
+    /// Darken the bottom row of every screen pixel, like the gaps between
+    /// the lines of a CRT
+    pub scanlines: bool,
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display {
+            scale: 2,
+            foreground: [0, 0, 0],
+            background: [255, 255, 255],
+            scanlines: false,
+        }
+    }
+}
+
+impl Display {
+    /// Swap the foreground and background colors
+    pub fn invert(&mut self) {
+        ::std::mem::swap(&mut self.foreground, &mut self.background)
+    }
+
+    /// Width and height of the drawn screen
+    pub fn size(&self) -> (u32, u32) {
+        ((WIDTH * self.scale) as u32, (HEIGHT * self.scale) as u32)
+    }
+
+    /// Draw `screen` into the top left corner of `image`
+    pub fn render(&self, screen: &Screen, image: &mut RgbaImage) {
+        let (width, height) = self.size();
+        assert!(image.width() >= width && image.height() >= height, "Image too small for the screen");
+
+        // A single row per pixel can't have a gap, so alternate rows instead
+        let line_height = self.scale.max(2) as u32;
+        for y in 0..height {
+            let scanline = self.scanlines && y % line_height == line_height - 1;
+            for x in 0..width {
+                let set = screen.pixel(x as usize / self.scale, y as usize / self.scale);
+                let color = if set { self.foreground } else { self.background };
+                let color = if scanline { darken(color) } else { color };
+                image.put_pixel(x, y, Rgba([color[0], color[1], color[2], 255]));
+            }
+        }
+    }
+
+    pub fn to_image(&self, screen: &Screen) -> RgbaImage {
+        let (width, height) = self.size();
+        let mut image = RgbaImage::new(width, height);
+        self.render(screen, &mut image);
+        image
+    }
+}
+
+fn darken(color: Color) -> Color {
+    [color[0] / 2, color[1] / 2, color[2] / 2]
+}
+
+/// Parse a color given as `#rrggbb`, `rrggbb` or one of a few names like `amber`
+pub fn parse_color(s: &str) -> Result<Color, String> {
+    if let Some(&(_, color)) = COLOR_NAMES.iter().find(|&&(name, _)| name == s.to_lowercase()) {
+        return Ok(color)
+    }
+
+    let hex = s.trim_left_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_digit(16)) {
+        let names: Vec<_> = COLOR_NAMES.iter().map(|&(name, _)| name).collect();
+        return Err(format!("Invalid color: '{}', expected #rrggbb or one of: {}", s, names.join(", ")))
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    Ok([channel(0), channel(2), channel(4)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::Ram;
+    use screen::{Screen, SCREEN_ADDR};
+
+    fn screen_with_first_pixel() -> Screen {
+        let mut ram = Ram::new();
+        ram[SCREEN_ADDR] = 1;
+        Screen::from_ram(&ram)
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("#FF8000"), Ok([255, 128, 0]));
+        assert_eq!(parse_color("0a0b0c"), Ok([10, 11, 12]));
+        assert_eq!(parse_color("Amber"), Ok([255, 176, 0]));
+        assert!(parse_color("#12345").is_err());
+        assert!(parse_color("purple").is_err());
+    }
+
+    #[test]
+    fn scales_and_colors() {
+        let mut display = Display { scale: 3, ..Display::default() };
+        display.foreground = [0, 255, 0];
+        let image = display.to_image(&screen_with_first_pixel());
+
+        assert_eq!(image.dimensions(), (1536, 768));
+        assert_eq!(image.get_pixel(2, 2), &Rgba([0, 255, 0, 255]));
+        assert_eq!(image.get_pixel(3, 0), &Rgba([255, 255, 255, 255]));
+
+        display.invert();
+        let image = display.to_image(&screen_with_first_pixel());
+        assert_eq!(image.get_pixel(0, 0), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(0, 3), &Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn scanlines() {
+        let display = Display { scanlines: true, ..Display::default() };
+        let image = display.to_image(&screen_with_first_pixel());
+
+        assert_eq!(image.get_pixel(5, 0), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(5, 1), &Rgba([127, 127, 127, 255]));
+
+        let display = Display { scale: 1, scanlines: true, ..Display::default() };
+        let image = display.to_image(&screen_with_first_pixel());
+        assert_eq!(image.get_pixel(5, 2), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(5, 3), &Rgba([127, 127, 127, 255]));
+    }
+}
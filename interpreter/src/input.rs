@@ -0,0 +1,164 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use keyboard::SpecialKey;
+use memory::Word;
+
+/// A change of the keyboard register at a given cycle.
+/// A `key` of 0 means all keys were released.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEvent {
+    pub cycle: u64,
+    pub key: Word,
+}
+
+/// A timeline of keyboard events, as read from or written to an input script.
+///
+/// The format is one event per line, `<cycle> press <key>` or `<cycle> release`,
+/// where `<key>` is a Hack key code, a single printable character or the name
+/// of a special key such as `LEFT`, `NEWLINE` or `F1`.
+/// Blank lines and `//` comments are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputScript {
+    events: Vec<KeyEvent>,
+}
+
+impl InputScript {
+    pub fn new() -> InputScript {
+        InputScript { events: vec![] }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<InputScript, String> {
+        let path = path.as_ref();
+        let s = file_to_string!(path);
+        InputScript::parse(&s)
+    }
+
+    pub fn parse(s: &str) -> Result<InputScript, String> {
+        let mut script = InputScript::new();
+
+        for (lineno, line) in s.lines().enumerate() {
+            let line = line.splitn(2, "//").next().unwrap().trim();
+            if line.is_empty() { continue }
+
+            let event = parse_event(line).map_err(|e| {
+                format!("Invalid input script line {}: {}", lineno + 1, e)
+            });
+            let event = try!(event);
+
+            if script.events.last().map_or(false, |last| last.cycle > event.cycle) {
+                return Err(format!("Invalid input script line {}: cycle {} is before \
+                                    the previous event", lineno + 1, event.cycle))
+            }
+            script.events.push(event);
+        }
+
+        Ok(script)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let mut f = try_s!(File::create(path));
+        try_s!(f.write_all(self.to_string().as_bytes()));
+        Ok(())
+    }
+
+    pub fn push(&mut self, event: KeyEvent) {
+        self.events.push(event)
+    }
+
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.events
+    }
+}
+
+impl fmt::Display for InputScript {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            match event.key {
+                0 => try!(writeln!(f, "{} release", event.cycle)),
+                key => try!(writeln!(f, "{} press {}", event.cycle, key)),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_event(line: &str) -> Result<KeyEvent, String> {
+    let mut parts = line.split_whitespace();
+    let cycle = try!(parts.next().ok_or("Missing cycle".to_string()));
+    let cycle = try_s!(cycle.parse());
+
+    let key = match parts.next() {
+        Some("press") => {
+            let key = try!(parts.next().ok_or("Missing key for press".to_string()));
+            try!(parse_key(key))
+        }
+        Some("release") => 0,
+        Some(action) => return Err(format!("Unknown action: '{}'", action)),
+        None => return Err("Missing action".to_string()),
+    };
+
+    if let Some(extra) = parts.next() {
+        return Err(format!("Unexpected trailing input: '{}'", extra))
+    }
+
+    Ok(KeyEvent { cycle: cycle, key: key })
+}
+
+fn parse_key(key: &str) -> Result<Word, String> {
+    if let Some(special) = SpecialKey::from_name(key) {
+        return Ok(special.code())
+    }
+
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if !c.is_digit(10) && (c as u32) < 128 => Ok(c as Word),
+        _ => match key.parse() {
+            Ok(0) | Err(..) => Err(format!("Invalid key: '{}'", key)),
+            Ok(code) => Ok(code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_events() {
+        let script = InputScript::parse("// Move the paddle left\n\
+                                         100 press 130\n\
+                                         \n\
+                                         250 release // let go\n\
+                                         300 press a\n\
+                                         400 press 7\n\
+                                         500 press LEFT\n").unwrap();
+
+        assert_eq!(script.events(), &[KeyEvent { cycle: 100, key: 130 },
+                                      KeyEvent { cycle: 250, key: 0 },
+                                      KeyEvent { cycle: 300, key: 97 },
+                                      KeyEvent { cycle: 400, key: 7 },
+                                      KeyEvent { cycle: 500, key: 130 }]);
+    }
+
+    #[test]
+    fn roundtrips() {
+        let script = InputScript::parse("5 press 32\n9 release\n").unwrap();
+        assert_eq!(script.to_string(), "5 press 32\n9 release\n");
+        assert_eq!(InputScript::parse(&script.to_string()).unwrap(), script);
+    }
+
+    #[test]
+    #[should_panic(expected="line 2: cycle 5 is before the previous event")]
+    fn rejects_unordered() {
+        InputScript::parse("10 press 32\n5 release\n").unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected="line 1: Unknown action: 'hold'")]
+    fn rejects_unknown_action() {
+        InputScript::parse("10 hold 32\n").unwrap();
+    }
+}
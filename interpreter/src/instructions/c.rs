@@ -4,31 +4,44 @@ use cpu::Register;
 pub struct CInstruction(pub Word);
 
 impl CInstruction {
-    /// Returns true if a jump should occur
+    /// Returns true if a jump should occur, or what's wrong with the
+    /// instruction without changing anything
     pub fn execute(self, ram: &mut Ram,
                            a: &mut Register,
-                           d: &mut Register) -> bool {
+                           d: &mut Register) -> Result<bool, String> {
+        let address = *a as usize;
+        if (self.a_is_address() || self.writes_memory()) && address >= ram.len() {
+            let access = if self.a_is_address() { "reads" } else { "writes to" };
+            return Err(format!("{} RAM[{}], which is outside of memory", access, address))
+        }
+
         // determine inputs
         let x = *d;
-        let y = if self.a_is_address() { ram[*a as usize] } else { *a };
+        let y = if self.a_is_address() { ram[address] } else { *a };
 
         // do computation
-        let (result, zero, negative) = self.computation(x, y);
+        let (result, zero, negative) = try!(self.computation(x, y));
 
         // save to destinations
         self.store(result, ram, a, d);
 
         // return jump intention
-        self.jump(zero, negative)
+        Ok(self.jump(zero, negative))
+    }
+
+    /// Returns true if the result is stored to RAM[A]
+    pub fn writes_memory(&self) -> bool {
+        self.0 & (1 << 3) != 0
     }
 
     fn a_is_address(&self) -> bool {
         self.0 & (1 << 12) != 0
     }
 
-    fn computation(&self, x: Word, y: Word) -> (Word, bool, bool) {
+    fn computation(&self, x: Word, y: Word) -> Result<(Word, bool, bool), String> {
         let bit = |i: usize| ((self.0 & (1<<i) != 0) as u8);
 
+        // The ALU wraps around like the 16-bit hardware
         let x = x as i16;
         let y = y as i16;
         let result: i16 = match (bit(11), bit(10), bit(9), bit(8), bit(7), bit(6)) {
@@ -39,22 +52,22 @@ impl CInstruction {
             (1, 1, 0, 0, 0, 0) => y,
             (0, 0, 1, 1, 0, 1) => !x,
             (1, 1, 0, 0, 0, 1) => !y,
-            (0, 0, 1, 1, 1, 1) => -x,
-            (1, 1, 0, 0, 1, 1) => -y,
-            (0, 1, 1, 1, 1, 1) => x + 1,
-            (1, 1, 0, 1, 1, 1) => y + 1,
-            (0, 0, 1, 1, 1, 0) => x - 1,
-            (1, 1, 0, 0, 1, 0) => y - 1,
-            (0, 0, 0, 0, 1, 0) => x + y,
-            (0, 1, 0, 0, 1, 1) => x - y,
-            (0, 0, 0, 1, 1, 1) => y - x,
+            (0, 0, 1, 1, 1, 1) => x.wrapping_neg(),
+            (1, 1, 0, 0, 1, 1) => y.wrapping_neg(),
+            (0, 1, 1, 1, 1, 1) => x.wrapping_add(1),
+            (1, 1, 0, 1, 1, 1) => y.wrapping_add(1),
+            (0, 0, 1, 1, 1, 0) => x.wrapping_sub(1),
+            (1, 1, 0, 0, 1, 0) => y.wrapping_sub(1),
+            (0, 0, 0, 0, 1, 0) => x.wrapping_add(y),
+            (0, 1, 0, 0, 1, 1) => x.wrapping_sub(y),
+            (0, 0, 0, 1, 1, 1) => y.wrapping_sub(x),
             (0, 0, 0, 0, 0, 0) => x & y,
             (0, 1, 0, 1, 0, 1) => x | y,
-            (c1, c2, c3, c4, c5, c6) => panic!("Unhandled computation: {}{}{}{}{}{}",
-                                                c1, c2, c3, c4, c5, c6)
+            (c1, c2, c3, c4, c5, c6) => return Err(format!("has an invalid computation: {}{}{}{}{}{}",
+                                                           c1, c2, c3, c4, c5, c6))
         };
 
-        (result as u16, result == 0, result < 0)
+        Ok((result as u16, result == 0, result < 0))
     }
 
     fn store(&self, result: Word,
@@ -63,7 +76,7 @@ impl CInstruction {
                     d: &mut Register) {
         let bit = |i: usize| ((self.0 & (1<<i) != 0));
 
-        if bit(3) { ram[*a as usize] = result }
+        if self.writes_memory() { ram[*a as usize] = result }
         if bit(4) { *d = result }
         if bit(5) { *a = result }
     }
@@ -87,3 +100,19 @@ impl CInstruction {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_wraps() {
+        // D+A, D-A, -D, D+1
+        let compute = |bits: Word, x: Word, y: Word| CInstruction(bits).computation(x, y);
+        assert_eq!(compute(0b1110000010010000, 0x7FFF, 1), Ok((0x8000, false, true)));
+        assert_eq!(compute(0b1110010011010000, 0x8000, 1), Ok((0x7FFF, false, false)));
+        assert_eq!(compute(0b1110001111010000, 0x8000, 0), Ok((0x8000, false, true)));
+        assert_eq!(compute(0b1110011111010000, 0xFFFF, 0), Ok((0, true, false)));
+        assert_eq!(compute(0b1110101101010000, 0, 0), Err("has an invalid computation: 101101".to_string()));
+    }
+}
@@ -0,0 +1,238 @@
+use memory::Word;
+
+// Key codes of the Hack character set that are not plain ASCII
+pub const NEWLINE: Word = 128;
+pub const BACKSPACE: Word = 129;
+pub const LEFT: Word = 130;
+pub const UP: Word = 131;
+pub const RIGHT: Word = 132;
+pub const DOWN: Word = 133;
+pub const HOME: Word = 134;
+pub const END: Word = 135;
+pub const PAGE_UP: Word = 136;
+pub const PAGE_DOWN: Word = 137;
+pub const INSERT: Word = 138;
+pub const DELETE: Word = 139;
+pub const ESC: Word = 140;
+pub const F1: Word = 141;
+
+/// Keys that have a Hack key code but don't produce printable text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpecialKey {
+    Newline,
+    Backspace,
+    Left,
+    Up,
+    Right,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    Esc,
+    /// Function keys F1 to F12
+    F(u8),
+}
+
+static KEY_NAMES: &'static [(&'static str, SpecialKey)] = &[
+    ("NEWLINE", SpecialKey::Newline),
+    ("BACKSPACE", SpecialKey::Backspace),
+    ("LEFT", SpecialKey::Left),
+    ("UP", SpecialKey::Up),
+    ("RIGHT", SpecialKey::Right),
+    ("DOWN", SpecialKey::Down),
+    ("HOME", SpecialKey::Home),
+    ("END", SpecialKey::End),
+    ("PAGEUP", SpecialKey::PageUp),
+    ("PAGEDOWN", SpecialKey::PageDown),
+    ("INSERT", SpecialKey::Insert),
+    ("DELETE", SpecialKey::Delete),
+    ("ESC", SpecialKey::Esc),
+];
+
+impl SpecialKey {
+    pub fn code(&self) -> Word {
+        match *self {
+            SpecialKey::Newline => NEWLINE,
+            SpecialKey::Backspace => BACKSPACE,
+            SpecialKey::Left => LEFT,
+            SpecialKey::Up => UP,
+            SpecialKey::Right => RIGHT,
+            SpecialKey::Down => DOWN,
+            SpecialKey::Home => HOME,
+            SpecialKey::End => END,
+            SpecialKey::PageUp => PAGE_UP,
+            SpecialKey::PageDown => PAGE_DOWN,
+            SpecialKey::Insert => INSERT,
+            SpecialKey::Delete => DELETE,
+            SpecialKey::Esc => ESC,
+            SpecialKey::F(n) => {
+                assert!(n >= 1 && n <= 12, "No such function key: F{}", n);
+                F1 + n as Word - 1
+            }
+        }
+    }
+
+    /// Look up a key by the name used in input scripts, e.g. `LEFT` or `F3`
+    pub fn from_name(name: &str) -> Option<SpecialKey> {
+        let name = name.to_uppercase();
+        if let Some(&(_, key)) = KEY_NAMES.iter().find(|&&(n, _)| n == name) {
+            return Some(key)
+        }
+
+        if !name.starts_with('F') { return None }
+        match name[1..].parse() {
+            Ok(n) if n >= 1 && n <= 12 => Some(SpecialKey::F(n)),
+            _ => None
+        }
+    }
+}
+
+/// The Hack key code of a character from a text input event.
+/// Only printable ASCII has a code; other characters are ignored.
+pub fn char_code(c: char) -> Option<Word> {
+    match c {
+        ' '...'~' => Some(c as Word),
+        _ => None
+    }
+}
+
+/// Combines raw key presses with text input events into Hack key codes.
+///
+/// Special keys map directly to their code. Any other key is held back until
+/// its text event arrives, so the code reflects Shift and Caps Lock, and
+/// modifiers on their own never register. Keys are identified by the
+/// frontend's own scancode or enum value.
+pub struct KeyTracker {
+    // The key whose text event is expected next
+    pending: Option<u32>,
+    // The key that produced the current code
+    held: Option<u32>,
+}
+
+impl KeyTracker {
+    pub fn new() -> KeyTracker {
+        KeyTracker { pending: None, held: None }
+    }
+
+    /// A key without printable text was pressed, returns the new key code
+    pub fn press_special(&mut self, id: u32, key: SpecialKey) -> Word {
+        self.pending = None;
+        self.held = Some(id);
+        key.code()
+    }
+
+    /// A key that may produce text was pressed
+    pub fn press(&mut self, id: u32) {
+        self.pending = Some(id);
+    }
+
+    /// Text was entered, returns the new key code if it has one
+    pub fn text(&mut self, text: &str) -> Option<Word> {
+        let code = match text.chars().last().and_then(char_code) {
+            Some(code) => code,
+            None => return None
+        };
+
+        // Text without a known key (e.g. from an input method) is held until
+        // the next release
+        self.held = Some(self.pending.take().unwrap_or(!0));
+        Some(code)
+    }
+
+    /// A key was released, returns true if the key code should be cleared
+    pub fn release(&mut self, id: u32) -> bool {
+        if self.pending == Some(id) {
+            self.pending = None;
+        }
+
+        match self.held {
+            Some(held) if held == id || held == !0 => {
+                self.held = None;
+                true
+            }
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn special_codes() {
+        assert_eq!(SpecialKey::Newline.code(), 128);
+        assert_eq!(SpecialKey::Backspace.code(), 129);
+        assert_eq!(SpecialKey::Left.code(), 130);
+        assert_eq!(SpecialKey::Up.code(), 131);
+        assert_eq!(SpecialKey::Right.code(), 132);
+        assert_eq!(SpecialKey::Down.code(), 133);
+        assert_eq!(SpecialKey::Home.code(), 134);
+        assert_eq!(SpecialKey::End.code(), 135);
+        assert_eq!(SpecialKey::PageUp.code(), 136);
+        assert_eq!(SpecialKey::PageDown.code(), 137);
+        assert_eq!(SpecialKey::Insert.code(), 138);
+        assert_eq!(SpecialKey::Delete.code(), 139);
+        assert_eq!(SpecialKey::Esc.code(), 140);
+        assert_eq!(SpecialKey::F(1).code(), 141);
+        assert_eq!(SpecialKey::F(12).code(), 152);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(SpecialKey::from_name("left"), Some(SpecialKey::Left));
+        assert_eq!(SpecialKey::from_name("PAGEDOWN"), Some(SpecialKey::PageDown));
+        assert_eq!(SpecialKey::from_name("F12"), Some(SpecialKey::F(12)));
+        assert_eq!(SpecialKey::from_name("F13"), None);
+        assert_eq!(SpecialKey::from_name("Fx"), None);
+        assert_eq!(SpecialKey::from_name("SHIFT"), None);
+    }
+
+    #[test]
+    fn printable_ascii() {
+        assert_eq!(char_code(' '), Some(32));
+        assert_eq!(char_code('a'), Some(97));
+        assert_eq!(char_code('A'), Some(65));
+        assert_eq!(char_code('~'), Some(126));
+        assert_eq!(char_code('\t'), None);
+        assert_eq!(char_code('\u{7f}'), None);
+        assert_eq!(char_code('é'), None);
+    }
+
+    #[test]
+    fn shifted_text() {
+        const SHIFT: u32 = 1;
+        const KEY_1: u32 = 2;
+        let mut tracker = KeyTracker::new();
+
+        tracker.press(SHIFT);
+        tracker.press(KEY_1);
+        assert_eq!(tracker.text("!"), Some(33));
+
+        // Releasing the modifier keeps the character held
+        assert!(!tracker.release(SHIFT));
+        assert!(tracker.release(KEY_1));
+    }
+
+    #[test]
+    fn modifiers_alone() {
+        const CAPS_LOCK: u32 = 1;
+        let mut tracker = KeyTracker::new();
+
+        tracker.press(CAPS_LOCK);
+        assert!(!tracker.release(CAPS_LOCK));
+    }
+
+    #[test]
+    fn special_then_release() {
+        const LEFT_ARROW: u32 = 7;
+        let mut tracker = KeyTracker::new();
+
+        assert_eq!(tracker.press_special(LEFT_ARROW, SpecialKey::Left), LEFT);
+        assert!(tracker.release(LEFT_ARROW));
+        assert!(!tracker.release(LEFT_ARROW));
+    }
+}
@@ -1,11 +1,35 @@
 #[macro_use] extern crate log;
 extern crate hack_assembler;
+extern crate hack_vm;
+extern crate hack_hdl;
+extern crate image;
+extern crate gif;
+extern crate glob;
 
 pub use cpu::Cpu;
+pub use display::Display;
+pub use input::{InputScript, KeyEvent};
+pub use machine::Machine;
 pub use memory::{Ram, Rom};
-pub use runner::runner;
+pub use runner::{runner, run_test, run_test_with_limits, run_tests, find_scripts, junit_report, json_report};
+pub use runner::{ComparisonFailure, Mismatch, TestFailure, TestRun};
+pub use limits::{Limits, LimitExceeded};
+pub use lockstep::{lockstep, Difference, Divergence};
+pub use recorder::ScreenRecorder;
+pub use screen::Screen;
 
+#[macro_use] mod macros;
 mod memory;
 mod instructions;
 mod cpu;
+mod input;
+pub mod machine;
 mod runner;
+pub mod screen;
+pub mod keyboard;
+pub mod debug;
+pub mod font;
+pub mod display;
+pub mod limits;
+mod lockstep;
+mod recorder;
@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use memory::Word;
+
+// Checks between readings of the wall clock
+const CHECKS_PER_CLOCK_READ: u64 = 4096;
+// Program counters kept to find the loop a runaway program is stuck in
+const TRACE_LEN: usize = 256;
+
+/// Bounds on how much work a program or test script may do
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    /// Clock cycles, across every program a script loads
+    pub max_cycles: Option<u64>,
+    /// Wall-clock time
+    pub timeout: Option<Duration>,
+    /// Bytes of test output
+    pub max_output: Option<usize>,
+}
+
+/// A limit that was exceeded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Cycles(u64),
+    Time(Duration),
+    Output(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Limit::Cycles(max) => write!(f, "the limit of {} cycles", max),
+            Limit::Time(timeout) => write!(f, "the time limit of {}s", timeout.as_secs()),
+            Limit::Output(max) => write!(f, "the output limit of {} bytes", max),
+        }
+    }
+}
+
+/// Where a program was when it went beyond its limits
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitExceeded {
+    pub limit: Limit,
+    pub cycles: u64,
+    pub pc: Word,
+    /// The first and last address of the loop the program seems to be
+    /// spinning in, if there is one
+    pub tight_loop: Option<(Word, Word)>,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "Exceeded {} after {} cycles, at PC {}", self.limit, self.cycles, self.pc));
+        match self.tight_loop {
+            Some((first, last)) => write!(f, ", probably stuck in the loop at ROM[{}..{}]", first, last),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Enforces `Limits` on a running program
+pub struct Watchdog {
+    limits: Limits,
+    started: Instant,
+    checks: u64,
+    trace: VecDeque<Word>,
+}
+
+impl Watchdog {
+    pub fn new(limits: Limits) -> Watchdog {
+        Watchdog { limits: limits, started: Instant::now(), checks: 0, trace: VecDeque::new() }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Note a clock cycle that left the program at `pc`, `cycles` cycles in
+    pub fn cycle(&mut self, pc: Word, cycles: u64) -> Result<(), LimitExceeded> {
+        if self.trace.len() == TRACE_LEN {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(pc);
+
+        if let Some(max) = self.limits.max_cycles {
+            if cycles >= max {
+                return Err(self.exceeded(Limit::Cycles(max), pc, cycles))
+            }
+        }
+        self.check_time(pc, cycles)
+    }
+
+    /// Check the wall clock, every so often, for work that doesn't run cycles
+    pub fn check_time(&mut self, pc: Word, cycles: u64) -> Result<(), LimitExceeded> {
+        self.checks += 1;
+        if let Some(timeout) = self.limits.timeout {
+            if self.checks % CHECKS_PER_CLOCK_READ == 0 && self.started.elapsed() > timeout {
+                return Err(self.exceeded(Limit::Time(timeout), pc, cycles))
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_output(&self, len: usize, pc: Word, cycles: u64) -> Result<(), LimitExceeded> {
+        match self.limits.max_output {
+            Some(max) if len > max => Err(self.exceeded(Limit::Output(max), pc, cycles)),
+            _ => Ok(()),
+        }
+    }
+
+    fn exceeded(&self, limit: Limit, pc: Word, cycles: u64) -> LimitExceeded {
+        LimitExceeded { limit: limit, cycles: cycles, pc: pc, tight_loop: self.tight_loop(pc) }
+    }
+
+    // The span of the latest backward jump, if it surrounds `pc` and the
+    // program hasn't left it since
+    fn tight_loop(&self, pc: Word) -> Option<(Word, Word)> {
+        let pcs: Vec<Word> = self.trace.iter().cloned().collect();
+        let jump = (1..pcs.len()).rev().find(|&i| pcs[i] <= pcs[i - 1]);
+
+        jump.and_then(|i| {
+            let (first, last) = (pcs[i], pcs[i - 1]);
+            let inside = |pc: &Word| *pc >= first && *pc <= last;
+            if inside(&pc) && pcs[i..].iter().all(inside) { Some((first, last)) } else { None }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn cycle_limit() {
+        let mut watchdog = Watchdog::new(Limits { max_cycles: Some(10), ..Limits::default() });
+        for cycles in 1..10 {
+            assert!(watchdog.cycle(0, cycles).is_ok());
+        }
+        let exceeded = watchdog.cycle(0, 10).unwrap_err();
+        assert_eq!(exceeded.limit, Limit::Cycles(10));
+        assert_eq!(exceeded.to_string(), "Exceeded the limit of 10 cycles after 10 cycles, at PC 0, \
+                                          probably stuck in the loop at ROM[0..0]");
+
+        let mut unlimited = Watchdog::new(Limits::default());
+        assert!(unlimited.cycle(0, u64::max_value()).is_ok());
+    }
+
+    #[test]
+    fn timeout() {
+        let mut watchdog = Watchdog::new(Limits { timeout: Some(Duration::from_secs(0)), ..Limits::default() });
+        watchdog.started = Instant::now() - Duration::from_millis(10);
+        // The clock is only read every so often
+        for _ in 1..CHECKS_PER_CLOCK_READ {
+            assert!(watchdog.check_time(7, 0).is_ok());
+        }
+        assert_eq!(watchdog.check_time(7, 0).unwrap_err().limit, Limit::Time(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn output_limit() {
+        let watchdog = Watchdog::new(Limits { max_output: Some(100), ..Limits::default() });
+        assert!(watchdog.check_output(100, 0, 0).is_ok());
+        assert_eq!(watchdog.check_output(101, 0, 0).unwrap_err().limit, Limit::Output(100));
+    }
+
+    #[test]
+    fn finds_tight_loop() {
+        let mut watchdog = Watchdog::new(Limits::default());
+        // Straight-line code, then a loop over 10..14
+        for pc in 0..10 {
+            watchdog.cycle(pc, 0).unwrap();
+        }
+        for _ in 0..3 {
+            for pc in 10..15 {
+                watchdog.cycle(pc, 0).unwrap();
+            }
+        }
+        assert_eq!(watchdog.tight_loop(12), Some((10, 14)));
+        // Somewhere else since the last jump back
+        assert_eq!(watchdog.tight_loop(20), None);
+
+        let mut straight = Watchdog::new(Limits::default());
+        for pc in 0..10 {
+            straight.cycle(pc, 0).unwrap();
+        }
+        assert_eq!(straight.tight_loop(9), None);
+    }
+}
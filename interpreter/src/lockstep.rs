@@ -0,0 +1,103 @@
+use std::fmt;
+
+use hack_hdl::Chip;
+
+use cpu::Cpu;
+use memory::{Rom, Word};
+
+// The chip's registers, found by the built-in chips holding them
+static REGISTERS: &'static [(&'static str, &'static str)] = &[("PC", "PC"), ("A", "ARegister"), ("D", "DRegister")];
+
+// The built-in chips a computer's memory can be made of, and where their
+// words are in it
+static MEMORIES: &'static [(&'static str, usize)] = &[("Memory", 0), ("RAM16K", 0), ("Screen", 0x4000)];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Difference {
+    /// A register's value on the chip and in the CPU emulator
+    Register(&'static str, Word, Word),
+    /// The address and value written to memory by the chip and by the CPU
+    /// emulator, if anything was
+    Write(Option<(Word, Word)>, Option<(Word, Word)>),
+}
+
+/// The first clock cycle, counting from 1, after which a chip running a
+/// program no longer matches the CPU emulator
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub cycle: u64,
+    pub difference: Difference,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let write = |write: Option<(Word, Word)>| match write {
+            Some((address, value)) => format!("writes {} to RAM[{}]", value, address),
+            None => "writes nothing".to_string(),
+        };
+        match self.difference {
+            Difference::Register(name, chip, emulator) => {
+                write!(f, "Cycle {}: {} is {} on the chip, but {} in the CPU emulator", self.cycle, name, chip, emulator)
+            }
+            Difference::Write(chip, emulator) => {
+                write!(f, "Cycle {}: The chip {}, but the CPU emulator {}", self.cycle, write(chip), write(emulator))
+            }
+        }
+    }
+}
+
+// The address and value the chip wrote to memory on the last cycle
+fn chip_write(chip: &Chip) -> Option<(Word, Word)> {
+    for &(part, base) in MEMORIES {
+        if let Some(address) = chip.written(part) {
+            let value = chip.memory(part).unwrap()[address];
+            return Some(((base + address) as Word, value))
+        }
+    }
+    None
+}
+
+/// Run `program` for up to `cycles` clock cycles both on a computer chip,
+/// like Computer.hdl with the built-in ROM32K, and in the CPU emulator,
+/// comparing the PC, A and D registers the chip has built-in parts for and
+/// the memory writes after every cycle
+pub fn lockstep(chip: &mut Chip, program: Rom, cycles: u64) -> Result<Option<Divergence>, String> {
+    let name = chip.name.clone();
+    match chip.memory_mut("ROM32K") {
+        Some(rom) => for (word, &instruction) in rom.iter_mut().zip(program.iter()) {
+            *word = instruction;
+        },
+        None => return Err(format!("{} has no built-in ROM32K to run the program", name)),
+    }
+    let registers: Vec<&(&str, &str)> = REGISTERS.iter().filter(|&&(_, part)| chip.memory(part).is_some()).collect();
+    if registers.is_empty() {
+        return Err(format!("{} has no built-in PC, ARegister or DRegister to compare", name))
+    }
+
+    let mut cpu = Cpu::new(program);
+    chip.eval();
+    for cycle in 1..cycles + 1 {
+        try!(cpu.step().map_err(|e| format!("Cycle {}: The CPU emulator stopped: {}", cycle, e)));
+        chip.tick();
+        chip.tock();
+
+        let divergence = |difference| Ok(Some(Divergence { cycle: cycle, difference: difference }));
+        for &&(name, part) in &registers {
+            let value = chip.memory(part).unwrap()[0];
+            let expected = match name {
+                "PC" => cpu.pc,
+                "A" => cpu.ra,
+                _ => cpu.rd,
+            };
+            if value != expected {
+                return divergence(Difference::Register(name, value, expected))
+            }
+        }
+        let written = chip_write(chip);
+        let expected = cpu.last_write().map(|address| (address, cpu.ram[address as usize]));
+        if written != expected {
+            return divergence(Difference::Write(written, expected))
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,432 @@
+use std::time::{Duration, Instant};
+
+use cpu::Cpu;
+use input::{InputScript, KeyEvent};
+use limits::{LimitExceeded, Watchdog};
+use memory::{Rom, Word, RAM_SIZE};
+use screen::KEYBOARD_ADDR;
+
+// TODO: MATH - Decide the MHz of the Cpu, partition it between frames
+pub const CYCLES_PER_UPDATE: u64 = 40000;
+pub const UPDATES_PER_SEC: u64 = 60;
+
+const MAX_CYCLES_PER_UPDATE: u64 = CYCLES_PER_UPDATE * 64;
+// How many cycles to run between checking the clock at full speed
+const FULL_SPEED_BATCH: u64 = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    /// A fixed number of cycles for every update
+    Cycles(u64),
+    /// As many cycles as the host can run in an update
+    Full,
+}
+
+/// Run controls shared by the simulator frontends, bound to Ctrl+<key>
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    TogglePause,
+    StepInstruction,
+    StepFrame,
+    Faster,
+    Slower,
+    ToggleFullSpeed,
+    Reset,
+}
+
+static CONTROL_KEYS: &'static [(char, Control, &'static str)] = &[
+    ('p', Control::TogglePause, "pause/resume"),
+    ('s', Control::StepInstruction, "step instruction"),
+    ('f', Control::StepFrame, "step frame"),
+    ('u', Control::Faster, "speed up"),
+    ('d', Control::Slower, "slow down"),
+    ('g', Control::ToggleFullSpeed, "full speed"),
+    ('r', Control::Reset, "reset"),
+];
+
+impl Control {
+    /// The control bound to Ctrl and `key`, if any
+    pub fn from_ctrl_key(key: char) -> Option<Control> {
+        let key = key.to_ascii_lowercase();
+        CONTROL_KEYS.iter().find(|&&(k, _, _)| k == key).map(|&(_, control, _)| control)
+    }
+
+    /// A one line summary of the key bindings
+    pub fn help() -> String {
+        CONTROL_KEYS.iter()
+                    .map(|&(key, _, description)| format!("^{} {}", key.to_ascii_uppercase(), description))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+    }
+}
+
+/// Drives a `Cpu`, keeping count of the executed cycles so keyboard input
+/// can be replayed from, or recorded to, an `InputScript` deterministically.
+pub struct Machine {
+    pub cpu: Cpu,
+    cycles: u64,
+    replay: Option<(InputScript, usize)>,
+    recording: Option<InputScript>,
+    paused: bool,
+    speed: Speed,
+    // The fixed speed to return to when leaving full speed
+    cycles_per_update: u64,
+    // The cycle each RAM address was last written in, plus one
+    written: Vec<u64>,
+    // Why the CPU stopped, if it did
+    fault: Option<String>,
+}
+
+impl Machine {
+    pub fn new(program: Rom) -> Machine {
+        Machine {
+            cpu: Cpu::new(program),
+            cycles: 0,
+            replay: None,
+            recording: None,
+            paused: false,
+            speed: Speed::Cycles(CYCLES_PER_UPDATE),
+            cycles_per_update: CYCLES_PER_UPDATE,
+            written: vec![0; RAM_SIZE],
+            fault: None,
+        }
+    }
+
+    /// Number of instructions executed so far
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Apply the events of `script` as the machine reaches their cycles
+    pub fn replay(&mut self, script: InputScript) {
+        self.replay = Some((script, 0));
+    }
+
+    /// Start recording live keyboard input
+    pub fn record(&mut self) {
+        self.recording = Some(InputScript::new());
+    }
+
+    pub fn recording(&self) -> Option<&InputScript> {
+        self.recording.as_ref()
+    }
+
+    /// Live key press, applied before the next instruction executes
+    pub fn press(&mut self, key: Word) {
+        self.set_key(key)
+    }
+
+    /// Live key release
+    pub fn release(&mut self) {
+        self.set_key(0)
+    }
+
+    /// Execute `cycles` instructions, applying any scheduled input on the way.
+    /// Stops early, pausing, at an instruction the CPU can't execute.
+    pub fn run(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            if !self.step() { break }
+        }
+    }
+
+    /// Like `run`, but stop early if the watchdog's limits are exceeded
+    pub fn run_watched(&mut self, cycles: u64, watchdog: &mut Watchdog) -> Result<(), LimitExceeded> {
+        for _ in 0..cycles {
+            if !self.step() { break }
+            try!(watchdog.cycle(self.cpu.pc, self.cycles));
+        }
+        Ok(())
+    }
+
+    /// Why the CPU stopped, like a write outside of memory
+    pub fn fault(&self) -> Option<&str> {
+        self.fault.as_ref().map(|fault| &fault[..])
+    }
+
+    // Returns whether the instruction ran
+    fn step(&mut self) -> bool {
+        if self.fault.is_some() { return false }
+
+        self.apply_scheduled_input();
+        if let Err(fault) = self.cpu.step() {
+            self.fault = Some(fault);
+            self.paused = true;
+            return false
+        }
+        if let Some(address) = self.cpu.last_write() {
+            self.written[address as usize] = self.cycles + 1;
+        }
+        self.cycles += 1;
+        true
+    }
+
+    /// Run one update's worth of cycles at the current speed unless paused,
+    /// frontends call this `UPDATES_PER_SEC` times a second
+    pub fn update(&mut self) {
+        if self.paused { return }
+
+        match self.speed {
+            Speed::Cycles(cycles) => self.run(cycles),
+            Speed::Full => {
+                let deadline = Instant::now() + Duration::from_millis(1000 / UPDATES_PER_SEC);
+                while Instant::now() < deadline {
+                    self.run(FULL_SPEED_BATCH)
+                }
+            }
+        }
+    }
+
+    pub fn control(&mut self, control: Control) {
+        match control {
+            Control::TogglePause => self.paused = !self.paused,
+            Control::StepInstruction => {
+                self.paused = true;
+                self.run(1);
+            }
+            Control::StepFrame => {
+                self.paused = true;
+                let cycles = self.cycles_per_update;
+                self.run(cycles);
+            }
+            Control::Faster => {
+                self.cycles_per_update = (self.cycles_per_update * 2).min(MAX_CYCLES_PER_UPDATE);
+                self.speed = Speed::Cycles(self.cycles_per_update);
+            }
+            Control::Slower => {
+                self.cycles_per_update = (self.cycles_per_update / 2).max(1);
+                self.speed = Speed::Cycles(self.cycles_per_update);
+            }
+            Control::ToggleFullSpeed => {
+                self.speed = match self.speed {
+                    Speed::Full => Speed::Cycles(self.cycles_per_update),
+                    Speed::Cycles(..) => Speed::Full,
+                };
+            }
+            Control::Reset => self.reset(),
+        }
+    }
+
+    /// The cycle in which the program last wrote to `address`
+    pub fn written_at(&self, address: usize) -> Option<u64> {
+        match self.written[address] {
+            0 => None,
+            cycle => Some(cycle - 1)
+        }
+    }
+
+    /// The number of cycles making up an update at the current speed
+    pub fn cycles_per_update(&self) -> u64 {
+        match self.speed {
+            Speed::Cycles(cycles) => cycles,
+            Speed::Full => CYCLES_PER_UPDATE,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    /// A short description of the run state, for a title bar or status line
+    pub fn status(&self) -> String {
+        let speed = match self.speed {
+            Speed::Cycles(cycles) => format!("{} cycles/update", cycles),
+            Speed::Full => "full speed".to_string(),
+        };
+        let state = match (self.fault(), self.paused) {
+            (Some(fault), _) => format!("stopped ({})", fault),
+            (None, true) => "paused".to_string(),
+            (None, false) => "running".to_string(),
+        };
+
+        format!("{} at {}, PC: {}, cycle: {}", state, speed, self.cpu.pc, self.cycles)
+    }
+
+    /// Restart the program with cleared memory, as if it was just loaded.
+    /// Replayed input starts over and any recording is discarded.
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.cycles = 0;
+        self.written = vec![0; RAM_SIZE];
+        self.fault = None;
+        if let Some((_, ref mut next)) = self.replay {
+            *next = 0;
+        }
+        if self.recording.is_some() {
+            self.record();
+        }
+    }
+
+    fn set_key(&mut self, key: Word) {
+        if self.cpu.ram[KEYBOARD_ADDR] == key { return }
+
+        self.cpu.ram[KEYBOARD_ADDR] = key;
+        if let Some(ref mut recording) = self.recording {
+            recording.push(KeyEvent { cycle: self.cycles, key: key });
+        }
+    }
+
+    fn apply_scheduled_input(&mut self) {
+        let key = match self.replay {
+            Some((ref script, ref mut next)) => {
+                let mut key = None;
+                while let Some(event) = script.events().get(*next) {
+                    if event.cycle > self.cycles { break }
+                    key = Some(event.key);
+                    *next += 1;
+                }
+                key
+            }
+            None => None
+        };
+
+        if let Some(key) = key {
+            self.set_key(key)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::InputScript;
+    use limits::{Limit, Limits, Watchdog};
+    use memory::Rom;
+    use screen::KEYBOARD_ADDR;
+
+    // @KBD, D=M, @0, M=D, @0, 0;JMP
+    static COPY_KEYBOARD: &'static str = "0110000000000000\n\
+                                          1111110000010000\n\
+                                          0000000000000000\n\
+                                          1110001100001000\n\
+                                          0000000000000000\n\
+                                          1110101010000111\n";
+
+    #[test]
+    fn replays_at_exact_cycle() {
+        let mut machine = Machine::new(Rom::from_str(COPY_KEYBOARD).unwrap());
+        machine.replay(InputScript::parse("3 press 65\n6 release\n").unwrap());
+
+        machine.run(3);
+        assert_eq!(machine.cpu.ram[KEYBOARD_ADDR], 0);
+        machine.run(1);
+        assert_eq!(machine.cpu.ram[KEYBOARD_ADDR], 65);
+        machine.run(2);
+        assert_eq!(machine.cpu.ram[KEYBOARD_ADDR], 65);
+        machine.run(1);
+        assert_eq!(machine.cpu.ram[KEYBOARD_ADDR], 0);
+        assert_eq!(machine.cycles(), 7);
+    }
+
+    #[test]
+    fn pause_and_step() {
+        let mut machine = Machine::new(Rom::from_str(COPY_KEYBOARD).unwrap());
+        machine.control(Control::TogglePause);
+        machine.update();
+        assert_eq!(machine.cycles(), 0);
+
+        machine.control(Control::StepInstruction);
+        assert!(machine.is_paused());
+        assert_eq!(machine.cycles(), 1);
+
+        machine.control(Control::StepFrame);
+        assert_eq!(machine.cycles(), 1 + CYCLES_PER_UPDATE);
+
+        machine.control(Control::TogglePause);
+        machine.update();
+        assert_eq!(machine.cycles(), 1 + 2 * CYCLES_PER_UPDATE);
+    }
+
+    #[test]
+    fn speed_controls() {
+        let mut machine = Machine::new(Rom::from_str(COPY_KEYBOARD).unwrap());
+        machine.control(Control::Faster);
+        assert_eq!(machine.speed(), Speed::Cycles(CYCLES_PER_UPDATE * 2));
+
+        machine.control(Control::ToggleFullSpeed);
+        assert_eq!(machine.speed(), Speed::Full);
+        machine.control(Control::ToggleFullSpeed);
+        assert_eq!(machine.speed(), Speed::Cycles(CYCLES_PER_UPDATE * 2));
+
+        for _ in 0..40 { machine.control(Control::Slower) }
+        assert_eq!(machine.speed(), Speed::Cycles(1));
+        machine.update();
+        assert_eq!(machine.cycles(), 1);
+
+        for _ in 0..40 { machine.control(Control::Faster) }
+        assert_eq!(machine.speed(), Speed::Cycles(MAX_CYCLES_PER_UPDATE));
+    }
+
+    #[test]
+    fn reset_restarts_replay() {
+        let mut machine = Machine::new(Rom::from_str(COPY_KEYBOARD).unwrap());
+        machine.replay(InputScript::parse("2 press 65\n").unwrap());
+        machine.run(10);
+        assert_eq!(machine.cpu.ram[0], 65);
+
+        machine.control(Control::Reset);
+        assert_eq!(machine.cycles(), 0);
+        assert_eq!(machine.cpu.pc, 0);
+        assert_eq!(machine.cpu.ram[0], 0);
+        assert_eq!(machine.cpu.ram[KEYBOARD_ADDR], 0);
+
+        machine.run(3);
+        assert_eq!(machine.cpu.ram[KEYBOARD_ADDR], 65);
+    }
+
+    #[test]
+    fn tracks_writes() {
+        let mut machine = Machine::new(Rom::from_str(COPY_KEYBOARD).unwrap());
+        machine.run(3);
+        assert_eq!(machine.written_at(0), None);
+        machine.run(1);
+        assert_eq!(machine.written_at(0), Some(3));
+        machine.run(6);
+        assert_eq!(machine.written_at(0), Some(9));
+        assert_eq!(machine.written_at(1), None);
+    }
+
+    #[test]
+    fn watched_run_stops_at_limit() {
+        let mut machine = Machine::new(Rom::from_str(COPY_KEYBOARD).unwrap());
+        let mut watchdog = Watchdog::new(Limits { max_cycles: Some(10), ..Limits::default() });
+        let exceeded = machine.run_watched(100, &mut watchdog).unwrap_err();
+
+        assert_eq!(machine.cycles(), 10);
+        assert_eq!(exceeded.limit, Limit::Cycles(10));
+        assert_eq!(exceeded.tight_loop, Some((0, 5)));
+    }
+
+    #[test]
+    fn control_keys() {
+        assert_eq!(Control::from_ctrl_key('P'), Some(Control::TogglePause));
+        assert_eq!(Control::from_ctrl_key('r'), Some(Control::Reset));
+        assert_eq!(Control::from_ctrl_key('x'), None);
+    }
+
+    #[test]
+    fn recording_replays_identically() {
+        let mut live = Machine::new(Rom::from_str(COPY_KEYBOARD).unwrap());
+        live.record();
+        live.run(5);
+        live.press(130);
+        live.run(7);
+        live.release();
+        live.run(4);
+        live.press(132);
+        live.run(10);
+
+        let script = live.recording().unwrap().clone();
+        assert_eq!(script.to_string(), "5 press 130\n12 release\n16 press 132\n");
+
+        let mut replayed = Machine::new(Rom::from_str(COPY_KEYBOARD).unwrap());
+        replayed.replay(script);
+        replayed.run(26);
+
+        assert_eq!(&replayed.cpu.ram[..], &live.cpu.ram[..]);
+        assert_eq!(replayed.cpu.pc, live.cpu.pc);
+    }
+}
@@ -0,0 +1,227 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use gif::{self, Frame, Repeat, SetParameter};
+use machine::{Machine, CYCLES_PER_UPDATE, UPDATES_PER_SEC};
+use screen::{Screen, WIDTH, HEIGHT};
+
+// Cycles making up a second of emulated time at the default speed
+const CYCLES_PER_SEC: u64 = CYCLES_PER_UPDATE * UPDATES_PER_SEC;
+// GIF frame delays are in hundredths of a second
+const MAX_FPS: u64 = 100;
+
+// White for clear pixels, black for set ones
+static PALETTE: [u8; 6] = [255, 255, 255, 0, 0, 0];
+
+enum Output {
+    // The latest frame is held back until the next one tells how long it showed
+    Gif(gif::Encoder<File>, Option<(Vec<u8>, u64)>),
+    Png(PathBuf),
+}
+
+/// Captures the screen of a running `Machine` at a fixed rate of emulated time,
+/// into an animated GIF, or numbered PNGs in a directory for any other path.
+pub struct ScreenRecorder {
+    output: Output,
+    cycles_per_frame: u64,
+    next_frame: u64,
+    frames: usize,
+}
+
+impl ScreenRecorder {
+    pub fn new<P: AsRef<Path>>(path: P, fps: u64) -> Result<ScreenRecorder, String> {
+        let path = path.as_ref();
+        if fps == 0 || fps > MAX_FPS {
+            return Err(format!("Frame rate must be between 1 and {}, got {}", MAX_FPS, fps))
+        }
+
+        let output = if path.extension().map_or(false, |ext| ext == "gif") {
+            let f = try_s!(File::create(path));
+            let mut encoder = try_s!(gif::Encoder::new(f, WIDTH as u16, HEIGHT as u16, &PALETTE));
+            try_s!(encoder.set(Repeat::Infinite));
+            Output::Gif(encoder, None)
+        } else {
+            try_s!(fs::create_dir_all(path));
+            Output::Png(path.to_path_buf())
+        };
+
+        Ok(ScreenRecorder {
+            output: output,
+            cycles_per_frame: CYCLES_PER_SEC / fps,
+            next_frame: 0,
+            frames: 0,
+        })
+    }
+
+    /// Capture a frame if one is due at the machine's current cycle
+    pub fn update(&mut self, machine: &Machine) -> Result<(), String> {
+        let cycles = machine.cycles();
+        // The machine was reset, start timing frames over
+        if cycles + self.cycles_per_frame < self.next_frame {
+            self.next_frame = cycles;
+        }
+        if cycles < self.next_frame { return Ok(()) }
+
+        try!(self.capture(&Screen::from_ram(&machine.cpu.ram), cycles));
+        self.next_frame = (cycles / self.cycles_per_frame + 1) * self.cycles_per_frame;
+        Ok(())
+    }
+
+    /// Cycles the machine can run before the next frame is due
+    pub fn cycles_until_frame(&self, machine: &Machine) -> u64 {
+        self.next_frame.saturating_sub(machine.cycles())
+    }
+
+    /// Number of frames captured so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Write out any frame still held back
+    pub fn finish(mut self) -> Result<(), String> {
+        if let Output::Gif(ref mut encoder, ref mut pending) = self.output {
+            if let Some((pixels, _)) = pending.take() {
+                let delay = centiseconds(self.cycles_per_frame);
+                try!(write_gif_frame(encoder, pixels, delay));
+            }
+        }
+        Ok(())
+    }
+
+    fn capture(&mut self, screen: &Screen, cycle: u64) -> Result<(), String> {
+        match self.output {
+            Output::Gif(ref mut encoder, ref mut pending) => {
+                let pixels = (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+                                        .map(|(x, y)| screen.pixel(x, y) as u8)
+                                        .collect();
+                if let Some((previous, shown_at)) = pending.take() {
+                    try!(write_gif_frame(encoder, previous, centiseconds(cycle - shown_at)));
+                }
+                *pending = Some((pixels, cycle));
+            }
+            Output::Png(ref dir) => {
+                try!(screen.save(dir.join(format!("{:05}.png", self.frames))));
+            }
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+fn centiseconds(cycles: u64) -> u16 {
+    (cycles * 100 / CYCLES_PER_SEC).max(1).min(u16::max_value() as u64) as u16
+}
+
+fn write_gif_frame(encoder: &mut gif::Encoder<File>, pixels: Vec<u8>, delay: u16) -> Result<(), String> {
+    let frame = Frame {
+        delay: delay,
+        width: WIDTH as u16,
+        height: HEIGHT as u16,
+        buffer: pixels.into(),
+        ..Frame::default()
+    };
+    try_s!(encoder.write_frame(&frame));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Read;
+    use limits::{Limits, Watchdog};
+    use machine::Machine;
+    use memory::Rom;
+
+    extern crate tempdir;
+    use self::tempdir::TempDir;
+
+    // @SCREEN, M=!M, @0, 0;JMP
+    fn blinking() -> Machine {
+        let program = "0100000000000000\n\
+                       1111110001001000\n\
+                       0000000000000000\n\
+                       1110101010000111\n";
+        Machine::new(Rom::from_str(program).unwrap())
+    }
+
+    // A fault stops the machine short of `cycles`, so it ends recording too
+    fn record(machine: &mut Machine, recorder: &mut ScreenRecorder, cycles: u64) {
+        let mut watchdog = Watchdog::new(Limits::default());
+        while machine.cycles() < cycles && machine.fault().is_none() {
+            recorder.update(machine).unwrap();
+            let n = recorder.cycles_until_frame(machine).min(cycles - machine.cycles());
+            machine.run_watched(n, &mut watchdog).unwrap();
+        }
+    }
+
+    #[test]
+    fn pngs_at_frame_rate() {
+        let dir = TempDir::new("recorder").unwrap();
+        let mut machine = blinking();
+        let mut recorder = ScreenRecorder::new(dir.path(), 10).unwrap();
+
+        record(&mut machine, &mut recorder, CYCLES_PER_SEC / 2);
+        assert_eq!(recorder.frames(), 5);
+        recorder.finish().unwrap();
+
+        assert!(dir.path().join("00004.png").exists());
+        assert!(!dir.path().join("00005.png").exists());
+        // The first frame shows the screen before the program starts
+        let first = Screen::from_file(dir.path().join("00000.png")).unwrap();
+        assert!(!(0..16).any(|x| first.pixel(x, 0)));
+    }
+
+    #[test]
+    fn animated_gif() {
+        let dir = TempDir::new("recorder").unwrap();
+        let path = dir.path().join("blink.gif");
+        let mut machine = blinking();
+        let mut recorder = ScreenRecorder::new(&path, 20).unwrap();
+
+        record(&mut machine, &mut recorder, CYCLES_PER_SEC);
+        assert_eq!(recorder.frames(), 20);
+        recorder.finish().unwrap();
+
+        let mut data = vec![];
+        File::open(&path).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(&data[..6], b"GIF89a");
+        assert_eq!(data.last(), Some(&0x3B));
+    }
+
+    #[test]
+    fn restarts_after_reset() {
+        let dir = TempDir::new("recorder").unwrap();
+        let mut machine = blinking();
+        let mut recorder = ScreenRecorder::new(dir.path(), 1).unwrap();
+
+        record(&mut machine, &mut recorder, CYCLES_PER_SEC * 2);
+        assert_eq!(recorder.frames(), 2);
+
+        machine.reset();
+        recorder.update(&machine).unwrap();
+        assert_eq!(recorder.frames(), 3);
+    }
+
+    #[test]
+    fn stops_at_a_fault() {
+        let dir = TempDir::new("recorder").unwrap();
+        // @32000, M=1
+        let program = "0111110100000000\n1110111111001000\n";
+        let mut machine = Machine::new(Rom::from_str(program).unwrap());
+        let mut recorder = ScreenRecorder::new(dir.path(), 10).unwrap();
+
+        record(&mut machine, &mut recorder, CYCLES_PER_SEC);
+        assert_eq!(machine.cycles(), 1);
+        assert_eq!(machine.fault(), Some("ROM[1] writes to RAM[32000], which is outside of memory"));
+        assert_eq!(recorder.frames(), 1);
+    }
+
+    #[test]
+    fn rejects_frame_rate() {
+        let dir = TempDir::new("recorder").unwrap();
+        assert!(ScreenRecorder::new(dir.path(), 0).is_err());
+        assert!(ScreenRecorder::new(dir.path(), 101).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use glob::glob;
+
+use limits::Limits;
+use super::{run_test_with_limits, TestFailure, TestRun};
+
+/// The test scripts named by `patterns`. A directory gives every .tst file
+/// below it, anything else is a file or a glob.
+pub fn find_scripts<S: AsRef<str>>(patterns: &[S]) -> Result<Vec<PathBuf>, String> {
+    let mut scripts = vec![];
+    for pattern in patterns {
+        let pattern = pattern.as_ref();
+        let glob_pattern = if Path::new(pattern).is_dir() {
+            format!("{}/**/*.tst", pattern.trim_right_matches('/'))
+        } else {
+            pattern.to_string()
+        };
+
+        let before = scripts.len();
+        for path in try!(glob(&glob_pattern).map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))) {
+            scripts.push(try_s!(path));
+        }
+        if scripts.len() == before {
+            return Err(format!("No test scripts found for '{}'", pattern))
+        }
+    }
+
+    scripts.sort();
+    scripts.dedup();
+    Ok(scripts)
+}
+
+/// Run the scripts on up to `jobs` threads, reporting them in the order given
+pub fn run_tests(scripts: &[PathBuf], jobs: usize, limits: &Limits) -> Vec<TestRun> {
+    let queue = Arc::new(Mutex::new(scripts.iter().cloned().enumerate().collect::<Vec<_>>().into_iter()));
+    let (sender, receiver) = mpsc::channel();
+
+    let workers: Vec<_> = (0..jobs.max(1).min(scripts.len())).map(|_| {
+        let (queue, sender, limits) = (queue.clone(), sender.clone(), *limits);
+        thread::spawn(move || {
+            loop {
+                let next = queue.lock().unwrap().next();
+                match next {
+                    Some((i, script)) => sender.send((i, run_isolated(script, &limits))).unwrap(),
+                    None => break,
+                }
+            }
+        })
+    }).collect();
+    drop(sender);
+
+    let mut runs: Vec<_> = receiver.iter().collect();
+    for worker in workers {
+        worker.join().expect("Test runner thread panicked");
+    }
+
+    runs.sort_by_key(|&(i, _)| i);
+    runs.into_iter().map(|(_, run)| run).collect()
+}
+
+// Run a script, failing it rather than the whole batch if it panics
+fn run_isolated(script: PathBuf, limits: &Limits) -> TestRun {
+    match panic::catch_unwind(AssertUnwindSafe(|| run_test_with_limits(&script, limits))) {
+        Ok(run) => run,
+        Err(payload) => {
+            let mut run = TestRun::new(script);
+            run.failure = Some(TestFailure::Error(format!("The test runner crashed: {}", panic_message(&payload))));
+            run
+        }
+    }
+}
+
+fn panic_message(payload: &Box<Any + Send>) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown panic".to_string(),
+    }
+}
+
+/// A JUnit XML report of the runs, as one test suite
+pub fn junit_report(runs: &[TestRun]) -> String {
+    let failures = runs.iter().filter(|r| is_comparison(r)).count();
+    let errors = runs.iter().filter(|r| !r.passed() && !is_comparison(r)).count();
+    let seconds: f64 = runs.iter().map(|r| r.seconds()).sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!("<testsuite name=\"hack\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n",
+                          runs.len(), failures, errors, seconds));
+
+    for run in runs {
+        let name = run.script.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned());
+        let class = run.script.parent().map_or(String::new(), |p| p.to_string_lossy().into_owned());
+        xml.push_str(&format!("  <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+                              escape_xml(&name), escape_xml(&class), run.seconds()));
+
+        match run.failure {
+            None => xml.push_str("/>\n"),
+            Some(ref failure) => {
+                let tag = if is_comparison(run) { "failure" } else { "error" };
+                let text = failure.to_string();
+                let message = text.lines().next().unwrap_or("");
+                xml.push_str(&format!(">\n    <{} message=\"{}\">{}</{}>\n  </testcase>\n",
+                                      tag, escape_xml(message), escape_xml(&text), tag));
+            }
+        }
+    }
+
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+/// A JSON report of the runs
+pub fn json_report(runs: &[TestRun]) -> String {
+    let results: Vec<String> = runs.iter().map(|run| {
+        let failure = match run.failure {
+            None => "null".to_string(),
+            Some(TestFailure::Comparison(ref failure)) => {
+                let mismatches: Vec<String> = failure.mismatches.iter().map(|m| {
+                    format!("{{\"column\": {}, \"expected\": {}, \"actual\": {}}}",
+                            escape_json(&m.column), escape_json(&m.expected), escape_json(&m.actual))
+                }).collect();
+                format!("{{\"kind\": \"comparison\", \"message\": {}, \"line\": {}, \"script_line\": {}, \
+                         \"mismatches\": [{}]}}",
+                        escape_json(&failure.to_string()), failure.line, failure.script_line,
+                        mismatches.join(", "))
+            }
+            Some(TestFailure::Limit(ref exceeded)) => {
+                let tight_loop = exceeded.tight_loop.map_or("null".to_string(), |(first, last)| {
+                    format!("[{}, {}]", first, last)
+                });
+                format!("{{\"kind\": \"limit\", \"message\": {}, \"pc\": {}, \"loop\": {}}}",
+                        escape_json(&exceeded.to_string()), exceeded.pc, tight_loop)
+            }
+            Some(TestFailure::Error(ref message)) => {
+                format!("{{\"kind\": \"error\", \"message\": {}}}", escape_json(message))
+            }
+        };
+
+        format!("    {{\"script\": {}, \"passed\": {}, \"outputs\": {}, \"cycles\": {}, \"seconds\": {:.3}, \
+                 \"failure\": {}}}",
+                escape_json(&run.script.to_string_lossy()), run.passed(), run.outputs, run.cycles,
+                run.seconds(), failure)
+    }).collect();
+
+    let passed = runs.iter().filter(|r| r.passed()).count();
+    format!("{{\n  \"tests\": {},\n  \"passed\": {},\n  \"failed\": {},\n  \"results\": [\n{}\n  ]\n}}\n",
+            runs.len(), passed, runs.len() - passed, results.join(",\n"))
+}
+
+fn is_comparison(run: &TestRun) -> bool {
+    match run.failure {
+        Some(TestFailure::Comparison(..)) => true,
+        _ => false,
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.chars().map(|c| match c {
+        '&' => "&amp;".to_string(),
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '"' => "&quot;".to_string(),
+        '\'' => "&apos;".to_string(),
+        // Colors and other control characters aren't allowed in XML 1.0
+        c if c < ' ' && c != '\n' && c != '\t' => String::new(),
+        c => c.to_string(),
+    }).collect()
+}
+
+// A quoted JSON string
+fn escape_json(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::{escape_json, escape_xml};
+    use std::path::PathBuf;
+    use runner::{TestFailure, TestRun};
+
+    fn runs() -> Vec<TestRun> {
+        let mut passed = TestRun::new(PathBuf::from("tests/Mult.tst"));
+        passed.outputs = 7;
+        passed.cycles = 630;
+        let mut failed = TestRun::new(PathBuf::from("tests/Loop.tst"));
+        failed.failure = Some(TestFailure::Error("Exceeded the limit of 10 cycles".to_string()));
+        vec![passed, failed]
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape_xml("a < \"b\" & \x1b[31mc"), "a &lt; &quot;b&quot; &amp; [31mc");
+        assert_eq!(escape_json("say \"hi\"\n\\"), "\"say \\\"hi\\\"\\n\\\\\"");
+        assert_eq!(escape_json("\x1b"), "\"\\u001b\"");
+    }
+
+    #[test]
+    fn junit() {
+        assert_eq!(junit_report(&runs()),
+                   "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                    <testsuite name=\"hack\" tests=\"2\" failures=\"0\" errors=\"1\" time=\"0.000\">\n  \
+                    <testcase name=\"Mult\" classname=\"tests\" time=\"0.000\"/>\n  \
+                    <testcase name=\"Loop\" classname=\"tests\" time=\"0.000\">\n    \
+                    <error message=\"Exceeded the limit of 10 cycles\">Exceeded the limit of 10 cycles</error>\n  \
+                    </testcase>\n\
+                    </testsuite>\n");
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(json_report(&runs()),
+                   "{\n  \"tests\": 2,\n  \"passed\": 1,\n  \"failed\": 1,\n  \"results\": [\n    \
+                    {\"script\": \"tests/Mult.tst\", \"passed\": true, \"outputs\": 7, \"cycles\": 630, \
+                    \"seconds\": 0.000, \"failure\": null},\n    \
+                    {\"script\": \"tests/Loop.tst\", \"passed\": false, \"outputs\": 0, \"cycles\": 0, \
+                    \"seconds\": 0.000, \"failure\": {\"kind\": \"error\", \
+                    \"message\": \"Exceeded the limit of 10 cycles\"}}\n  ]\n}\n");
+    }
+}
@@ -1,112 +1,251 @@
-use self::Command::*;
+use std::fmt;
 
-#[derive(Debug)]
+use hack_vm::Segment;
+
+use memory::{Word, RAM_SIZE};
+use super::format::Column;
+use super::parser::Location;
+
+#[derive(Debug, PartialEq)]
 pub enum Command<'a> {
-    Load(&'a str),
+    /// A program, or VM code from the script's directory when no file is given
+    Load(Option<&'a str>),
     OutputFile(&'a str),
     CompareTo(&'a str),
-    OutputList(Vec<&'a str>),
-    Set(&'a str, i16),
-    Repeat(u16, Vec<Command<'a>>),
+    CompareScreen(&'a str, usize),
+    OutputList(Vec<Column>, Location),
+    Set(Var, Word),
+    /// Without a count, repeats until the script fails
+    Repeat(Option<u64>, Vec<Command<'a>>),
+    While(Condition, Vec<Command<'a>>),
+    Tick,
+    Tock,
     TickTock,
-    Output
+    VmStep,
+    Eval,
+    Output(Location),
+    Echo(&'a str),
+    ClearEcho,
+    Breakpoint(Var, Word),
+    ClearBreakpoints,
+}
+
+/// A location that scripts can read, and except for `time`, write
+#[derive(Debug, Clone, PartialEq)]
+pub enum Var {
+    A,
+    D,
+    PC,
+    Ram(usize),
+    Time,
+    /// The VM's stack pointer, `sp`
+    Sp,
+    /// The base address of `local`, `argument`, `this` or `that`
+    Base(Segment),
+    /// An entry of a VM segment, like `local[2]` or `temp[0]`
+    Entry(Segment, u16),
+    /// A pin of a chip, a bit of one like `out[3]`, or the state of a
+    /// built-in part like `RAM16K[5]` or `DRegister[]`
+    Pin(String),
 }
 
-impl<'a> Command<'a> {
-    fn parse(s: &'a str) -> Result<Command<'a>, String> {
-        debug!("Parsing Command: '{}'", s);
-
-        let mut parts = s.split(|c| c == ' ' || c == '\t' || c == '\n' || c == '\r');
-        let cmd = match expect!(parts, "name") {
-            "load" => Load(expect!(parts, "filename for load")),
-            "output-file" => OutputFile(expect!(parts, "filename for output-file")),
-            "compare-to" => CompareTo(expect!(parts, "filename for compare-to")),
-            "output-list" => OutputList(parts.map(|s| s.trim())
-                                             .filter(|s| !s.is_empty())
-                                             .collect()),
-            "set" => {
-                let location = expect!(parts, "location for set");
-                let raw_value = expect!(parts, "value for set");
-                let value = try_s!(raw_value.parse());
-                Set(location, value)
+impl Var {
+    pub fn parse(s: &str) -> Result<Var, String> {
+        let var = match s {
+            "A" => Var::A,
+            "D" => Var::D,
+            "PC" | "pc" => Var::PC,
+            "time" => Var::Time,
+            "sp" => Var::Sp,
+            "local" => Var::Base(Segment::Local),
+            "argument" => Var::Base(Segment::Argument),
+            "this" => Var::Base(Segment::This),
+            "that" => Var::Base(Segment::That),
+            _ if s.starts_with("RAM[") || s.starts_with("ram[") => {
+                if !s.ends_with(']') {
+                    return Err(format!("Missing ']' in '{}'", s))
+                }
+                let index: usize = try_s!(s[4..s.len() - 1].parse());
+                if index >= RAM_SIZE {
+                    return Err(format!("RAM address out of range: {}", index))
+                }
+                Var::Ram(index)
+            }
+            _ if s.ends_with(']') && s.contains('[') => {
+                let open = s.find('[').unwrap();
+                let segment = match &s[..open] {
+                    "local" => Segment::Local,
+                    "argument" => Segment::Argument,
+                    "this" => Segment::This,
+                    "that" => Segment::That,
+                    "temp" => Segment::Temp,
+                    _ => return Err(format!("Unknown variable: '{}'", s))
+                };
+                let index: u16 = try_s!(s[open + 1..s.len() - 1].parse());
+                if segment.size().map_or(false, |size| index >= size) {
+                    return Err(format!("Index out of range for the {} segment: {}", segment, index))
+                }
+                Var::Entry(segment, index)
             }
-            "repeat" => unreachable!(), // Handle in Commands Iterator
-            "ticktock" => TickTock,
-            "output" => Output,
-            cmd => return Err(format!("Unexpected command: '{}'", cmd))
+            _ => return Err(format!("Unknown variable: '{}'", s))
         };
 
-        Ok(cmd)
+        Ok(var)
+    }
+
+    /// Parse a variable of a script testing a chip, where every name but
+    /// `time` is a pin
+    pub fn parse_pin(s: &str) -> Result<Var, String> {
+        if s == "time" {
+            return Ok(Var::Time)
+        }
+        let name = s.find('[').map_or(s, |open| &s[..open]);
+        let index = &s[name.len()..];
+        let valid_name = name.chars().next().map_or(false, |c| c.is_alphabetic()) &&
+                         name.chars().all(|c| c.is_alphanumeric() || c == '_');
+        let valid_index = index.is_empty() ||
+                          (index.ends_with(']') && index[1..index.len() - 1].chars().all(|c| c.is_digit(10)));
+        if !valid_name || !valid_index {
+            return Err(format!("Invalid pin: '{}'", s))
+        }
+        Ok(Var::Pin(s.to_string()))
     }
 }
 
-impl<'a> Commands<'a> {
-    pub fn new(s: &str) -> Commands {
-        Commands { remaining: s }
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Var::A => write!(f, "A"),
+            Var::D => write!(f, "D"),
+            Var::PC => write!(f, "PC"),
+            Var::Ram(index) => write!(f, "RAM[{}]", index),
+            Var::Time => write!(f, "time"),
+            Var::Sp => write!(f, "sp"),
+            Var::Base(segment) => write!(f, "{}", segment),
+            Var::Entry(segment, index) => write!(f, "{}[{}]", segment, index),
+            Var::Pin(ref name) => write!(f, "{}", name),
+        }
     }
 }
 
-pub struct Commands<'a> {
-    remaining: &'a str,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
 }
 
-impl<'a> Iterator for Commands<'a> {
-    type Item = Result<Command<'a>, String>;
+impl Comparison {
+    pub fn parse(s: &str) -> Result<Comparison, String> {
+        let comparison = match s {
+            "=" => Comparison::Equal,
+            "<>" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            ">" => Comparison::Greater,
+            "<=" => Comparison::LessOrEqual,
+            ">=" => Comparison::GreaterOrEqual,
+            op => return Err(format!("Unknown comparison: '{}'", op))
+        };
 
-    fn next(&mut self) -> Option<Result<Command<'a>, String>> {
-        self.remaining = self.remaining.trim_left();
-        if self.remaining.len() == 0 { return None }
+        Ok(comparison)
+    }
+}
 
-        // Skip commented lines
-        if self.remaining.starts_with("//") {
-            return self.remaining.find('\n').and_then(|idx| {
-                    self.remaining = &self.remaining[idx+1..];
-                    self.next()
-            });
-        }
+/// The condition of a `while` loop, e.g. `RAM[0] <> 0`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub var: Var,
+    pub comparison: Comparison,
+    pub value: Word,
+}
 
-        // Special case for repeat
-        if self.remaining.starts_with("repeat") {
-            return parse_repeat(&mut self.remaining)
+impl Condition {
+    /// Does the condition hold for `actual`, the current value of `var`?
+    /// Values compare as signed 16-bit numbers.
+    pub fn holds(&self, actual: Word) -> bool {
+        let (actual, value) = (actual as i16, self.value as i16);
+        match self.comparison {
+            Comparison::Equal => actual == value,
+            Comparison::NotEqual => actual != value,
+            Comparison::Less => actual < value,
+            Comparison::Greater => actual > value,
+            Comparison::LessOrEqual => actual <= value,
+            Comparison::GreaterOrEqual => actual >= value,
         }
+    }
+}
 
-        let seperators: &[_] = &[',', ';']; // Coercion fail.
-        return self.remaining.find(seperators).map(|idx| {
-            let cmd = Command::parse(&self.remaining[0..idx]);
-            self.remaining = &self.remaining[idx+1..];
-            cmd
-        });
-
-        fn parse_repeat<'a>(remaining: &mut &'a str) -> Option<Result<Command<'a>, String>> {
-            let start = match remaining.find('{') {
-                Some(idx) => idx,
-                None => return Some(Err("Missing '{' after 'repeat'".to_string()))
-            };
-
-            let iterations = &remaining["repeat".len()..start].trim();
-            let iterations = match iterations.parse() {
-                Ok(i) => i,
-                Err(e) => return Some(Err(format!("Failed to parse iteration count \
-                                                   for 'repeat': {} - '{}'",
-                                                   e, iterations)))
-            };
-
-            // FIXME: This won't handle nested repeat commands
-            let end = match remaining.find('}') {
-                Some(idx) => idx,
-                None => return Some(Err("Missing '}' after 'repeat'".to_string()))
-            };
-
-            let mut commands = vec![];
-            for command in Commands::new(&remaining[start+1..end]) {
-                match command {
-                    Ok(cmd) => commands.push(cmd),
-                    Err(e) => return Some(Err(e))
-                }
-            }
+/// Parse a decimal value, or a `%B` binary, `%X` hex or `%D` decimal literal
+pub fn parse_value(s: &str) -> Result<Word, String> {
+    let value = if s.starts_with("%B") {
+        i32::from_str_radix(&s[2..], 2)
+    } else if s.starts_with("%X") {
+        i32::from_str_radix(&s[2..], 16)
+    } else if s.starts_with("%D") {
+        s[2..].parse()
+    } else {
+        s.parse()
+    };
+
+    match value {
+        Ok(v) if v >= i16::min_value() as i32 && v <= Word::max_value() as i32 => Ok(v as Word),
+        Ok(..) => Err(format!("Value out of range: '{}'", s)),
+        Err(e) => Err(format!("Invalid value '{}': {}", s, e)),
+    }
+}
 
-            *remaining = &remaining[end+1..];
-            Some(Ok(Repeat(iterations, commands)))
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_value("-1"), Ok(0xFFFF));
+        assert_eq!(parse_value("%D32767"), Ok(0x7FFF));
+        assert_eq!(parse_value("%B1000000000000001"), Ok(0x8001));
+        assert_eq!(parse_value("%XBEEF"), Ok(0xBEEF));
+        assert_eq!(parse_value("65535"), Ok(0xFFFF));
+        assert!(parse_value("65536").is_err());
+        assert!(parse_value("%B102").is_err());
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(Var::parse("A"), Ok(Var::A));
+        assert_eq!(Var::parse("RAM[24576]"), Ok(Var::Ram(24576)));
+        assert!(Var::parse("RAM[24577]").is_err());
+        assert!(Var::parse("RAM[3").is_err());
+        assert!(Var::parse("M").is_err());
+
+        assert_eq!(Var::parse("sp"), Ok(Var::Sp));
+        assert_eq!(Var::parse("argument"), Ok(Var::Base(Segment::Argument)));
+        assert_eq!(Var::parse("that[5]"), Ok(Var::Entry(Segment::That, 5)));
+        assert_eq!(Var::parse("temp[7]").map(|v| v.to_string()), Ok("temp[7]".to_string()));
+        assert!(Var::parse("temp[8]").is_err());
+        assert!(Var::parse("static[0]").is_err());
+
+        assert_eq!(Var::parse_pin("out"), Ok(Var::Pin("out".to_string())));
+        assert_eq!(Var::parse_pin("out[3]"), Ok(Var::Pin("out[3]".to_string())));
+        assert_eq!(Var::parse_pin("DRegister[]"), Ok(Var::Pin("DRegister[]".to_string())));
+        assert_eq!(Var::parse_pin("time"), Ok(Var::Time));
+        assert!(Var::parse_pin("out[x]").is_err());
+        assert!(Var::parse_pin("out[3").is_err());
+        assert!(Var::parse_pin("3out").is_err());
+    }
+
+    #[test]
+    fn conditions() {
+        let condition = Condition { var: Var::Ram(0), comparison: Comparison::NotEqual, value: 0 };
+        assert!(condition.holds(3));
+        assert!(!condition.holds(0));
+
+        // Comparisons are signed
+        let condition = Condition { var: Var::D, comparison: Comparison::Less, value: 0 };
+        assert!(condition.holds(0xFFFF));
+        assert_eq!(Comparison::parse(">="), Ok(Comparison::GreaterOrEqual));
+        assert!(Comparison::parse("!=").is_err());
     }
 }
@@ -0,0 +1,200 @@
+use std::fmt;
+
+use memory::Word;
+use super::format::{Column, Kind};
+
+// Lines of the output shown before the failing one in a table diff
+const DIFF_CONTEXT: usize = 5;
+
+const RED: &'static str = "\x1b[31m";
+const GREEN: &'static str = "\x1b[32m";
+const BOLD: &'static str = "\x1b[1m";
+const RESET: &'static str = "\x1b[0m";
+
+/// A cell of the output that differs from the comparison file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// The header of the cell's column
+    pub column: String,
+    pub expected: String,
+    pub actual: String,
+    /// The cells decoded as numbers, where their format allows it
+    pub expected_value: Option<Word>,
+    pub actual_value: Option<Word>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: expected {}, got {}",
+               self.column,
+               describe(&self.expected, self.expected_value),
+               describe(&self.actual, self.actual_value))
+    }
+}
+
+fn describe(cell: &str, value: Option<Word>) -> String {
+    match value {
+        Some(value) => format!("{} (hex {:04X}, binary {:016b})", value as i16, value, value),
+        None => format!("'{}'", cell),
+    }
+}
+
+/// A line of the output that doesn't match the comparison file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparisonFailure {
+    /// The line of the output, counted from 1
+    pub line: usize,
+    /// The script command that wrote the line, and the script line it's on
+    pub command: String,
+    pub script_line: usize,
+    pub mismatches: Vec<Mismatch>,
+    /// The output up to and including the failing line
+    pub actual: Vec<String>,
+    /// The whole comparison file
+    pub expected: Vec<String>,
+}
+
+impl ComparisonFailure {
+    /// Compare a line of output with the comparison file's line. `columns`
+    /// are the output list the line was written with.
+    pub fn check(actual: &str, expected: &str, columns: &[Column], is_header: bool) -> Vec<Mismatch> {
+        let cells = actual.split('|').zip(expected.split('|'));
+        // Lines start with a '|', so cell 1 is the first column
+        cells.enumerate().filter(|&(_, (a, b))| a.trim() != b.trim()).map(|(i, (a, b))| {
+            let column = i.checked_sub(1).and_then(|i| columns.get(i));
+            let kind = if is_header { None } else { column.map(|c| c.format.kind) };
+            Mismatch {
+                column: column.map_or(format!("column {}", i), |c| c.name.clone()),
+                expected: b.trim().to_string(),
+                actual: a.trim().to_string(),
+                expected_value: kind.and_then(|k| decode(b.trim(), k)),
+                actual_value: kind.and_then(|k| decode(a.trim(), k)),
+            }
+        }).collect()
+    }
+
+    /// The failing line and the lines before it, with the output on the
+    /// left and the comparison file on the right. Differing cells are
+    /// highlighted with terminal colors when `color` is set.
+    pub fn table_diff(&self, color: bool) -> String {
+        let first = self.line.saturating_sub(DIFF_CONTEXT + 1);
+        let rows = first..self.line.min(self.actual.len());
+        let width = rows.clone().map(|i| self.actual[i].chars().count()).max().unwrap_or(0).max(6);
+
+        let mut diff = format!("       {:<width$}   {}\n", "Output", "Comparison", width = width);
+        for i in rows {
+            let actual = &self.actual[i];
+            let expected = self.expected.get(i).map_or("", |l| &l[..]);
+            let fill = width - actual.chars().count();
+            let marker = if i + 1 == self.line { ">" } else { " " };
+
+            let (actual, expected) = if color {
+                (highlight(actual, expected, RED), highlight(expected, actual, GREEN))
+            } else {
+                (actual.to_string(), expected.to_string())
+            };
+            diff.push_str(&format!("{} {:4}  {}{}   {}\n", marker, i + 1, actual,
+                                   (0..fill).map(|_| ' ').collect::<String>(), expected));
+        }
+        diff
+    }
+}
+
+impl fmt::Display for ComparisonFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "Comparison failed at line {}, written by '{}' on line {} of the script",
+                      self.line, self.command, self.script_line));
+        for mismatch in &self.mismatches {
+            try!(writeln!(f, "  {}", mismatch));
+        }
+        let expected = self.expected.get(self.line - 1).map_or("", |l| &l[..]);
+        write!(f, "Got: '{}'\nExpected: '{}'", self.actual[self.line - 1], expected)
+    }
+}
+
+// A cell's value in the format of its column. Strings are decimal numbers
+// unless they're something else, like the time.
+fn decode(cell: &str, kind: Kind) -> Option<Word> {
+    let value = match kind {
+        Kind::Binary => i32::from_str_radix(cell, 2).ok(),
+        Kind::Hex => i32::from_str_radix(cell, 16).ok(),
+        Kind::Decimal | Kind::String => cell.parse().ok(),
+    };
+    value.and_then(|v| {
+        if v >= i16::min_value() as i32 && v <= Word::max_value() as i32 { Some(v as Word) } else { None }
+    })
+}
+
+// Color the cells of `line` that differ from `other`
+fn highlight(line: &str, other: &str, color: &str) -> String {
+    let mut others = other.split('|');
+    let cells: Vec<String> = line.split('|').map(|cell| match others.next() {
+        Some(o) if o.trim() == cell.trim() => cell.to_string(),
+        _ if cell.is_empty() => String::new(),
+        _ => format!("{}{}{}{}", BOLD, color, cell, RESET),
+    }).collect();
+    cells.join("|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runner::format::Column;
+
+    fn columns() -> Vec<Column> {
+        let mut columns = Column::parse_item("RAM[0]%D1.6.1").unwrap();
+        columns.extend(Column::parse_item("RAM[1]%X1.4.1").unwrap());
+        columns
+    }
+
+    #[test]
+    fn mismatching_columns() {
+        let mismatches = ComparisonFailure::check("|     -1 | 000A |", "|      5 | 000A |", &columns(), false);
+        assert_eq!(mismatches, vec![Mismatch {
+            column: "RAM[0]".to_string(),
+            expected: "5".to_string(),
+            actual: "-1".to_string(),
+            expected_value: Some(5),
+            actual_value: Some(0xFFFF),
+        }]);
+        assert_eq!(mismatches[0].to_string(),
+                   "RAM[0]: expected 5 (hex 0005, binary 0000000000000101), \
+                    got -1 (hex FFFF, binary 1111111111111111)");
+
+        let mismatches = ComparisonFailure::check("|     -1 | 000B |", "|     -1 | 000A |", &columns(), false);
+        assert_eq!(mismatches[0].column, "RAM[1]");
+        assert_eq!(mismatches[0].actual_value, Some(11));
+
+        // Header cells aren't numbers, whatever their column's format
+        let mismatches = ComparisonFailure::check("|  RAM[0]  |", "|   RAM[9] |", &columns(), true);
+        assert_eq!(mismatches[0].expected_value, None);
+        assert_eq!(mismatches[0].to_string(), "RAM[0]: expected 'RAM[9]', got 'RAM[0]'");
+    }
+
+    #[test]
+    fn report() {
+        let actual = vec!["|  RAM[0] |".to_string(), "|      1 |".to_string(), "|      3 |".to_string()];
+        let expected = vec!["|  RAM[0] |".to_string(), "|      1 |".to_string(), "|      2 |".to_string()];
+        let mismatches = ComparisonFailure::check(&actual[2], &expected[2], &columns(), false);
+        let failure = ComparisonFailure {
+            line: 3,
+            command: "output".to_string(),
+            script_line: 9,
+            mismatches: mismatches,
+            actual: actual,
+            expected: expected,
+        };
+
+        assert_eq!(failure.to_string(),
+                   "Comparison failed at line 3, written by 'output' on line 9 of the script\n  \
+                    RAM[0]: expected 2 (hex 0002, binary 0000000000000010), \
+                    got 3 (hex 0003, binary 0000000000000011)\n\
+                    Got: '|      3 |'\nExpected: '|      2 |'");
+        assert_eq!(failure.table_diff(false), ["       Output        Comparison",
+                                               "     1  |  RAM[0] |   |  RAM[0] |",
+                                               "     2  |      1 |    |      1 |",
+                                               ">    3  |      3 |    |      2 |",
+                                               ""].join("\n"));
+        assert!(failure.table_diff(true).contains("|\x1b[1m\x1b[31m      3 \x1b[0m|"));
+    }
+}
@@ -0,0 +1,217 @@
+use memory::{Word, RAM_SIZE};
+use super::commands::Var;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Binary,
+    Decimal,
+    Hex,
+    String,
+}
+
+/// How a value is laid out in an output column, e.g. `%D2.6.2`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Format {
+    pub kind: Kind,
+    pub pad_left: usize,
+    pub len: usize,
+    pub pad_right: usize,
+}
+
+impl Format {
+    /// Parse a format without its leading '%'
+    pub fn parse(s: &str) -> Result<Format, String> {
+        let mut chars = s.chars();
+        let kind = match chars.next() {
+            Some('B') => Kind::Binary,
+            Some('D') => Kind::Decimal,
+            Some('X') => Kind::Hex,
+            Some('S') => Kind::String,
+            Some(c) => return Err(format!("Unknown format: '{}'", c)),
+            None => return Err("Missing format after '%'".to_string()),
+        };
+
+        let mut parts = chars.as_str().split('.');
+        let mut width = |what: &str| -> Result<usize, String> {
+            let part = expect!(parts, what);
+            part.parse().map_err(|e| format!("Invalid {} '{}' in format '%{}': {}", what, part, s, e))
+        };
+        let pad_left = try!(width("left padding"));
+        let len = try!(width("length"));
+        let pad_right = try!(width("right padding"));
+        if let Some(extra) = parts.next() {
+            return Err(format!("Unexpected '.{}' in format '%{}'", extra, s))
+        }
+
+        Ok(Format { kind: kind, pad_left: pad_left, len: len, pad_right: pad_right })
+    }
+
+    /// The format used when an output-list item doesn't give one
+    pub fn default_for(var: &Var) -> Format {
+        match *var {
+            Var::Time => Format { kind: Kind::String, pad_left: 1, len: 4, pad_right: 1 },
+            _ => Format { kind: Kind::Decimal, pad_left: 1, len: 6, pad_right: 1 },
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.pad_left + self.len + self.pad_right
+    }
+
+    /// Render a number in this format, before padding
+    pub fn value(&self, value: Word) -> String {
+        match self.kind {
+            Kind::Binary => format!("{:016b}", value),
+            Kind::Hex => format!("{:04X}", value),
+            Kind::Decimal | Kind::String => format!("{}", value as i16),
+        }
+    }
+
+    /// Pad `value` to fill a column. Numbers are right aligned and strings left
+    /// aligned. Values longer than the format's length keep their rightmost
+    /// characters.
+    pub fn cell(&self, value: &str) -> String {
+        let chars: Vec<char> = value.chars().collect();
+        let value: String = chars[chars.len().saturating_sub(self.len)..].iter().cloned().collect();
+        let fill = self.len - value.chars().count();
+
+        let (left, right) = match self.kind {
+            Kind::String => (self.pad_left, self.pad_right + fill),
+            _ => (self.pad_left + fill, self.pad_right),
+        };
+        format!("{}{}{}", spaces(left), value, spaces(right))
+    }
+}
+
+/// A column of the output file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub var: Var,
+    pub format: Format,
+}
+
+impl Column {
+    /// Parse an output-list item, `<var>[%<format>]`. A RAM range like
+    /// `RAM[0..3]` gives a column for each address.
+    pub fn parse_item(item: &str) -> Result<Vec<Column>, String> {
+        Column::parse_with(item, Var::parse)
+    }
+
+    /// Parse an output-list item with variables parsed by `parse`, like
+    /// `Var::parse_pin` for the pins of a chip
+    pub fn parse_with(item: &str, parse: fn(&str) -> Result<Var, String>) -> Result<Vec<Column>, String> {
+        let mut halves = item.splitn(2, '%');
+        let name = halves.next().unwrap();
+        let format = match halves.next() {
+            Some(format) => Some(try!(Format::parse(format))),
+            None => None,
+        };
+
+        let vars = match range(name) {
+            Some(range) => try!(range),
+            None => vec![(name.to_string(), try!(parse(name)))],
+        };
+
+        Ok(vars.into_iter().map(|(name, var)| Column {
+            name: name,
+            format: format.unwrap_or_else(|| Format::default_for(&var)),
+            var: var,
+        }).collect())
+    }
+
+    /// The column's name centered in its width, cut to fit
+    pub fn header(&self) -> String {
+        let width = self.format.width();
+        let name: String = self.name.chars().take(width).collect();
+        let left = (width - name.chars().count()) / 2;
+        let right = width - left - name.chars().count();
+        format!("{}{}{}", spaces(left), name, spaces(right))
+    }
+}
+
+// The addresses of `RAM[first..last]`, or None if `name` isn't a range
+fn range(name: &str) -> Option<Result<Vec<(String, Var)>, String>> {
+    if !(name.starts_with("RAM[") || name.starts_with("ram[")) || !name.contains("..") {
+        return None
+    }
+
+    let bounds = name[4..].trim_right_matches(']');
+    let mut bounds = bounds.splitn(2, "..").map(|b| b.parse::<usize>());
+    let range = match (bounds.next(), bounds.next()) {
+        (Some(Ok(first)), Some(Ok(last))) if first <= last && last < RAM_SIZE && name.ends_with(']') => {
+            Ok((first..last + 1).map(|i| (format!("{}[{}]", &name[..3], i), Var::Ram(i))).collect())
+        }
+        _ => Err(format!("Invalid RAM range: '{}'", name)),
+    };
+    Some(range)
+}
+
+fn spaces(n: usize) -> String {
+    (0..n).map(|_| ' ').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runner::commands::Var;
+
+    fn column(item: &str) -> Column {
+        let mut columns = Column::parse_item(item).unwrap();
+        assert_eq!(columns.len(), 1);
+        columns.remove(0)
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(Format::parse("D2.6.2"),
+                   Ok(Format { kind: Kind::Decimal, pad_left: 2, len: 6, pad_right: 2 }));
+        assert_eq!(Format::parse("S0.4.0").map(|f| f.kind), Ok(Kind::String));
+        assert!(Format::parse("Q1.2.3").is_err());
+        assert!(Format::parse("D1.6").is_err());
+        assert!(Format::parse("D1.6.1.1").is_err());
+    }
+
+    #[test]
+    fn headers_are_centered() {
+        assert_eq!(column("RAM[0]%D2.6.2").header(), "  RAM[0]  ");
+        assert_eq!(column("RAM[11]%D1.6.1").header(), "RAM[11] ");
+        assert_eq!(column("RAM[256]%D2.6.3").header(), " RAM[256]  ");
+        assert_eq!(column("RAM[402]%B1.4.1").header(), "RAM[40");
+        assert_eq!(column("time%S1.4.1").header(), " time ");
+        assert_eq!(column("A").header(), "   A    ");
+    }
+
+    #[test]
+    fn cells() {
+        let cell = |item: &str, value: Word| {
+            let column = column(item);
+            column.format.cell(&column.format.value(value))
+        };
+
+        assert_eq!(cell("RAM[0]%D2.6.2", 472), "     472  ");
+        assert_eq!(cell("RAM[0]%D1.6.1", 0xFFFF), "     -1 ");
+        assert_eq!(cell("RAM[0]%B1.16.1", 5), " 0000000000000101 ");
+        assert_eq!(cell("RAM[0]%B1.4.1", 22), " 0110 ");
+        assert_eq!(cell("RAM[0]%X1.4.1", 0xBEEF), " BEEF ");
+        assert_eq!(cell("RAM[0]%X2.2.0", 0x1234), "  34");
+        assert_eq!(cell("RAM[0]%S1.6.1", 42), " 42     ");
+        assert_eq!(cell("PC", 7), "      7 ");
+        assert_eq!(column("time").format.cell("12+"), " 12+  ");
+    }
+
+    #[test]
+    fn ranges() {
+        let columns = Column::parse_item("RAM[3..5]%D1.2.1").unwrap();
+        let names: Vec<_> = columns.iter().map(|c| &c.name[..]).collect();
+        let vars: Vec<_> = columns.iter().map(|c| c.var.clone()).collect();
+
+        assert_eq!(names, ["RAM[3]", "RAM[4]", "RAM[5]"]);
+        assert_eq!(vars, [Var::Ram(3), Var::Ram(4), Var::Ram(5)]);
+        assert_eq!(columns[2].format.len, 2);
+
+        assert!(Column::parse_item("RAM[5..3]").is_err());
+        assert!(Column::parse_item("RAM[0..24577]").is_err());
+        assert!(Column::parse_item("RAM[0..x]").is_err());
+    }
+}
@@ -2,50 +2,136 @@ use std::str::FromStr;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::fs::File;
-use {Rom, Cpu};
-use hack_assembler::assemble;
-
-use self::commands::{Command, Commands};
-#[macro_use] mod macros;
+use std::mem;
+use std::time::Instant;
+use {Rom, Cpu, Screen};
+use limits::{Limits, Watchdog};
+
+use memory::Word;
+use self::commands::{Command, Var};
+use self::format::{Column, Kind};
+use self::parser::{parse, Location};
+use self::target::Target;
+
+pub use self::compare::{ComparisonFailure, Mismatch};
+pub use self::batch::{find_scripts, run_tests, junit_report, json_report};
+pub use self::report::{TestFailure, TestRun};
+
+mod batch;
 mod commands;
+mod compare;
+mod format;
+mod parser;
+mod report;
+mod target;
 
+/// Run a test script, failing with a message if it doesn't pass
 pub fn runner<P: AsRef<Path>>(base: P) -> Result<(), String> {
-    let tst = file_to_string!(base.as_ref());
-    let commands = Commands::new(&tst);
-    let program = try_s!(Rom::from_str(""));
+    let run = run_test(base);
+    match run.failure {
+        None => Ok(()),
+        Some(failure) => Err(format!("Failure running '{}':\n{}", run.script.to_string_lossy(), failure)),
+    }
+}
+
+/// Run a test script and report how it went
+pub fn run_test<P: AsRef<Path>>(path: P) -> TestRun {
+    run_test_with_limits(path, &Limits::default())
+}
+
+/// Run a test script, failing it if it goes beyond `limits`
+pub fn run_test_with_limits<P: AsRef<Path>>(path: P, limits: &Limits) -> TestRun {
+    let started = Instant::now();
+    let mut run = TestRun::new(path.as_ref().to_path_buf());
+    if let Err(failure) = run_script(path.as_ref(), limits, &mut run) {
+        run.failure = Some(failure);
+    }
+    run.duration = started.elapsed();
+    run
+}
+
+fn run_script(path: &Path, limits: &Limits, run: &mut TestRun) -> Result<(), TestFailure> {
+    let tst = try!(read_script(path).map_err(TestFailure::Error));
+    let commands = try!(parse(&tst).map_err(|e| {
+        TestFailure::Error(format!("Failed to parse '{}': {}", path.to_string_lossy(), e))
+    }));
+    let program = try!(Rom::from_str("").map_err(|e| TestFailure::Error(format!("{:?}", e))));
 
     let mut runner = Runner {
-        base_path: base.as_ref(),
-        cpu: Cpu::new(program),
+        base_path: path,
+        target: Box::new(Cpu::new(program)),
         output_path: None,
         comparison: String::new(),
-        formats: vec![],
+        lists: vec![],
         output: String::new(),
+        written: vec![],
+        compared: 0,
+        failure: None,
+        time: 0,
+        ticked: false,
+        cycles: 0,
+        watchdog: Watchdog::new(*limits),
+        breakpoints: vec![],
+        messages: vec![],
     };
 
-    for cmd in commands {
-        let cmd = try!(cmd);
-        match runner.step(&cmd) {
-            Ok(..) => {},
-            Err(e) => {
-                let _ = runner.flush_output();
-                return Err(format!("Failure running '{}':\n{}",
-                                    base.as_ref().to_string_lossy(),
-                                    e))
-            }
+    let mut result = Ok(());
+    for cmd in &commands {
+        result = runner.step(cmd);
+        if result.is_err() {
+            break
         }
     }
 
-    runner.flush_output()
+    // The output is kept even when the test fails
+    let flushed = runner.flush_output();
+    run.outputs = runner.compared;
+    run.cycles = runner.cycles;
+    run.output = mem::replace(&mut runner.output, String::new());
+    run.messages = mem::replace(&mut runner.messages, vec![]);
+
+    match result {
+        Err(e) => Err(runner.failure.take().unwrap_or(TestFailure::Error(e))),
+        Ok(()) => flushed.map_err(TestFailure::Error),
+    }
+}
+
+fn read_script(path: &Path) -> Result<String, String> {
+    Ok(file_to_string!(path))
 }
 
 struct Runner<'a> {
     base_path: &'a Path,
-    cpu: Cpu,
+    target: Box<Target>,
     output_path: Option<PathBuf>,
     comparison: String,
-    formats: Vec<&'a str>,
-    output: String
+    // Every output list so far, the last one is in use
+    lists: Vec<Vec<Column>>,
+    output: String,
+    // What wrote each line of the output
+    written: Vec<Written>,
+    // Lines of the output already checked against the comparison file
+    compared: usize,
+    // Details of a failure reported as an error message
+    failure: Option<TestFailure>,
+    // Completed clock cycles
+    time: u64,
+    // Set between a tick and its tock
+    ticked: bool,
+    // Clock cycles since the script started
+    cycles: u64,
+    watchdog: Watchdog,
+    breakpoints: Vec<(Var, Word)>,
+    // Echoed text and breakpoint hits, for whoever runs the script to show
+    messages: Vec<String>,
+}
+
+struct Written {
+    command: &'static str,
+    location: Location,
+    // Index of the output list used
+    list: usize,
+    is_header: bool,
 }
 
 impl<'a> Runner<'a> {
@@ -54,10 +140,14 @@ impl<'a> Runner<'a> {
 
         match *cmd {
             Repeat(times, ref commands) => {
-                for _ in 0..times {
+                // Only the limits end a repeat without a count
+                let mut done = 0;
+                while times.map_or(true, |times| done < times) {
                     for c in commands {
                         try!(self.step(c))
                     }
+                    try!(self.check_time());
+                    done += 1;
                 }
                 Ok(())
             }
@@ -65,12 +155,82 @@ impl<'a> Runner<'a> {
                 self.output_path = Some(self.base_path.with_file_name(&filename));
                 Ok(())
             }
-            OutputList(ref formats) => self.set_formatting(formats),
-            TickTock => { self.cpu.step(); Ok(()) },
-            Output => self.check_output_line(),
-            Load(ref filename) => self.load_program(filename),
+            While(ref condition, ref commands) => {
+                while condition.holds(try!(self.get(&condition.var))) {
+                    for c in commands {
+                        try!(self.step(c))
+                    }
+                    try!(self.check_time());
+                }
+                Ok(())
+            }
+            OutputList(ref columns, location) => self.set_formatting(columns, location),
+            Tick => self.tick(),
+            Tock | TickTock | VmStep => {
+                try!(self.tick());
+                self.tock()
+            }
+            Eval => self.target.eval(),
+            Output(location) => self.check_output_line(location),
+            Load(filename) => self.load_program(filename),
             CompareTo(ref filename) => self.compare_with(filename),
-            Set(ref dest, val) => self.set(dest, val),
+            CompareScreen(ref filename, tolerance) => self.compare_screen(filename, tolerance),
+            Set(ref var, val) => self.set(var, val),
+            Echo(ref text) => { self.messages.push(text.to_string()); Ok(()) },
+            // Echoed text is only recorded, there's no status line to clear
+            ClearEcho => Ok(()),
+            Breakpoint(ref var, val) => { self.breakpoints.push((var.clone(), val)); Ok(()) },
+            ClearBreakpoints => { self.breakpoints.clear(); Ok(()) },
+        }
+    }
+
+    // The CPU state only changes on the falling edge of the clock, but chips
+    // latch their inputs on the rising edge
+    fn tick(&mut self) -> Result<(), String> {
+        if !self.ticked {
+            try!(self.target.tick());
+            self.ticked = true;
+        }
+        Ok(())
+    }
+
+    fn tock(&mut self) -> Result<(), String> {
+        try!(self.target.step());
+        self.time += 1;
+        self.ticked = false;
+        self.cycles += 1;
+
+        // Nobody can resume a paused script here, so just record the hit
+        for &(ref var, val) in &self.breakpoints {
+            if try!(self.get(var)) == val {
+                self.messages.push(format!("Breakpoint reached: {} = {} at time {}", var, val as i16, self.time));
+            }
+        }
+
+        match self.watchdog.cycle(self.target.pc(), self.cycles) {
+            Ok(()) => Ok(()),
+            Err(exceeded) => self.fail(TestFailure::Limit(exceeded)),
+        }
+    }
+
+    // Loops that don't run the clock can still run out of time
+    fn check_time(&mut self) -> Result<(), String> {
+        match self.watchdog.check_time(self.target.pc(), self.cycles) {
+            Ok(()) => Ok(()),
+            Err(exceeded) => self.fail(TestFailure::Limit(exceeded)),
+        }
+    }
+
+    fn fail(&mut self, failure: TestFailure) -> Result<(), String> {
+        let message = failure.to_string();
+        self.failure = Some(failure);
+        Err(message)
+    }
+
+    fn get(&self, var: &Var) -> Result<Word, String> {
+        match *var {
+            Var::Time => Ok(self.time as Word),
+            _ => self.target.get(var),
         }
     }
 
@@ -80,39 +240,45 @@ impl<'a> Runner<'a> {
         Ok(())
     }
 
-    fn load_program(&mut self, filename: &str) -> Result<(), String> {
-        let path = self.base_path.with_file_name(&filename);
-        let rom = if filename.ends_with(".asm") {
-            let assembly = file_to_string!(&path);
-            let program = try!(assemble(&assembly));
-            Rom::from_str(&program)
-        } else if filename.ends_with(".hack") {
-            Rom::from_file(&path)
-        } else {
-            return Err(format!("Unsupported file type: {}", filename))
-        };
-
-        self.cpu = Cpu::new(try_s!(rom));
+    fn compare_screen(&mut self, filename: &str, tolerance: usize) -> Result<(), String> {
+        let path = self.base_path.with_file_name(filename);
+        let screen = try!(self.target.screen().ok_or("Only the CPU and VM emulators have a screen to compare"));
+        let expected = try!(Screen::from_file(&path));
+        let diff = screen.diff(&expected);
+        debug!("{} pixels differ from '{}'", diff.differing, filename);
+
+        if diff.differing > tolerance {
+            let diff_path = path.with_extension("diff.png");
+            try!(diff.save(&diff_path));
+            return Err(format!("Screen comparison with '{}' failed: {} pixels differ \
+                                (tolerance: {})\nDiff written to '{}'",
+                                filename, diff.differing, tolerance,
+                                diff_path.to_string_lossy()))
+        }
         Ok(())
     }
 
-    fn set(&mut self, dest: &str, val: i16) -> Result<(), String> {
-        let mut parts = dest.split(|c| c == ' ' || c == '[' || c == ']');
-
-        match expect!(parts, "destination for set") {
-            "PC" | "pc" => self.cpu.pc = val as u16,
-            "RAM" | "ram" => {
-                let index = expect!(parts, "index for set RAM[?]");
-                let index : usize = try_s!(index.parse());
+    fn load_program(&mut self, filename: Option<&str>) -> Result<(), String> {
+        let directory = match self.base_path.parent() {
+            Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let path = directory.join(filename.unwrap_or(""));
 
-                self.cpu.ram[index] = val as u16;
-            }
-            dest => return Err(format!("Unhandled set destination: {}", dest))
+        if try!(target::load(&mut self.target, &path)) {
+            self.time = 0;
+            self.ticked = false;
         }
-
         Ok(())
     }
 
+    fn set(&mut self, var: &Var, val: Word) -> Result<(), String> {
+        match *var {
+            Var::Time => Err("Cannot set 'time'".to_string()),
+            _ => self.target.set(var, val),
+        }
+    }
+
     fn flush_output(&mut self) -> Result<(), String> {
         let path = try!(self.output_path.as_ref()
                             .ok_or(format!("No output file specified")));
@@ -121,125 +287,73 @@ impl<'a> Runner<'a> {
         Ok(())
     }
 
-    fn check_output_line(&mut self) -> Result<(), String> {
-        try!(self.write_output_line(false));
+    fn check_output_line(&mut self, location: Location) -> Result<(), String> {
+        try!(self.write_output_line(false, "output", location));
+
+        let failure = {
+            let expected: Vec<&str> = self.comparison.lines().collect();
+            let actual: Vec<&str> = self.output.lines().collect();
+            // Output beyond the end of the comparison file isn't checked
+            let end = actual.len().min(expected.len());
+
+            let failure = (self.compared..end).filter_map(|i| {
+                let written = &self.written[i];
+                let columns = self.lists.get(written.list).map_or(&[][..], |l| &l[..]);
+                let mismatches = ComparisonFailure::check(actual[i], expected[i], columns, written.is_header);
+                if mismatches.is_empty() {
+                    return None
+                }
 
-        let expected = self.comparison.lines();
-        let actual = self.output.lines();
+                Some(ComparisonFailure {
+                    line: i + 1,
+                    command: written.command.to_string(),
+                    script_line: written.location.line,
+                    mismatches: mismatches,
+                    actual: actual[..i + 1].iter().map(|l| l.to_string()).collect(),
+                    expected: expected.iter().map(|l| l.to_string()).collect(),
+                })
+            }).next();
+
+            self.compared = self.compared.max(end);
+            failure
+        };
 
-        for (lineno, (line_a, line_b)) in actual.zip(expected).enumerate() {
-            // Only care about mismatches if the actual cell content is different
-            for (a, b) in line_a.split('|').zip(line_b.split('|')) {
-                if a.trim() != b.trim() {
-                    return Err(format!("Comparison failed at line: {}\n\
-                                        Got: '{}'\nExpected: '{}'",
-                                        lineno, line_a, line_b))
-                }
-            }
+        match failure {
+            Some(failure) => self.fail(TestFailure::Comparison(failure)),
+            None => Ok(())
         }
-        Ok(())
     }
 
-    fn set_formatting(&mut self, formats: &[&'a str]) -> Result<(), String> {
-        self.formats = formats.to_vec();
-        self.write_output_line(true)
+    fn set_formatting(&mut self, columns: &[Column], location: Location) -> Result<(), String> {
+        self.lists.push(columns.to_vec());
+        self.write_output_line(true, "output-list", location)
     }
 
-    fn write_output_line(&mut self, is_header: bool) -> Result<(), String> {
-        // TODO: Default formatting as specified in appendix if none given
-        for format in &self.formats {
-            let parts = &mut format.split(|c| c == '%' || c == '.' ||
-                                              c == '[' || c == ']');
-
-            let val = try!(get_val(parts, &self.cpu));
-
-            let left_fmt = expect!(parts, "type for format");
-            debug!("fmt {:?}, left_fmt {:?}", format, left_fmt);
-
-            let mut val = if is_header {
-                try!(header_name(format))
+    fn write_output_line(&mut self, is_header: bool, command: &'static str,
+                         location: Location) -> Result<(), String> {
+        let list = self.lists.len().saturating_sub(1);
+        self.written.push(Written { command: command, location: location, list: list, is_header: is_header });
+
+        for column in self.lists.last().map_or(&[][..], |l| &l[..]) {
+            let cell = if is_header {
+                column.header()
+            } else if column.var == Var::Time && column.format.kind == Kind::String {
+                // Halfway through a cycle, after a tick, time shows as 'n+'
+                let time = format!("{}{}", self.time, if self.ticked { "+" } else { "" });
+                column.format.cell(&time)
             } else {
-                try!(format_val(&left_fmt[0..1], val as i16))
+                column.format.cell(&column.format.value(try!(self.get(&column.var))))
             };
 
-            let lpad: isize = try_s!(left_fmt[1..].parse());
-            let len: isize = try_s!(expect!(parts, "length for format").parse());
-            let rpad = try_s!(expect!(parts, "right padding for format").parse());
-            let max_len = lpad + len + rpad;
-            let val_len = val.len() as isize;
-
-            debug!("lpad: {}, val_len: {}, len: {}, rpad: {}",
-                    lpad, val_len, len, rpad);
-
-            let padding = |l| (0..l).map(|_| ' ');
             self.output.push('|');
-
-            // The JVM version first uses the left pad
-            // then the right pad and then truncates silently.
-            let lpad = lpad + (len - val_len);
-            if lpad > 0 {
-                self.output.extend(padding(lpad))
-            }
-
-            let rpad = if val_len > max_len {
-                if is_header {
-                    // Silent truncation for headers \o/
-                    val.truncate(max_len as usize);
-                    0
-                } else {
-                    return Err(format!("value '{}' could not fit in the specified\
-                                        formatting: '{}", val, format))
-                }
-            } else if val_len + rpad > max_len {
-                max_len - val_len
-            } else {
-                rpad
-            };
-
-            self.output.push_str(&val);
-            self.output.extend(padding(rpad));
+            self.output.push_str(&cell);
         }
 
         self.output.push_str("|\n");
-
-        return Ok(());
-
-        fn get_val<'a, I: Iterator<Item=&'a str>>(parts: &mut I, cpu: &Cpu) -> Result<u16, String> {
-            let val = match expect!(parts, "destination for format") {
-                "PC" | "pc" => cpu.pc,
-                "RAM" | "ram" => {
-                    let index = expect!(parts, "index for set RAM[?]");
-                    let index : usize = try_s!(index.parse());
-
-                    // discard the space between RAM[..] and '%' from splits
-                    assert_eq!(expect!(parts, "skipping blank space"), "");
-                    cpu.ram[index]
-                }
-                dest => return Err(format!("Unhandled set destination: {}", dest))
-            };
-
-            Ok(val)
-        }
-
-        fn header_name(format: &str) -> Result<String, String> {
-            Ok(expect!(format.split('%'),
-                        "destination for format header").to_string())
-        }
-
-        fn format_val(format: &str, val: i16) -> Result<String, String> {
-            let s = match format {
-                // String
-                "S" => unimplemented!(),
-                // Binary
-                "B" => format!("{:b}", val),
-                // Hex
-                "X" => format!("{:X}", val),
-                // Decimal
-                "D" => format!("{}", val),
-                fmt => return Err(format!("Unknown format: {}", fmt)),
-            };
-
-            Ok(s)
+        match self.watchdog.check_output(self.output.len(), self.target.pc(), self.cycles) {
+            Ok(()) => Ok(()),
+            Err(exceeded) => self.fail(TestFailure::Limit(exceeded)),
         }
     }
 }
+
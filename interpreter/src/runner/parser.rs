@@ -0,0 +1,489 @@
+use std::fmt;
+
+use super::commands::{Command, Comparison, Condition, Var, parse_value};
+use super::commands::Command::*;
+use super::format::Column;
+
+/// A position in a script, both counted from 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub location: Location,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.location.line, self.location.column, self.message)
+    }
+}
+
+fn error<T, S: Into<String>>(location: Location, message: S) -> Result<T, ParseError> {
+    Err(ParseError { location: location, message: message.into() })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Word,
+    /// A double quoted string, without its quotes
+    Text,
+    Comma,
+    Semicolon,
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    kind: Kind,
+    text: &'a str,
+    location: Location,
+}
+
+impl<'a> Token<'a> {
+    /// Attach the token's location to an error from parsing its text
+    fn check<T>(&self, result: Result<T, String>) -> Result<T, ParseError> {
+        result.map_err(|e| ParseError { location: self.location, message: e })
+    }
+
+    fn describe(&self) -> String {
+        match self.kind {
+            Kind::Text => format!("\"{}\"", self.text),
+            _ => format!("'{}'", self.text),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    script: &'a str,
+    offset: usize,
+    location: Location,
+}
+
+impl<'a> Lexer<'a> {
+    fn rest(&self) -> &'a str {
+        &self.script[self.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if let Some(c) = c {
+            self.offset += c.len_utf8();
+            if c == '\n' {
+                self.location.line += 1;
+                self.location.column = 1;
+            } else {
+                self.location.column += 1;
+            }
+        }
+        c
+    }
+
+    fn at_word_end(&self) -> bool {
+        match self.peek() {
+            None => true,
+            Some(c) => c.is_whitespace() || ",;{}\"".contains(c) ||
+                       self.rest().starts_with("//") || self.rest().starts_with("/*")
+        }
+    }
+
+    fn tokenize(mut self) -> Result<Vec<Token<'a>>, ParseError> {
+        let mut tokens = vec![];
+
+        while let Some(c) = self.peek() {
+            let (start, location) = (self.offset, self.location);
+
+            if c.is_whitespace() {
+                self.bump();
+            } else if self.rest().starts_with("//") {
+                while self.peek().map_or(false, |c| c != '\n') {
+                    self.bump();
+                }
+            } else if self.rest().starts_with("/*") {
+                self.bump();
+                self.bump();
+                while !self.rest().starts_with("*/") {
+                    if self.bump().is_none() {
+                        return error(location, "Unterminated comment")
+                    }
+                }
+                self.bump();
+                self.bump();
+            } else if c == '"' {
+                self.bump();
+                while self.peek() != Some('"') {
+                    if self.bump().map_or(true, |c| c == '\n') {
+                        return error(location, "Unterminated string")
+                    }
+                }
+                self.bump();
+                let text = &self.script[start + 1..self.offset - 1];
+                tokens.push(Token { kind: Kind::Text, text: text, location: location });
+            } else {
+                let kind = match c {
+                    ',' => Kind::Comma,
+                    ';' => Kind::Semicolon,
+                    '{' => Kind::Open,
+                    '}' => Kind::Close,
+                    _ => Kind::Word,
+                };
+                self.bump();
+                if kind == Kind::Word {
+                    while !self.at_word_end() {
+                        self.bump();
+                    }
+                }
+                let text = &self.script[start..self.offset];
+                tokens.push(Token { kind: kind, text: text, location: location });
+            }
+        }
+
+        Ok(tokens)
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    next: usize,
+    // Where the script ends, for errors about missing tokens
+    end: Location,
+    // Set once the script loads a chip, after which variables are its pins
+    hardware: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.next).cloned()
+    }
+
+    fn advance(&mut self) -> Option<Token<'a>> {
+        let token = self.peek();
+        self.next += 1;
+        token
+    }
+
+    /// The next token, which must be of `kind`
+    fn expect(&mut self, kind: Kind, what: &str) -> Result<Token<'a>, ParseError> {
+        match self.advance() {
+            Some(ref token) if token.kind == kind => Ok(*token),
+            Some(token) => error(token.location, format!("Expected {}, found {}", what, token.describe())),
+            None => error(self.end, format!("Expected {}, found the end of the script", what)),
+        }
+    }
+
+    /// Commands up to the end of the script, or the closing brace of a block
+    /// opened at `open`
+    fn commands(&mut self, open: Option<Location>) -> Result<Vec<Command<'a>>, ParseError> {
+        let mut commands = vec![];
+
+        loop {
+            match (self.peek(), open) {
+                (None, None) => return Ok(commands),
+                (None, Some(open)) => return error(open, "Missing '}' to close this block"),
+                (Some(token), None) if token.kind == Kind::Close => {
+                    return error(token.location, "Unexpected '}'")
+                }
+                (Some(token), Some(_)) if token.kind == Kind::Close => {
+                    self.advance();
+                    return Ok(commands)
+                }
+                _ => commands.push(try!(self.command())),
+            }
+        }
+    }
+
+    fn command(&mut self) -> Result<Command<'a>, ParseError> {
+        let name = try!(self.expect(Kind::Word, "a command"));
+
+        match name.text {
+            "repeat" => {
+                // Without a count, the block repeats forever
+                let count = match self.peek() {
+                    Some(count) if count.kind == Kind::Word => {
+                        self.advance();
+                        Some(try!(count.check(count.text.parse().map_err(|e| {
+                            format!("Invalid iteration count for 'repeat': {} - '{}'", e, count.text)
+                        }))))
+                    }
+                    _ => None,
+                };
+                let body = try!(self.block("repeat"));
+                Ok(Repeat(count, body))
+            }
+            "while" => {
+                let var = try!(self.expect(Kind::Word, "a variable for 'while'"));
+                let comparison = try!(self.expect(Kind::Word, "a comparison for 'while'"));
+                let value = try!(self.expect(Kind::Word, "a value for 'while'"));
+
+                let condition = Condition {
+                    var: try!(var.check(variable(self.hardware)(var.text))),
+                    comparison: try!(comparison.check(Comparison::parse(comparison.text))),
+                    value: try!(value.check(parse_value(value.text))),
+                };
+                let body = try!(self.block("while"));
+                Ok(While(condition, body))
+            }
+            _ => {
+                let mut args = vec![];
+                loop {
+                    match self.peek() {
+                        Some(token) if token.kind == Kind::Word || token.kind == Kind::Text => {
+                            args.push(token);
+                            self.advance();
+                        }
+                        Some(token) if token.kind == Kind::Comma || token.kind == Kind::Semicolon => {
+                            self.advance();
+                            break
+                        }
+                        Some(token) => {
+                            return error(token.location, format!("Expected ',' or ';' after '{}', \
+                                                                  found {}", name.text, token.describe()))
+                        }
+                        None => return error(self.end, format!("Expected ',' or ';' after '{}'", name.text)),
+                    }
+                }
+                simple_command(name, &args, &mut self.hardware)
+            }
+        }
+    }
+
+    fn block(&mut self, keyword: &str) -> Result<Vec<Command<'a>>, ParseError> {
+        let open = try!(self.expect(Kind::Open, &format!("'{{' after '{}'", keyword)));
+        self.commands(Some(open.location))
+    }
+}
+
+// How variables are parsed, as pins in scripts testing chips
+fn variable(hardware: bool) -> fn(&str) -> Result<Var, String> {
+    if hardware { Var::parse_pin } else { Var::parse }
+}
+
+/// A command made of a name and its arguments, terminated by ',' or ';'.
+/// Loading a .hdl file sets `hardware`.
+fn simple_command<'a>(name: Token<'a>, args: &[Token<'a>], hardware: &mut bool) -> Result<Command<'a>, ParseError> {
+    if name.text == "load" && args.get(0).map_or(false, |file| file.text.ends_with(".hdl")) {
+        *hardware = true;
+    }
+    let parse = variable(*hardware);
+    let arg = |i: usize, what: &str| match args.get(i) {
+        Some(token) => Ok(*token),
+        None => error(name.location, format!("Missing {} for '{}'", what, name.text)),
+    };
+
+    let (cmd, used) = match name.text {
+        "load" => (Load(args.get(0).map(|token| token.text)), args.len().min(1)),
+        "ROM32K" => {
+            let load = try!(arg(0, "'load'"));
+            if load.text != "load" {
+                return error(load.location, format!("Unexpected ROM32K command: '{}'", load.text))
+            }
+            (Load(Some(try!(arg(1, "filename")).text)), 2)
+        }
+        "output-file" => (OutputFile(try!(arg(0, "filename")).text), 1),
+        "compare-to" => (CompareTo(try!(arg(0, "filename")).text), 1),
+        "compare-screen" => {
+            let filename = try!(arg(0, "filename")).text;
+            // Optionally followed by the number of pixels allowed to differ
+            let tolerance = match args.get(1) {
+                Some(token) => try!(token.check(token.text.parse().map_err(|e| {
+                    format!("Invalid tolerance '{}': {}", token.text, e)
+                }))),
+                None => 0
+            };
+            (CompareScreen(filename, tolerance), args.len().min(2))
+        }
+        "output-list" => {
+            let mut columns = vec![];
+            for token in args {
+                columns.extend(try!(token.check(Column::parse_with(token.text, parse))));
+            }
+            (OutputList(columns, name.location), args.len())
+        }
+        "set" => {
+            let location = try!(arg(0, "location"));
+            let value = try!(arg(1, "value"));
+            let var = try!(location.check(parse(location.text)));
+            if var == Var::Time {
+                return error(location.location, "Cannot set 'time'")
+            }
+            (Set(var, try!(value.check(parse_value(value.text)))), 2)
+        }
+        "breakpoint" => {
+            let var = try!(arg(0, "variable"));
+            let value = try!(arg(1, "value"));
+            (Breakpoint(try!(var.check(parse(var.text))),
+                        try!(value.check(parse_value(value.text)))), 2)
+        }
+        "echo" => (Echo(try!(arg(0, "text")).text), 1),
+        "tick" => (Tick, 0),
+        "tock" => (Tock, 0),
+        "ticktock" => (TickTock, 0),
+        "vmstep" => (VmStep, 0),
+        "eval" => (Eval, 0),
+        "output" => (Output(name.location), 0),
+        "clear-echo" => (ClearEcho, 0),
+        "clear-breakpoints" => (ClearBreakpoints, 0),
+        cmd => return error(name.location, format!("Unexpected command: '{}'", cmd))
+    };
+
+    if let Some(extra) = args.get(used) {
+        return error(extra.location, format!("Unexpected {} after '{}'", extra.describe(), name.text))
+    }
+
+    Ok(cmd)
+}
+
+/// Parse a test script into its commands
+pub fn parse(script: &str) -> Result<Vec<Command>, ParseError> {
+    let lexer = Lexer { script: script, offset: 0, location: Location { line: 1, column: 1 } };
+    let end = script.lines().count().max(1);
+    let end = Location { line: end, column: script.lines().last().map_or(0, |l| l.chars().count()) + 1 };
+
+    let tokens = try!(lexer.tokenize());
+    let mut parser = Parser { tokens: tokens, next: 0, end: end, hardware: false };
+    parser.commands(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hack_vm::Segment;
+    use runner::commands::{Command, Comparison, Condition, Var};
+    use runner::commands::Command::*;
+
+    fn parse_ok(s: &str) -> Vec<Command> {
+        parse(s).unwrap()
+    }
+
+    fn parse_err(s: &str) -> String {
+        parse(s).unwrap_err().to_string()
+    }
+
+    fn at(line: usize, column: usize) -> Location {
+        Location { line: line, column: column }
+    }
+
+    #[test]
+    fn cpu_emulator_commands() {
+        let commands = parse_ok("ROM32K load Max.hack,\n\
+                                 set A %B101, set D %XFFFF,\n\
+                                 echo \"Press any key, then wait\";\n\
+                                 breakpoint PC 12, clear-breakpoints,\n\
+                                 while RAM[0] <> 5 { tick, tock; }\n\
+                                 repeat 1000000 { ticktock; }\n\
+                                 repeat { ticktock; }\n\
+                                 clear-echo;");
+        let condition = Condition { var: Var::Ram(0), comparison: Comparison::NotEqual, value: 5 };
+
+        assert_eq!(commands, vec![Load(Some("Max.hack")), Set(Var::A, 5), Set(Var::D, 0xFFFF),
+                                  Echo("Press any key, then wait"), Breakpoint(Var::PC, 12),
+                                  ClearBreakpoints, While(condition, vec![Tick, Tock]),
+                                  Repeat(Some(1000000), vec![TickTock]),
+                                  Repeat(None, vec![TickTock]), ClearEcho]);
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let commands = parse_ok("repeat 2 {\n\
+                                     repeat 3 { ticktock; }\n\
+                                     while PC < 4 { ticktock; }\n\
+                                     output;\n\
+                                 }\n\
+                                 output;");
+        let condition = Condition { var: Var::PC, comparison: Comparison::Less, value: 4 };
+
+        assert_eq!(commands, vec![Repeat(Some(2), vec![Repeat(Some(3), vec![TickTock]),
+                                                       While(condition, vec![TickTock]),
+                                                       Output(at(4, 1))]),
+                                  Output(at(6, 1))]);
+    }
+
+    #[test]
+    fn comments() {
+        let commands = parse_ok("// Header, with a comma; and a semicolon\n\
+                                 output-list RAM[0]%D2.6.2 /* inline, comment */ RAM[1]%D2.6.2;\n\
+                                 /* A block comment\n\
+                                    spanning lines { } */\n\
+                                 set RAM[0] 3, // Trailing, comment\n\
+                                 output;");
+
+        let columns = vec![Column::parse_item("RAM[0]%D2.6.2").unwrap(),
+                           Column::parse_item("RAM[1]%D2.6.2").unwrap()];
+        assert_eq!(commands, vec![OutputList(columns.concat(), at(2, 1)),
+                                  Set(Var::Ram(0), 3), Output(at(6, 1))]);
+    }
+
+    #[test]
+    fn vm_emulator_commands() {
+        let commands = parse_ok("load,\n\
+                                 output-list sp%D1.6.1 local[0]%D1.6.1;\n\
+                                 set sp 256, set local 300,\n\
+                                 repeat 2 { vmstep; }\n\
+                                 load Main.vm;");
+
+        let columns = vec![Column::parse_item("sp%D1.6.1").unwrap(),
+                           Column::parse_item("local[0]%D1.6.1").unwrap()];
+        assert_eq!(commands, vec![Load(None), OutputList(columns.concat(), at(2, 1)),
+                                  Set(Var::Sp, 256), Set(Var::Base(Segment::Local), 300),
+                                  Repeat(Some(2), vec![VmStep]), Load(Some("Main.vm"))]);
+    }
+
+    #[test]
+    fn hardware_simulator_commands() {
+        let commands = parse_ok("load Xor.hdl,\n\
+                                 output-list time%S1.4.1 a%B3.16.3 out[3]%D1.6.1 RAM16K[0];\n\
+                                 set a %B0000000000000101, eval, output;\n\
+                                 tick, output; tock, output;\n\
+                                 while pc <> 7 { ticktock; }");
+        let pin = |name: &str| Var::Pin(name.to_string());
+        let columns = vec![Column::parse_item("time%S1.4.1").unwrap(),
+                           Column::parse_with("a%B3.16.3", Var::parse_pin).unwrap(),
+                           Column::parse_with("out[3]%D1.6.1", Var::parse_pin).unwrap(),
+                           Column::parse_with("RAM16K[0]", Var::parse_pin).unwrap()];
+        let condition = Condition { var: pin("pc"), comparison: Comparison::NotEqual, value: 7 };
+
+        assert_eq!(columns[1][0].var, pin("a"));
+        assert_eq!(commands, vec![Load(Some("Xor.hdl")), OutputList(columns.concat(), at(2, 1)),
+                                  Set(pin("a"), 5), Eval, Output(at(3, 33)),
+                                  Tick, Output(at(4, 7)), Tock, Output(at(4, 21)),
+                                  While(condition, vec![TickTock])]);
+
+        // Before a chip is loaded, names are those of the CPU emulator
+        assert_eq!(parse_err("set a 1;\nload Xor.hdl;"), "line 1, column 5: Unknown variable: 'a'");
+        assert_eq!(parse_err("load Xor.hdl, set out[x] 1;"), "line 1, column 19: Invalid pin: 'out[x]'");
+    }
+
+    #[test]
+    fn error_locations() {
+        assert_eq!(parse_err("output;\nset RAM[0] 12x;"),
+                   "line 2, column 12: Invalid value '12x': invalid digit found in string");
+        assert_eq!(parse_err("repeat 3 {\n  ticktock;\n"),
+                   "line 1, column 10: Missing '}' to close this block");
+        assert_eq!(parse_err("output;\n}"), "line 2, column 1: Unexpected '}'");
+        assert_eq!(parse_err("ticktock\noutput;"),
+                   "line 2, column 1: Unexpected 'output' after 'ticktock'");
+        assert_eq!(parse_err("output"), "line 1, column 7: Expected ',' or ';' after 'output'");
+        assert_eq!(parse_err("  /* never\nclosed"), "line 1, column 3: Unterminated comment");
+        assert_eq!(parse_err("echo \"oops;"), "line 1, column 6: Unterminated string");
+        assert_eq!(parse_err("while RAM[0] != 1 {}"), "line 1, column 14: Unknown comparison: '!='");
+        assert_eq!(parse_ok("repeat { ticktock; }"), vec![Repeat(None, vec![TickTock])]);
+        assert_eq!(parse_err("repeat x { ticktock; }"),
+                   "line 1, column 8: Invalid iteration count for 'repeat': invalid digit found in string - 'x'");
+        assert_eq!(parse_err("repeat;"), "line 1, column 7: Expected '{' after 'repeat', found ';'");
+        assert_eq!(parse_err("set time 3;"), "line 1, column 5: Cannot set 'time'");
+        assert_eq!(parse_err("compare-screen a.pbm 3 junk;"),
+                   "line 1, column 24: Unexpected 'junk' after 'compare-screen'");
+        assert_eq!(parse_err("output-list RAM[0]%D1.6.1 RAM[1]%Q1.6.1;"),
+                   "line 1, column 27: Unknown format: 'Q'");
+        assert_eq!(parse_err("\n\nfrobnicate;"), "line 3, column 1: Unexpected command: 'frobnicate'");
+    }
+}
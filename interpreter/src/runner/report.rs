@@ -0,0 +1,73 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use limits::LimitExceeded;
+use super::compare::ComparisonFailure;
+
+/// Why a test script failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestFailure {
+    /// The output didn't match the comparison file
+    Comparison(ComparisonFailure),
+    /// The script or its program went beyond the limits it was run with
+    Limit(LimitExceeded),
+    /// The script couldn't be read, parsed or run to the end
+    Error(String),
+}
+
+impl fmt::Display for TestFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TestFailure::Comparison(ref failure) => write!(f, "{}", failure),
+            TestFailure::Limit(ref exceeded) => write!(f, "{}", exceeded),
+            TestFailure::Error(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// The outcome of running a test script
+#[derive(Debug, Clone)]
+pub struct TestRun {
+    pub script: PathBuf,
+    /// Lines of output checked against the comparison file
+    pub outputs: usize,
+    /// Clock cycles executed, across every program the script loaded
+    pub cycles: u64,
+    pub duration: Duration,
+    /// The output file's contents, as far as the script got
+    pub output: String,
+    /// Text echoed by the script and breakpoints reached, in order
+    pub messages: Vec<String>,
+    pub failure: Option<TestFailure>,
+}
+
+impl TestRun {
+    pub fn new(script: PathBuf) -> TestRun {
+        TestRun {
+            script: script,
+            outputs: 0,
+            cycles: 0,
+            duration: Duration::from_secs(0),
+            output: String::new(),
+            messages: vec![],
+            failure: None,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    pub fn seconds(&self) -> f64 {
+        self.duration.as_secs() as f64 + self.duration.subsec_nanos() as f64 / 1e9
+    }
+}
+
+impl fmt::Display for TestRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} '{}': {} output lines compared, {} cycles in {:.3}s",
+               if self.passed() { "Passed" } else { "Failed" },
+               self.script.to_string_lossy(), self.outputs, self.cycles, self.seconds())
+    }
+}
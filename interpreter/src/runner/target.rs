@@ -0,0 +1,310 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use hack_assembler::assemble;
+use hack_hdl::{self, Chip};
+use hack_vm::{self, Segment, Vm};
+
+use {Cpu, Rom, Screen};
+use memory::Word;
+use super::commands::Var;
+
+// Where the VM keeps its stack pointer and temp segment
+const SP: usize = 0;
+const TEMP_BASE: usize = 5;
+
+/// What a script runs: a Hack program on the CPU, VM code or a chip
+pub trait Target {
+    /// Run the first half of a clock cycle, on targets that have one
+    fn tick(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+    /// Run a clock cycle, or the rest of one after a tick, or a VM command
+    fn step(&mut self) -> Result<(), String>;
+    /// Propagate a chip's inputs to its outputs
+    fn eval(&mut self) -> Result<(), String> {
+        Err("Only chips can be evaluated".to_string())
+    }
+    /// The address of the next instruction, or the index of the next command
+    fn pc(&self) -> Word;
+    /// Read a register, a word of RAM or a pin, except for `time`
+    fn get(&self, var: &Var) -> Result<Word, String>;
+    fn set(&mut self, var: &Var, value: Word) -> Result<(), String>;
+    /// The screen, on targets with the memory of the Hack platform
+    fn screen(&self) -> Option<Screen> {
+        None
+    }
+    /// Load a program into the target's own ROM, if it has one
+    fn load_rom(&mut self, _program: &Rom) -> bool {
+        false
+    }
+}
+
+/// Load the file a script's `load` command names: VM code, a chip, or a Hack
+/// program run by the computer chip already loaded or else by the CPU.
+/// Returns whether `target` was replaced.
+pub fn load(target: &mut Box<Target>, path: &Path) -> Result<bool, String> {
+    let filename = path.to_string_lossy().into_owned();
+    *target = if path.is_dir() || filename.ends_with(".vm") {
+        Box::new(try!(hack_vm::load_path(path)))
+    } else if filename.ends_with(".hdl") {
+        Box::new(try!(hack_hdl::load_path(path)))
+    } else {
+        let rom = if filename.ends_with(".asm") {
+            let assembly = file_to_string!(path);
+            let program = try!(assemble(&assembly));
+            Rom::from_str(&program)
+        } else if filename.ends_with(".hack") {
+            Rom::from_file(&path)
+        } else {
+            return Err(format!("Unsupported file type: {}", filename))
+        };
+        // A computer chip runs the program from its ROM32K
+        let rom = try_s!(rom);
+        if target.load_rom(&rom) {
+            return Ok(false)
+        }
+        Box::new(Cpu::new(rom))
+    };
+    Ok(true)
+}
+
+impl Target for Cpu {
+    fn step(&mut self) -> Result<(), String> {
+        Cpu::step(self)
+    }
+
+    fn pc(&self) -> Word {
+        self.pc
+    }
+
+    fn get(&self, var: &Var) -> Result<Word, String> {
+        match *var {
+            Var::A => Ok(self.ra),
+            Var::D => Ok(self.rd),
+            Var::PC => Ok(self.pc),
+            _ => address(&self.ram, var, "a Hack program").map(|address| self.ram[address]),
+        }
+    }
+
+    fn set(&mut self, var: &Var, value: Word) -> Result<(), String> {
+        match *var {
+            Var::A => self.ra = value,
+            Var::D => self.rd = value,
+            Var::PC => self.pc = value,
+            _ => {
+                let address = try!(address(&self.ram, var, "a Hack program"));
+                self.ram[address] = value;
+            }
+        }
+        Ok(())
+    }
+
+    fn screen(&self) -> Option<Screen> {
+        Some(Screen::from_ram(&self.ram))
+    }
+}
+
+impl Target for Vm {
+    fn step(&mut self) -> Result<(), String> {
+        Vm::step(self)
+    }
+
+    fn pc(&self) -> Word {
+        self.pc as Word
+    }
+
+    fn get(&self, var: &Var) -> Result<Word, String> {
+        match *var {
+            Var::PC => Ok(self.pc as Word),
+            _ => address(&self.ram, var, "VM code").map(|address| self.ram[address]),
+        }
+    }
+
+    fn set(&mut self, var: &Var, value: Word) -> Result<(), String> {
+        match *var {
+            Var::PC => self.pc = value as usize,
+            _ => {
+                let address = try!(address(&self.ram, var, "VM code"));
+                self.ram[address] = value;
+            }
+        }
+        Ok(())
+    }
+
+    fn screen(&self) -> Option<Screen> {
+        Some(Screen::from_ram(&self.ram))
+    }
+}
+
+// Where a variable kept in RAM is, following the segment bases for entries
+// of the VM's segments
+fn address(ram: &[Word], var: &Var, target: &str) -> Result<usize, String> {
+    let address = match *var {
+        Var::Ram(index) => index,
+        Var::Sp => SP,
+        Var::Base(segment) => base(segment),
+        Var::Entry(Segment::Temp, index) => TEMP_BASE + index as usize,
+        Var::Entry(segment, index) => ram[base(segment)] as usize + index as usize,
+        _ => return Err(unavailable(var, target)),
+    };
+
+    if address >= ram.len() {
+        return Err(format!("'{}' is outside of RAM, at {}", var, address))
+    }
+    Ok(address)
+}
+
+// The address of a segment's base
+fn base(segment: Segment) -> usize {
+    match segment {
+        Segment::Local => 1,
+        Segment::Argument => 2,
+        Segment::This => 3,
+        Segment::That => 4,
+        _ => unreachable!(),
+    }
+}
+
+impl Target for Chip {
+    fn tick(&mut self) -> Result<(), String> {
+        Chip::tick(self);
+        Ok(())
+    }
+
+    fn step(&mut self) -> Result<(), String> {
+        Chip::tock(self);
+        Ok(())
+    }
+
+    fn eval(&mut self) -> Result<(), String> {
+        Chip::eval(self);
+        Ok(())
+    }
+
+    // A computer's program counter, if it has the built-in PC
+    fn pc(&self) -> Word {
+        self.memory("PC").map_or(0, |pc| pc[0])
+    }
+
+    fn get(&self, var: &Var) -> Result<Word, String> {
+        match try!(pin(self, var)) {
+            Pin::Whole(pin) => self.get(pin),
+            Pin::Bit(pin, bit) => self.get(pin).map(|value| value >> bit & 1),
+            Pin::Part(part, index) => Ok(self.memory(part).unwrap()[index]),
+        }
+    }
+
+    fn set(&mut self, var: &Var, value: Word) -> Result<(), String> {
+        match try!(pin(self, var)) {
+            Pin::Whole(pin) => self.set(pin, value),
+            Pin::Bit(pin, bit) => {
+                let old = try!(self.get(pin));
+                self.set(pin, old & !(1 << bit) | (value & 1) << bit)
+            }
+            Pin::Part(part, index) => {
+                self.memory_mut(part).unwrap()[index] = value;
+                Ok(())
+            }
+        }
+    }
+
+    fn load_rom(&mut self, program: &Rom) -> bool {
+        match self.memory_mut("ROM32K") {
+            Some(rom) => {
+                for (word, &instruction) in rom.iter_mut().zip(program.iter()) {
+                    *word = instruction;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// What a variable of a chip refers to
+enum Pin<'a> {
+    Whole(&'a str),
+    /// A bit of a pin, like `out[3]`
+    Bit(&'a str, usize),
+    /// A word of a built-in part's state, like `RAM16K[5]`, or `DRegister[]`
+    /// for a register's value
+    Part(&'a str, usize),
+}
+
+fn pin<'a>(chip: &Chip, var: &'a Var) -> Result<Pin<'a>, String> {
+    let name = match *var {
+        Var::Pin(ref name) => name,
+        _ => return Err(unavailable(var, "a chip")),
+    };
+    let open = match name.find('[') {
+        Some(open) => open,
+        None => return Ok(Pin::Whole(name)),
+    };
+    let (pin, index) = (&name[..open], &name[open + 1..name.len() - 1]);
+    let index: usize = if index.is_empty() { 0 } else { try_s!(index.parse()) };
+    match (chip.width(pin), chip.memory(pin)) {
+        (Some(width), _) if index < width => Ok(Pin::Bit(pin, index)),
+        (Some(width), _) => Err(format!("'{}' is out of range, '{}' is {} bits wide", name, pin, width)),
+        (None, Some(memory)) if index < memory.len() => Ok(Pin::Part(pin, index)),
+        (None, Some(memory)) => Err(format!("'{}' is out of range, '{}' has {} words", name, pin, memory.len())),
+        (None, None) => Err(format!("Unknown pin: '{}'", name)),
+    }
+}
+
+fn unavailable(var: &Var, target: &str) -> String {
+    format!("'{}' isn't available when running {}", var, target)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use hack_hdl::Chip;
+    use {Cpu, Rom};
+    use runner::commands::Var;
+    use super::Target;
+
+    #[test]
+    fn cpu_variables() {
+        let mut cpu = Cpu::new(Rom::from_str("").unwrap());
+        let var = |s| Var::parse(s).unwrap();
+        for &(name, value) in &[("A", 3), ("RAM[7]", 9), ("sp", 256), ("local", 300)] {
+            cpu.set(&var(name), value).unwrap();
+        }
+        cpu.set(&var("local[2]"), 12).unwrap();
+        assert_eq!(cpu.get(&var("A")), Ok(3));
+        assert_eq!(cpu.ram[7], 9);
+        assert_eq!(cpu.ram[0], 256);
+        assert_eq!(cpu.ram[302], 12);
+        assert_eq!(cpu.get(&var("temp[2]")), Ok(9));
+        assert_eq!(cpu.get(&Var::Pin("out".to_string())), Err("'out' isn't available when running a Hack program".to_string()));
+        assert!(cpu.screen().is_some());
+    }
+
+    #[test]
+    fn chip_variables() {
+        let source = "CHIP Reg { IN in[4], load; OUT out[4]; PARTS: Register(in[0..3]=in, load=load, out[0..3]=out); }";
+        let mut chip = Chip::new("Reg", &mut |name| Ok(if name == "Reg" { Some(source.to_string()) } else { None })).unwrap();
+        let chip: &mut Target = &mut chip;
+        let pin = |s: &str| Var::parse_pin(s).unwrap();
+        chip.set(&pin("in"), 0b1010).unwrap();
+        chip.set(&pin("in[0]"), 1).unwrap();
+        chip.set(&pin("load"), 1).unwrap();
+        chip.tick().unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.get(&pin("out")), Ok(0b1011));
+        assert_eq!(chip.get(&pin("out[1]")), Ok(1));
+        assert_eq!(chip.get(&pin("out[2]")), Ok(0));
+        assert_eq!(chip.get(&pin("Register[]")), Ok(0b1011));
+
+        chip.set(&pin("Register[0]"), 6).unwrap();
+        chip.eval().unwrap();
+        assert_eq!(chip.get(&pin("out")), Ok(6));
+        assert_eq!(chip.get(&pin("out[4]")), Err("'out[4]' is out of range, 'out' is 4 bits wide".to_string()));
+        assert_eq!(chip.get(&pin("Register[1]")), Err("'Register[1]' is out of range, 'Register' has 1 words".to_string()));
+        assert_eq!(chip.get(&pin("foo")), Err("Unknown pin: 'foo'".to_string()));
+        assert_eq!(chip.get(&Var::A), Err("'A' isn't available when running a chip".to_string()));
+        assert!(chip.screen().is_none());
+    }
+}
@@ -0,0 +1,282 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use image::{self, ImageBuffer, Luma, Rgba, RgbaImage};
+use memory::Word;
+
+pub const WIDTH: usize = 512;
+pub const HEIGHT: usize = 256;
+pub const SCREEN_ADDR: usize = 16384;
+pub const SCREEN_MEMORY_LEN: usize = WIDTH * HEIGHT / 16;
+pub const KEYBOARD_ADDR: usize = SCREEN_ADDR + SCREEN_MEMORY_LEN;
+
+/// A monochrome snapshot of the 512x256 Hack screen, `true` being a black pixel.
+#[derive(Clone, PartialEq)]
+pub struct Screen(Vec<bool>);
+
+/// The result of comparing two screens pixel by pixel.
+pub struct ScreenDiff {
+    pub differing: usize,
+    image: RgbaImage,
+}
+
+impl Screen {
+    /// Snapshot the screen memory map of `ram`.
+    pub fn from_ram(ram: &[Word]) -> Screen {
+        let words = &ram[SCREEN_ADDR..SCREEN_ADDR + SCREEN_MEMORY_LEN];
+
+        // Each word of memory holds 16 pixels, least significant bit leftmost
+        Screen(words.iter()
+                    .flat_map(|word| (0..16).map(move |bit| word & (1 << bit) != 0))
+                    .collect())
+    }
+
+    /// Load a reference screen from a PBM (P1 or P4) file, or any other
+    /// image format the `image` crate understands. Dark pixels are black.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Screen, String> {
+        let path = path.as_ref();
+        let is_pbm = path.extension().map_or(false, |ext| ext == "pbm");
+
+        let screen = if is_pbm {
+            let mut data = vec![];
+            try_s!(File::open(path).and_then(|mut f| f.read_to_end(&mut data)));
+            try!(parse_pbm(&data))
+        } else {
+            let luma = try_s!(image::open(path)).to_luma();
+            if luma.dimensions() != (WIDTH as u32, HEIGHT as u32) {
+                return Err(format!("Expected a {}x{} image, got {}x{}",
+                                   WIDTH, HEIGHT, luma.width(), luma.height()))
+            }
+            Screen(luma.pixels().map(|p| p.data[0] < 128).collect())
+        };
+
+        Ok(screen)
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.0[y * WIDTH + x]
+    }
+
+    /// Compare against `expected`, marking pixels that should be black
+    /// but are not in red, and stray black pixels in blue.
+    pub fn diff(&self, expected: &Screen) -> ScreenDiff {
+        let mut differing = 0;
+        let image = ImageBuffer::from_fn(WIDTH as u32, HEIGHT as u32, |x, y| {
+            let (x, y) = (x as usize, y as usize);
+            match (self.pixel(x, y), expected.pixel(x, y)) {
+                (true, true) => Rgba([0, 0, 0, 255]),
+                (false, false) => Rgba([255, 255, 255, 255]),
+                (false, true) => { differing += 1; Rgba([255, 0, 0, 255]) }
+                (true, false) => { differing += 1; Rgba([0, 0, 255, 255]) }
+            }
+        });
+
+        ScreenDiff { differing: differing, image: image }
+    }
+
+    /// Save as a PBM file, or any other image format the `image` crate can write.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        if path.extension().map_or(false, |ext| ext == "pbm") {
+            let mut f = try_s!(File::create(path));
+            try_s!(f.write_all(self.to_pbm().as_bytes()));
+        } else {
+            let image = ImageBuffer::from_fn(WIDTH as u32, HEIGHT as u32, |x, y| {
+                Luma([if self.pixel(x as usize, y as usize) { 0u8 } else { 255 }])
+            });
+            try_s!(image.save(path));
+        }
+        Ok(())
+    }
+
+    /// Render as lines of Unicode braille characters, each covering 2x4 pixels.
+    pub fn to_braille(&self) -> Vec<String> {
+        // Dot bits of a braille cell, indexed by [y][x]
+        const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+        (0..HEIGHT / 4).map(|row| {
+            (0..WIDTH / 2).map(|col| {
+                let mut cell = 0x2800;
+                for (dy, dots) in DOTS.iter().enumerate() {
+                    for (dx, dot) in dots.iter().enumerate() {
+                        if self.pixel(col * 2 + dx, row * 4 + dy) { cell |= *dot }
+                    }
+                }
+                ::std::char::from_u32(cell).unwrap()
+            }).collect()
+        }).collect()
+    }
+
+    /// Render as lines of half-block characters. Each character covers
+    /// `step` pixels across and `step * 2` down, a half being black when
+    /// any of its pixels are.
+    pub fn to_half_blocks(&self, step: usize) -> Vec<String> {
+        let black = |x: usize, y: usize| {
+            (y..y + step).any(|y| (x..x + step).any(|x| self.pixel(x, y)))
+        };
+
+        (0..HEIGHT / (step * 2)).map(|row| {
+            (0..WIDTH / step).map(|col| {
+                let (x, y) = (col * step, row * step * 2);
+                match (black(x, y), black(x, y + step)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                }
+            }).collect()
+        }).collect()
+    }
+
+    /// Write the screen as a plain (P1) PBM file.
+    pub fn to_pbm(&self) -> String {
+        let mut out = format!("P1\n{} {}\n", WIDTH, HEIGHT);
+        for row in self.0.chunks(WIDTH) {
+            let line = row.iter().map(|&p| if p { '1' } else { '0' }).collect::<String>();
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl ScreenDiff {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        try_s!(self.image.save(path));
+        Ok(())
+    }
+}
+
+fn parse_pbm(data: &[u8]) -> Result<Screen, String> {
+    // The header is whitespace separated, with '#' comments until end of line
+    let mut pos = 0;
+    let mut header = vec![];
+    while header.len() < 3 {
+        while pos < data.len() && (data[pos] as char).is_whitespace() { pos += 1 }
+        if pos < data.len() && data[pos] == b'#' {
+            while pos < data.len() && data[pos] != b'\n' { pos += 1 }
+            continue
+        }
+
+        let start = pos;
+        while pos < data.len() && !(data[pos] as char).is_whitespace() { pos += 1 }
+        if start == pos { return Err("Truncated PBM header".to_string()) }
+        header.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+    }
+
+    let width: usize = try_s!(header[1].parse());
+    let height: usize = try_s!(header[2].parse());
+    if (width, height) != (WIDTH, HEIGHT) {
+        return Err(format!("Expected a {}x{} image, got {}x{}", WIDTH, HEIGHT, width, height))
+    }
+
+    let pixels: Vec<bool> = match &header[0][..] {
+        "P1" => data[pos..].iter()
+                           .filter(|&&b| b == b'0' || b == b'1')
+                           .map(|&b| b == b'1')
+                           .collect(),
+        // A single whitespace byte separates the header from the raster
+        "P4" => data.get(pos + 1..).unwrap_or(&[]).iter()
+                   .flat_map(|byte| (0..8).rev().map(move |bit| byte & (1 << bit) != 0))
+                   .collect(),
+        magic => return Err(format!("Unsupported PBM type: {}", magic))
+    };
+
+    if pixels.len() < WIDTH * HEIGHT {
+        return Err(format!("PBM data is truncated: {} of {} pixels",
+                           pixels.len(), WIDTH * HEIGHT))
+    }
+
+    Ok(Screen(pixels[..WIDTH * HEIGHT].to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::Ram;
+
+    #[test]
+    fn from_ram_lsb_is_leftmost() {
+        let mut ram = Ram::new();
+        ram[SCREEN_ADDR] = 0b101;
+        ram[SCREEN_ADDR + 32] = 0x8000;
+        let screen = Screen::from_ram(&ram);
+
+        assert!(screen.pixel(0, 0));
+        assert!(!screen.pixel(1, 0));
+        assert!(screen.pixel(2, 0));
+        assert!(screen.pixel(15, 1));
+        assert!(!screen.pixel(0, 1));
+    }
+
+    #[test]
+    fn pbm_roundtrip() {
+        let mut ram = Ram::new();
+        ram[SCREEN_ADDR + 100] = 0xF0F0;
+        let screen = Screen::from_ram(&ram);
+
+        let parsed = parse_pbm(screen.to_pbm().as_bytes()).unwrap();
+        assert!(parsed == screen);
+    }
+
+    #[test]
+    fn raw_pbm() {
+        let mut data = format!("P4\n# comment\n{} {}\n", WIDTH, HEIGHT).into_bytes();
+        data.extend((0..WIDTH * HEIGHT / 8).map(|i| if i == 0 { 0b1000_0001 } else { 0 }));
+        let screen = parse_pbm(&data).unwrap();
+
+        assert!(screen.pixel(0, 0));
+        assert!(!screen.pixel(1, 0));
+        assert!(screen.pixel(7, 0));
+        assert!(!screen.pixel(8, 0));
+
+        let header = format!("P4\n{} {}", WIDTH, HEIGHT);
+        assert_eq!(parse_pbm(header.as_bytes()).err(),
+                   Some(format!("PBM data is truncated: 0 of {} pixels", WIDTH * HEIGHT)));
+    }
+
+    #[test]
+    fn diff_counts_pixels() {
+        let mut ram = Ram::new();
+        let blank = Screen::from_ram(&ram);
+        ram[SCREEN_ADDR] = 0b111;
+        let drawn = Screen::from_ram(&ram);
+
+        assert_eq!(drawn.diff(&blank).differing, 3);
+        assert_eq!(blank.diff(&drawn).differing, 3);
+        assert_eq!(drawn.diff(&drawn).differing, 0);
+    }
+
+    #[test]
+    fn braille() {
+        let mut ram = Ram::new();
+        ram[SCREEN_ADDR] = 0b11;
+        ram[SCREEN_ADDR + 32 * 3] = 0b10;
+        let lines = Screen::from_ram(&ram).to_braille();
+
+        assert_eq!(lines.len(), 64);
+        assert_eq!(lines[0].chars().count(), 256);
+        assert_eq!(lines[0].chars().next(), Some('\u{2889}'));
+        assert_eq!(lines[1].chars().next(), Some('\u{2800}'));
+    }
+
+    #[test]
+    fn half_blocks() {
+        let mut ram = Ram::new();
+        ram[SCREEN_ADDR] = 0b1;
+        ram[SCREEN_ADDR + 32 * 3] = 0b1000;
+        let lines = Screen::from_ram(&ram).to_half_blocks(2);
+
+        assert_eq!(lines.len(), 64);
+        assert_eq!(lines[0].chars().count(), 256);
+        assert!(lines[0].starts_with("▀▄  "));
+        assert!(lines[1].starts_with("    "));
+    }
+
+    #[test]
+    #[should_panic(expected="Expected a 512x256 image")]
+    fn wrong_size() {
+        parse_pbm(b"P1\n2 2\n0 1\n1 0\n").unwrap();
+    }
+}
@@ -0,0 +1,20 @@
+// Runs Mult.hack on the computer chip, like projects/05/ComputerMax.tst
+load Computer/Computer.hdl,
+output-file ComputerMult.out,
+compare-to ComputerMult.cmp,
+output-list time%S1.4.1 reset%B2.1.2 ARegister[]%D1.7.1 DRegister[]%D1.7.1 PC[]%D0.4.0 RAM16K[0]%D1.7.1 RAM16K[1]%D1.7.1 RAM16K[2]%D1.7.1;
+
+ROM32K load Mult.hack,
+
+set RAM16K[0] 3,
+set RAM16K[1] 5,
+output;
+
+repeat 120 {
+    tick, tock;
+}
+output;
+
+set reset 1,
+tick, tock,
+output;
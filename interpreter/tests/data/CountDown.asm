@@ -0,0 +1,17 @@
+// Adds RAM[0] + (RAM[0] - 1) + ... + 1 into RAM[1], counting RAM[0] down to 0
+    @1
+    M=0
+(LOOP)
+    @0
+    D=M
+    @END
+    D;JEQ
+    @1
+    M=D+M
+    @0
+    M=M-1
+    @LOOP
+    0;JMP
+(END)
+    @END
+    0;JMP
@@ -0,0 +1,25 @@
+// Exercises the CPU emulator commands beyond set, repeat and ticktock
+ROM32K load CountDown.asm,
+output-file CountDown.out,
+compare-to CountDown.cmp,
+output-list RAM[0]%D2.6.2 RAM[1]%D2.6.2 A%D1.6.1 D%D1.6.1 PC%D1.6.1;
+
+set RAM[0] %X4,
+breakpoint PC 12;
+while PC <> 12 {
+    tick, tock;
+}
+clear-breakpoints,
+echo "Counted down from 4";
+output;
+
+tick;
+output;
+tock;
+output;
+
+set A %B1010,
+set D %D-2,
+set RAM[0] %XFFFF,
+clear-echo;
+output;
@@ -0,0 +1,18 @@
+// Runs the FibonacciElement directory on the VM emulator, as if the
+// bootstrap code had called Sys.init
+load FibonacciElement,
+output-file FibonacciElement.out,
+compare-to FibonacciElement.cmp,
+output-list RAM[0]%D1.6.1 RAM[261]%D1.6.1 sp%D1.6.1 argument[0]%D1.6.1;
+
+set sp 261,
+set local 261,
+set argument 256,
+set this 3000,
+set that 4000;
+
+repeat 110 {
+  vmstep;
+}
+
+output;
@@ -0,0 +1,15 @@
+// Output-list items without formats, RAM ranges, time and the %S format
+load CountDown.asm,
+output-file OutputList.out,
+compare-to OutputList.cmp,
+output-list time RAM[0..1] A D PC%S1.4.1;
+
+set RAM[0] 2,
+output;
+
+repeat 2 {
+    tick,
+    output;
+    tock,
+    output;
+}
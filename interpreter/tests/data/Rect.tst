@@ -0,0 +1,10 @@
+load Rect.asm,
+output-file Rect.out,
+compare-to Rect.cmp,
+output-list RAM[0]%D2.6.2;
+set RAM[0] 4; // Draw a rectangle 4 pixels high
+repeat 100 {
+    ticktock;
+}
+output;
+compare-screen Rect.pbm;
@@ -0,0 +1,82 @@
+extern crate hack_assembler;
+extern crate hack_hdl;
+extern crate hack_interpreter;
+
+use std::fs::File;
+use std::io::Read;
+
+use hack_assembler::assemble;
+use hack_hdl::{load_path, Chip};
+use hack_interpreter::{lockstep, Difference, Divergence, Rom};
+
+// Adds 5 + 4 + ... + 1 into R1, drawing a line down the screen for each
+static PROGRAM: &'static str = "
+    @5
+    D=A
+    @R0
+    M=D
+    @SCREEN
+    D=A
+    @R2
+    M=D
+(LOOP)
+    @R0
+    D=M
+    @END
+    D;JLE
+    @R1
+    M=D+M
+    @R2
+    A=M
+    M=-1
+    @32
+    D=A
+    @R2
+    M=D+M
+    @R0
+    M=M-1
+    @LOOP
+    0;JMP
+(END)
+    @END
+    0;JMP
+";
+
+fn program() -> Rom {
+    Rom::from_str(&assemble(PROGRAM).unwrap()).unwrap()
+}
+
+// The computer in tests/data/Computer, with a change to its CPU
+fn computer(from: &str, to: &str) -> Chip {
+    Chip::new("Computer", &mut |chip| {
+        let mut source = String::new();
+        Ok(File::open(format!("tests/data/Computer/{}.hdl", chip)).and_then(|mut f| f.read_to_string(&mut source))
+                                                                  .ok().map(|_| source.replace(from, to)))
+    }).unwrap()
+}
+
+#[test]
+fn matching() {
+    let mut chip = load_path("tests/data/Computer/Computer.hdl").unwrap();
+    assert_eq!(lockstep(&mut chip, program(), 200), Ok(None));
+    assert_eq!(chip.memory("RAM16K").map(|ram| (ram[0], ram[1])), Some((0, 15)));
+    assert_eq!(chip.memory("Screen").map(|screen| (screen[0], screen[4 * 32], screen[5 * 32])), Some((0xFFFF, 0xFFFF, 0)));
+}
+
+#[test]
+fn differences() {
+    // Jumping if greater than on the bit for less than
+    let mut chip = computer("a=instruction[0], b=positive", "a=instruction[2], b=positive");
+    let divergence = lockstep(&mut chip, program(), 200).unwrap().unwrap();
+    assert_eq!(divergence, Divergence { cycle: 12, difference: Difference::Register("PC", 25, 12) });
+    assert_eq!(divergence.to_string(), "Cycle 12: PC is 25 on the chip, but 12 in the CPU emulator");
+
+    // Writing memory for the D destination
+    let mut chip = computer("b=instruction[3], out=writeM", "b=instruction[4], out=writeM");
+    let divergence = lockstep(&mut chip, program(), 200).unwrap().unwrap();
+    assert_eq!(divergence.difference, Difference::Write(Some((5, 5)), None));
+    assert_eq!(divergence.to_string(), "Cycle 2: The chip writes 5 to RAM[5], but the CPU emulator writes nothing");
+
+    let mut chip = load_path("tests/data/Computer/Memory.hdl").unwrap();
+    assert_eq!(lockstep(&mut chip, program(), 200), Err("Memory has no built-in ROM32K to run the program".to_string()));
+}
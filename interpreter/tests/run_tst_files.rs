@@ -1,9 +1,16 @@
 extern crate hack_interpreter;
 extern crate glob;
+extern crate tempdir;
 
+use std::fs::File;
+use std::io::Write;
 use std::thread;
 use glob::glob;
-use hack_interpreter::runner;
+use tempdir::TempDir;
+use hack_interpreter::{runner, run_test, run_test_with_limits, run_tests, find_scripts};
+use hack_interpreter::{Limits, Ram, Screen, TestFailure};
+use hack_interpreter::limits::Limit;
+use hack_interpreter::screen::SCREEN_ADDR;
 
 #[test]
 fn run_tst_files() {
@@ -19,3 +26,187 @@ fn run_tst_files() {
         }
     }
 }
+
+#[test]
+fn compare_screen_tolerance() {
+    let dir = TempDir::new("hack-interpreter").unwrap();
+    let mut ram = Ram::new();
+    ram[SCREEN_ADDR] = 1;
+    File::create(dir.path().join("Dot.pbm")).unwrap()
+        .write_all(Screen::from_ram(&ram).to_pbm().as_bytes()).unwrap();
+
+    let write_tst = |name: &str, compare: &str| {
+        let path = dir.path().join(name);
+        File::create(&path).unwrap()
+            .write_all(format!("output-file Blank.out,\n{};\n", compare).as_bytes())
+            .unwrap();
+        path
+    };
+
+    let strict = write_tst("Strict.tst", "compare-screen Dot.pbm");
+    let err = runner(&strict).unwrap_err();
+    assert!(err.contains("1 pixels differ"), "{}", err);
+    assert!(dir.path().join("Dot.diff.png").exists());
+
+    let tolerant = write_tst("Tolerant.tst", "compare-screen Dot.pbm 1");
+    runner(&tolerant).unwrap();
+}
+
+#[test]
+fn comparison_failure_details() {
+    let dir = TempDir::new("hack-interpreter").unwrap();
+    File::create(dir.path().join("Wrong.cmp")).unwrap()
+        .write_all(b"|  RAM[0]  |  RAM[1]  |\n|       3  |       0  |\n|       7  |       1  |\n").unwrap();
+    let path = dir.path().join("Wrong.tst");
+    File::create(&path).unwrap()
+        .write_all(b"output-file Wrong.out,\ncompare-to Wrong.cmp,\n\
+                     output-list RAM[0]%D2.6.2 RAM[1]%D2.6.2;\n\
+                     set RAM[0] 3,\noutput;\nset RAM[0] 5,\nset RAM[1] 1,\noutput;\n").unwrap();
+
+    let err = runner(&path).unwrap_err();
+    assert!(err.contains("Comparison failed at line 3, written by 'output' on line 8 of the script"),
+            "{}", err);
+    assert!(err.contains("RAM[0]: expected 7 (hex 0007, binary 0000000000000111), got 5"), "{}", err);
+    assert!(!err.contains("RAM[1]:"), "{}", err);
+}
+
+#[test]
+fn test_run_report() {
+    let dir = TempDir::new("hack-interpreter").unwrap();
+    File::create(dir.path().join("Count.asm")).unwrap().write_all(b"@3\nD=A\n@0\nM=D\n").unwrap();
+    File::create(dir.path().join("Count.cmp")).unwrap()
+        .write_all(b"|  RAM[0]  |\n|       0  |\n|       3  |\n").unwrap();
+    let write_tst = |name: &str, cycles: usize| {
+        let path = dir.path().join(name);
+        File::create(&path).unwrap()
+            .write_all(format!("load Count.asm, output-file Count.out, compare-to Count.cmp,\n\
+                                output-list RAM[0]%D2.6.2;\n\
+                                output;\nrepeat {} {{ ticktock; }}\noutput;\n", cycles).as_bytes())
+            .unwrap();
+        path
+    };
+
+    let run = run_test(write_tst("Pass.tst", 4));
+    assert!(run.passed(), "{:?}", run.failure);
+    assert_eq!(run.outputs, 3);
+    assert_eq!(run.cycles, 4);
+    assert_eq!(run.output, "|  RAM[0]  |\n|       0  |\n|       3  |\n");
+
+    let run = run_test(write_tst("Fail.tst", 2));
+    assert!(!run.passed());
+    assert_eq!(run.cycles, 2);
+    match run.failure {
+        Some(TestFailure::Comparison(ref failure)) => {
+            assert_eq!(failure.line, 3);
+            assert_eq!(failure.script_line, 5);
+            assert_eq!(failure.mismatches[0].expected_value, Some(3));
+        }
+        ref failure => panic!("Unexpected failure: {:?}", failure),
+    }
+
+    let run = run_test(dir.path().join("Missing.tst"));
+    match run.failure {
+        Some(TestFailure::Error(ref e)) => assert!(e.contains("does not exist"), "{}", e),
+        ref failure => panic!("Unexpected failure: {:?}", failure),
+    }
+}
+
+#[test]
+fn script_messages() {
+    let run = run_test("tests/data/CountDown.tst");
+    assert!(run.passed(), "{:?}", run.failure);
+    assert_eq!(run.messages, ["Breakpoint reached: PC = 12 at time 46", "Counted down from 4"]);
+}
+
+#[test]
+fn batch_with_cycle_limit() {
+    let dir = TempDir::new("hack-interpreter").unwrap();
+    let write = |name: &str, contents: &str| {
+        File::create(dir.path().join(name)).unwrap().write_all(contents.as_bytes()).unwrap();
+    };
+    write("Spin.asm", "(LOOP)\n@LOOP\n0;JMP\n");
+    write("Short.tst", "load Spin.asm, output-file Short.out;\nrepeat 10 { ticktock; }\n");
+    write("Forever.tst", "load Spin.asm, output-file Forever.out;\nwhile PC < 2 { ticktock; }\n");
+
+    let scripts = find_scripts(&[dir.path().to_string_lossy()]).unwrap();
+    let names: Vec<_> = scripts.iter().map(|s| s.file_name().unwrap().to_string_lossy().into_owned()).collect();
+    assert_eq!(names, ["Forever.tst", "Short.tst"]);
+
+    let limits = Limits { max_cycles: Some(1000), ..Limits::default() };
+    let runs = run_tests(&scripts, 2, &limits);
+    match runs[0].failure {
+        Some(TestFailure::Limit(ref exceeded)) => {
+            assert_eq!(exceeded.limit, Limit::Cycles(1000));
+            assert_eq!(exceeded.tight_loop, Some((0, 1)));
+        }
+        ref failure => panic!("Unexpected failure: {:?}", failure),
+    }
+    assert_eq!(runs[0].cycles, 1000);
+    assert!(runs[1].passed(), "{:?}", runs[1].failure);
+
+    assert!(find_scripts(&[dir.path().join("*.cmp").to_string_lossy()]).is_err());
+
+    // A repeat without a count only stops at a limit
+    write("Endless.tst", "load Spin.asm, output-file Endless.out;\nrepeat { ticktock; }\n");
+    let run = run_test_with_limits(dir.path().join("Endless.tst"), &limits);
+    match run.failure {
+        Some(TestFailure::Limit(ref exceeded)) => assert_eq!(exceeded.limit, Limit::Cycles(1000)),
+        ref failure => panic!("Unexpected failure: {:?}", failure),
+    }
+    assert_eq!(run.cycles, 1000);
+
+    // Scripts that write output forever without running the clock
+    write("Chatty.tst", "output-file Chatty.out, output-list RAM[0];\nwhile PC = 0 { output; }\n");
+    let limits = Limits { max_output: Some(1000), ..Limits::default() };
+    let run = run_test_with_limits(dir.path().join("Chatty.tst"), &limits);
+    match run.failure {
+        Some(TestFailure::Limit(ref exceeded)) => assert_eq!(exceeded.limit, Limit::Output(1000)),
+        ref failure => panic!("Unexpected failure: {:?}", failure),
+    }
+    assert!(run.output.len() <= 1010);
+}
+
+#[test]
+fn batch_with_faulty_program() {
+    let dir = TempDir::new("hack-interpreter").unwrap();
+    let write = |name: &str, contents: &str| {
+        File::create(dir.path().join(name)).unwrap().write_all(contents.as_bytes()).unwrap();
+    };
+    write("Far.asm", "@32000\nM=1\n");
+    write("Bad.tst", "load Far.asm, output-file Bad.out;\nrepeat 3 { ticktock; }\n");
+    write("Past.tst", "load Far.asm, output-file Past.out;\nset PC 32767, ticktock, ticktock;\n");
+    write("Good.tst", "load Far.asm, output-file Good.out;\nticktock;\n");
+
+    let scripts = find_scripts(&[dir.path().to_string_lossy()]).unwrap();
+    let runs = run_tests(&scripts, 2, &Limits::default());
+    let failures: Vec<_> = runs.iter().map(|r| r.failure.as_ref().map(|f| f.to_string())).collect();
+    assert_eq!(failures, [Some("ROM[1] writes to RAM[32000], which is outside of memory".to_string()),
+                          None,
+                          Some("PC 32768 is outside of ROM".to_string())]);
+    assert_eq!(runs[0].cycles, 1);
+}
+
+#[test]
+fn vm_scripts() {
+    let dir = TempDir::new("hack-interpreter").unwrap();
+    let write = |name: &str, contents: &str| {
+        File::create(dir.path().join(name)).unwrap().write_all(contents.as_bytes()).unwrap();
+    };
+    write("Main.vm", "function Main.start 2\npush constant 7\npop local 1\npush constant 3\npop temp 2\n\
+                      label END\ngoto END\n");
+    // Without a file name, every .vm file next to the script is loaded
+    write("Main.tst", "load, output-file Main.out,\n\
+                       output-list sp%D1.6.1 local%D1.6.1 local[1]%D1.6.1 temp[2]%D1.6.1;\n\
+                       set sp 256, set local 256;\nrepeat 5 { vmstep; }\noutput;\n");
+
+    let run = run_test(dir.path().join("Main.tst"));
+    assert!(run.passed(), "{:?}", run.failure);
+    assert_eq!(run.output, "|   sp   | local  |local[1]|temp[2] |\n\
+                            |    258 |    256 |      7 |      3 |\n");
+
+    write("Registers.tst", "load Main.vm, output-file Registers.out;\nset A 1;\n");
+    match run_test(dir.path().join("Registers.tst")).failure {
+        Some(TestFailure::Error(ref e)) => assert_eq!(e, "'A' isn't available when running VM code"),
+        ref failure => panic!("Unexpected failure: {:?}", failure),
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+
+use error::Span;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Char,
+    Boolean,
+    Class(String),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Int => write!(f, "int"),
+            Type::Char => write!(f, "char"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::Class(ref name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub name: Ident,
+    pub vars: Vec<ClassVarDec>,
+    pub subroutines: Vec<Subroutine>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClassVarKind { Static, Field }
+
+/// A `static` or `field` declaration of one or more variables
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassVarDec {
+    pub kind: ClassVarKind,
+    pub ty: Type,
+    pub names: Vec<Ident>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubroutineKind { Constructor, Function, Method }
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subroutine {
+    pub kind: SubroutineKind,
+    /// `None` for `void`
+    pub return_type: Option<Type>,
+    pub name: Ident,
+    pub params: Vec<(Type, Ident)>,
+    pub locals: Vec<VarDec>,
+    pub body: Vec<Statement>,
+    /// The closing brace of the body
+    pub end: Span,
+}
+
+/// A `var` declaration of one or more local variables
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarDec {
+    pub ty: Type,
+    pub names: Vec<Ident>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    /// The statement's keyword
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
+    Let { target: Ident, index: Option<Expr>, value: Expr },
+    If { condition: Expr, then: Vec<Statement>, otherwise: Option<Vec<Statement>> },
+    While { condition: Expr, body: Vec<Statement> },
+    Do(Call),
+    Return(Option<Expr>),
+}
+
+/// Jack has no operator precedence, so an expression is a term followed by
+/// operators and terms applied from left to right
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub term: Term,
+    pub rest: Vec<(BinaryOp, Term)>,
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        let last = self.rest.last().map_or(&self.term, |&(_, ref term)| term);
+        self.term.span.to(last.span)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp { Add, Sub, Mul, Div, And, Or, Lt, Gt, Eq }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp { Neg, Not }
+
+impl BinaryOp {
+    pub fn parse(symbol: char) -> Option<BinaryOp> {
+        let op = match symbol {
+            '+' => BinaryOp::Add,
+            '-' => BinaryOp::Sub,
+            '*' => BinaryOp::Mul,
+            '/' => BinaryOp::Div,
+            '&' => BinaryOp::And,
+            '|' => BinaryOp::Or,
+            '<' => BinaryOp::Lt,
+            '>' => BinaryOp::Gt,
+            '=' => BinaryOp::Eq,
+            _ => return None
+        };
+
+        Some(op)
+    }
+
+    pub fn symbol(&self) -> char {
+        match *self {
+            BinaryOp::Add => '+',
+            BinaryOp::Sub => '-',
+            BinaryOp::Mul => '*',
+            BinaryOp::Div => '/',
+            BinaryOp::And => '&',
+            BinaryOp::Or => '|',
+            BinaryOp::Lt => '<',
+            BinaryOp::Gt => '>',
+            BinaryOp::Eq => '=',
+        }
+    }
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> char {
+        match *self {
+            UnaryOp::Neg => '-',
+            UnaryOp::Not => '~',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub kind: TermKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TermKind {
+    Int(u16),
+    Str(String),
+    True,
+    False,
+    Null,
+    This,
+    Var(String),
+    Index(Ident, Box<Expr>),
+    Call(Call),
+    Paren(Box<Expr>),
+    Unary(UnaryOp, Box<Term>),
+}
+
+/// A subroutine call, `name(args)` or `receiver.name(args)` where the
+/// receiver is a variable or a class
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub receiver: Option<Ident>,
+    pub name: Ident,
+    pub args: Vec<Expr>,
+}
+
+impl Call {
+    pub fn span(&self) -> Span {
+        self.receiver.as_ref().map_or(self.name.span, |r| r.span.to(self.name.span))
+    }
+}
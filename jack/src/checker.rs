@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+
+use ast::*;
+use ast::SubroutineKind::{Constructor as C, Function as F, Method as M};
+use error::{Error, Span};
+use symbols::{Kind, SymbolTable};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Signature {
+    kind: SubroutineKind,
+    params: usize,
+    void: bool,
+    // Where it's declared, for those in the program
+    line: Option<usize>,
+}
+
+// The subroutines of the standard library: class, name, kind, parameters
+// and whether it's void
+static OS: &'static [(&'static str, &'static str, SubroutineKind, usize, bool)] = &[
+    ("Math", "init", F, 0, true), ("Math", "abs", F, 1, false), ("Math", "multiply", F, 2, false),
+    ("Math", "divide", F, 2, false), ("Math", "min", F, 2, false), ("Math", "max", F, 2, false),
+    ("Math", "sqrt", F, 1, false),
+    ("String", "new", C, 1, false), ("String", "dispose", M, 0, true), ("String", "length", M, 0, false),
+    ("String", "charAt", M, 1, false), ("String", "setCharAt", M, 2, true),
+    ("String", "appendChar", M, 1, false), ("String", "eraseLastChar", M, 0, true),
+    ("String", "intValue", M, 0, false), ("String", "setInt", M, 1, true),
+    ("String", "backSpace", F, 0, false), ("String", "doubleQuote", F, 0, false),
+    ("String", "newLine", F, 0, false),
+    ("Array", "new", F, 1, false), ("Array", "dispose", M, 0, true),
+    ("Output", "init", F, 0, true), ("Output", "moveCursor", F, 2, true), ("Output", "printChar", F, 1, true),
+    ("Output", "printString", F, 1, true), ("Output", "printInt", F, 1, true), ("Output", "println", F, 0, true),
+    ("Output", "backSpace", F, 0, true),
+    ("Screen", "init", F, 0, true), ("Screen", "clearScreen", F, 0, true), ("Screen", "setColor", F, 1, true),
+    ("Screen", "drawPixel", F, 2, true), ("Screen", "drawLine", F, 4, true),
+    ("Screen", "drawRectangle", F, 4, true), ("Screen", "drawCircle", F, 3, true),
+    ("Keyboard", "init", F, 0, true), ("Keyboard", "keyPressed", F, 0, false),
+    ("Keyboard", "readChar", F, 0, false), ("Keyboard", "readLine", F, 1, false),
+    ("Keyboard", "readInt", F, 1, false),
+    ("Memory", "init", F, 0, true), ("Memory", "peek", F, 1, false), ("Memory", "poke", F, 2, true),
+    ("Memory", "alloc", F, 1, false), ("Memory", "deAlloc", F, 1, true),
+    ("Sys", "init", F, 0, true), ("Sys", "halt", F, 0, true), ("Sys", "error", F, 1, true),
+    ("Sys", "wait", F, 1, true),
+];
+
+/// Look for mistakes the code generator would turn into broken VM code:
+/// undeclared variables and subroutines, calls with the wrong number of
+/// arguments, objects used in functions, void results used as values and
+/// missing or misplaced returns. Calls are checked against the classes given
+/// and the standard library. The errors are in order of position in each
+/// class.
+pub fn check(classes: &[Class]) -> Vec<Vec<Error>> {
+    let mut known: HashMap<String, HashMap<String, Signature>> = HashMap::new();
+    for &(class, name, kind, params, void) in OS {
+        let signature = Signature { kind: kind, params: params, void: void, line: None };
+        known.entry(class.to_string()).or_insert_with(HashMap::new).insert(name.to_string(), signature);
+    }
+
+    // The program's classes replace those of the library they're named after
+    let mut duplicates = vec![vec![]; classes.len()];
+    for class in classes {
+        known.insert(class.name.name.clone(), HashMap::new());
+    }
+    for (i, class) in classes.iter().enumerate() {
+        let subroutines = known.get_mut(&class.name.name).unwrap();
+        for subroutine in &class.subroutines {
+            let signature = Signature {
+                kind: subroutine.kind,
+                params: subroutine.params.len(),
+                void: subroutine.return_type.is_none(),
+                line: Some(subroutine.name.span.line),
+            };
+            if let Some(previous) = subroutines.insert(subroutine.name.name.clone(), signature) {
+                duplicates[i].push(Error::new(subroutine.name.span,
+                                              format!("Subroutine '{}' is already declared on line {}",
+                                                      subroutine.name.name, previous.line.unwrap_or(0))));
+            }
+        }
+    }
+
+    classes.iter().zip(duplicates).map(|(class, duplicates)| {
+        let mut checker = Checker { known: &known, class: class, errors: duplicates };
+        checker.class();
+        checker.errors.sort_by_key(|e| (e.span.line, e.span.column));
+        checker.errors
+    }).collect()
+}
+
+struct Checker<'a> {
+    known: &'a HashMap<String, HashMap<String, Signature>>,
+    class: &'a Class,
+    errors: Vec<Error>,
+}
+
+// What's being checked in a subroutine
+struct Scope<'a> {
+    subroutine: &'a Subroutine,
+    symbols: &'a SymbolTable,
+}
+
+impl<'a> Checker<'a> {
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(Error::new(span, message));
+    }
+
+    fn class(&mut self) {
+        let mut symbols = match SymbolTable::for_class(self.class) {
+            Ok(symbols) => symbols,
+            Err(e) => return self.errors.push(e),
+        };
+
+        for subroutine in &self.class.subroutines {
+            if let Err(e) = symbols.start_subroutine(self.class, subroutine) {
+                self.errors.push(e);
+                continue
+            }
+            let scope = Scope { subroutine: subroutine, symbols: &symbols };
+            self.subroutine(&scope);
+        }
+    }
+
+    fn subroutine(&mut self, scope: &Scope) {
+        let subroutine = scope.subroutine;
+        self.statements(scope, &subroutine.body);
+
+        if !returns(&subroutine.body) {
+            self.error(subroutine.end, format!("Missing 'return' at the end of '{}'", subroutine.name.name));
+        }
+    }
+
+    fn statements(&mut self, scope: &Scope, statements: &[Statement]) {
+        for (i, statement) in statements.iter().enumerate() {
+            self.statement(scope, statement);
+
+            if returns(&statements[..i + 1]) && i + 1 < statements.len() {
+                self.error(statements[i + 1].span, "Unreachable code after 'return'".to_string());
+                // The rest is still checked, it's just never run
+                for statement in &statements[i + 1..] {
+                    self.statement(scope, statement);
+                }
+                return
+            }
+        }
+    }
+
+    fn statement(&mut self, scope: &Scope, statement: &Statement) {
+        match statement.kind {
+            StatementKind::Let { ref target, ref index, ref value } => {
+                match scope.symbols.lookup(&target.name) {
+                    Some(_) => self.variable(scope, target),
+                    None => {
+                        self.error(target.span, format!("Assignment to undeclared variable '{}', declare it \
+                                                         with 'var', 'field' or 'static'", target.name))
+                    }
+                }
+                if let Some(ref index) = *index {
+                    self.expression(scope, index);
+                }
+                self.expression(scope, value);
+            }
+            StatementKind::If { ref condition, ref then, ref otherwise } => {
+                self.expression(scope, condition);
+                self.statements(scope, then);
+                if let Some(ref otherwise) = *otherwise {
+                    self.statements(scope, otherwise);
+                }
+            }
+            StatementKind::While { ref condition, ref body } => {
+                self.expression(scope, condition);
+                self.statements(scope, body);
+            }
+            StatementKind::Do(ref call) => {
+                self.call(scope, call);
+            }
+            StatementKind::Return(ref value) => {
+                let subroutine = scope.subroutine;
+                match (value, &subroutine.return_type) {
+                    (&Some(ref value), &None) => {
+                        self.error(value.span(), format!("'{}' is void, so it can't return a value",
+                                                         subroutine.name.name))
+                    }
+                    (&None, &Some(ref ty)) => {
+                        self.error(statement.span, format!("'{}' must return {} {}",
+                                                           subroutine.name.name, article(ty), ty))
+                    }
+                    _ => {}
+                }
+                if let Some(ref value) = *value {
+                    self.expression(scope, value);
+                }
+            }
+        }
+    }
+
+    // A variable that's declared, which mustn't be a field in a function
+    fn variable(&mut self, scope: &Scope, name: &Ident) {
+        let kind = scope.symbols.lookup(&name.name).map(|s| s.kind);
+        if kind == Some(Kind::Field) && scope.subroutine.kind == SubroutineKind::Function {
+            self.error(name.span, format!("Field '{}' used in function '{}', which has no object",
+                                          name.name, scope.subroutine.name.name));
+        }
+    }
+
+    fn expression(&mut self, scope: &Scope, expr: &Expr) {
+        self.term(scope, &expr.term);
+        for &(_, ref term) in &expr.rest {
+            self.term(scope, term);
+        }
+    }
+
+    fn term(&mut self, scope: &Scope, term: &Term) {
+        match term.kind {
+            TermKind::This if scope.subroutine.kind == SubroutineKind::Function => {
+                self.error(term.span, format!("'this' used in function '{}', which has no object",
+                                              scope.subroutine.name.name))
+            }
+            TermKind::Var(ref name) => self.declared(scope, &Ident { name: name.clone(), span: term.span }),
+            TermKind::Index(ref array, ref index) => {
+                self.declared(scope, array);
+                self.expression(scope, index);
+            }
+            TermKind::Call(ref call) => {
+                if let Some((name, true)) = self.call(scope, call) {
+                    self.error(call.span(), format!("'{}' is void, so its result can't be used", name));
+                }
+            }
+            TermKind::Paren(ref expr) => self.expression(scope, expr),
+            TermKind::Unary(_, ref term) => self.term(scope, term),
+            _ => {}
+        }
+    }
+
+    fn declared(&mut self, scope: &Scope, name: &Ident) {
+        match scope.symbols.lookup(&name.name) {
+            Some(_) => self.variable(scope, name),
+            None => self.error(name.span, format!("Undeclared variable '{}'", name.name)),
+        }
+    }
+
+    /// Check a call, giving the full name of what it calls and whether it's
+    /// void, when that's known
+    fn call(&mut self, scope: &Scope, call: &Call) -> Option<(String, bool)> {
+        for arg in &call.args {
+            self.expression(scope, arg);
+        }
+
+        let subroutine = scope.subroutine;
+        // The class called, and whether there's an object
+        let (class, object) = match call.receiver {
+            None => (self.class.name.name.clone(), true),
+            Some(ref receiver) => match scope.symbols.lookup(&receiver.name) {
+                Some(symbol) => {
+                    self.variable(scope, receiver);
+                    match symbol.ty {
+                        Type::Class(ref class) => (class.clone(), true),
+                        ref ty => {
+                            self.error(receiver.span, format!("'{}' is {} {}, which has no methods",
+                                                              receiver.name, article(ty), ty));
+                            return None
+                        }
+                    }
+                }
+                None => (receiver.name.clone(), false),
+            },
+        };
+        let full_name = format!("{}.{}", class, call.name.name);
+
+        let signature = match self.known.get(&class) {
+            Some(subroutines) => match subroutines.get(&call.name.name) {
+                Some(signature) => *signature,
+                None => {
+                    self.error(call.name.span, format!("Undeclared subroutine '{}'", full_name));
+                    return None
+                }
+            },
+            None => {
+                // Classes are capitalized, so this is more likely a variable
+                if !object && class.starts_with(|c: char| c.is_lowercase()) {
+                    self.error(call.receiver.as_ref().unwrap().span,
+                               format!("Undeclared variable '{}'", class));
+                }
+                return None
+            }
+        };
+
+        match (call.receiver.is_some(), object, signature.kind) {
+            (false, _, SubroutineKind::Method) if subroutine.kind == SubroutineKind::Function => {
+                self.error(call.name.span, format!("Method '{}' called from function '{}', which has no \
+                                                    object", call.name.name, subroutine.name.name))
+            }
+            (false, _, SubroutineKind::Function) | (false, _, SubroutineKind::Constructor) => {
+                self.error(call.name.span, format!("'{}' isn't a method, call it as '{}'", call.name.name, full_name))
+            }
+            (true, true, SubroutineKind::Function) | (true, true, SubroutineKind::Constructor) => {
+                self.error(call.span(), format!("'{}' isn't a method, call it as '{}'", call.name.name, full_name))
+            }
+            (true, false, SubroutineKind::Method) => {
+                self.error(call.span(), format!("'{}' is a method, call it on an object", full_name))
+            }
+            _ => {}
+        }
+
+        if call.args.len() != signature.params {
+            self.error(call.name.span, format!("'{}' takes {} argument{}, but {} {} given",
+                                               full_name, signature.params, plural(signature.params),
+                                               call.args.len(), if call.args.len() == 1 { "was" } else { "were" }));
+        }
+
+        Some((full_name, signature.void))
+    }
+}
+
+/// Do the statements always return?
+fn returns(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match statement.kind {
+        StatementKind::Return(_) => true,
+        StatementKind::If { ref then, otherwise: Some(ref otherwise), .. } => returns(then) && returns(otherwise),
+        _ => false,
+    })
+}
+
+fn article(ty: &Type) -> &'static str {
+    match *ty {
+        Type::Int => "an",
+        _ => "a",
+    }
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 { "" } else { "s" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parse;
+
+    fn errors(sources: &[&str]) -> Vec<String> {
+        let classes: Vec<Class> = sources.iter().map(|s| parse(s).unwrap()).collect();
+        check(&classes).into_iter().flat_map(|errors| errors).map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(errors(&["class Main {\n  field int x;\n  function void main() {\n    var int y;\n\
+                             \x20   let y = x + z;\n    let w = this;\n    return;\n  }\n}"]),
+                   ["line 5, column 13: Field 'x' used in function 'main', which has no object",
+                    "line 5, column 17: Undeclared variable 'z'",
+                    "line 6, column 9: Assignment to undeclared variable 'w', declare it with 'var', \
+                     'field' or 'static'",
+                    "line 6, column 13: 'this' used in function 'main', which has no object"]);
+    }
+
+    #[test]
+    fn calls() {
+        let main = "class Main {\n  function void main() {\n    var Point p;\n    let p = Point.new(1);\n\
+                    \x20   do p.move(1, 2);\n    do Point.move(1, 2);\n    do p.origin();\n    do draw();\n\
+                    \x20   let p = Output.printInt(Math.max(1, 2));\n    do Main.frobnicate();\n\
+                    \x20   do point.move();\n    return;\n  }\n  method void draw() { return; }\n}";
+        let point = "class Point {\n  constructor Point new(int x, int y) { return this; }\n\
+                     \x20 method void move(int dx, int dy) { return; }\n  function Point origin() { return null; }\n}";
+
+        assert_eq!(errors(&[main, point]),
+                   ["line 4, column 19: 'Point.new' takes 2 arguments, but 1 was given",
+                    "line 6, column 8: 'Point.move' is a method, call it on an object",
+                    "line 7, column 8: 'origin' isn't a method, call it as 'Point.origin'",
+                    "line 8, column 8: Method 'draw' called from function 'main', which has no object",
+                    "line 9, column 13: 'Output.printInt' is void, so its result can't be used",
+                    "line 10, column 13: Undeclared subroutine 'Main.frobnicate'",
+                    "line 11, column 8: Undeclared variable 'point'"]);
+    }
+
+    #[test]
+    fn returns() {
+        assert_eq!(errors(&["class Main {\n  function int f(int x) {\n    if (x) { return 1; } else { return; }\n\
+                             \x20   let x = 2;\n  }\n  function void g() {\n    while (true) { return 3; }\n  }\n\
+                             \x20 function int h(int x) {\n    if (x) { return 1; } else { return 2; }\n  }\n}"]),
+                   ["line 3, column 33: 'f' must return an int",
+                    "line 4, column 5: Unreachable code after 'return'",
+                    "line 7, column 27: 'g' is void, so it can't return a value",
+                    "line 8, column 3: Missing 'return' at the end of 'g'"]);
+    }
+
+    #[test]
+    fn declarations() {
+        assert_eq!(errors(&["class Main {\n  function void f() { return; }\n  method void f() { return; }\n}",
+                            "class Other {\n  function void g(int a, int a) { return; }\n}"]),
+                   ["line 3, column 15: Subroutine 'f' is already declared on line 2",
+                    "line 2, column 30: 'a' is already declared on line 2"]);
+    }
+}
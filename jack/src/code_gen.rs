@@ -0,0 +1,238 @@
+use ast::*;
+use error::Error;
+use symbols::{Kind, SymbolTable};
+
+/// Writes the VM code for a class
+struct CodeGen<'a> {
+    class: &'a Class,
+    symbols: SymbolTable,
+    output: String,
+    // Make the labels of a subroutine unique, like the reference compiler
+    ifs: usize,
+    whiles: usize,
+}
+
+/// Compile a class to VM code
+pub fn compile_class(class: &Class) -> Result<String, Error> {
+    let mut gen = CodeGen {
+        class: class,
+        symbols: try!(SymbolTable::for_class(class)),
+        output: String::new(),
+        ifs: 0,
+        whiles: 0,
+    };
+
+    for subroutine in &class.subroutines {
+        try!(gen.subroutine(subroutine));
+    }
+    Ok(gen.output)
+}
+
+impl<'a> CodeGen<'a> {
+    fn emit(&mut self, line: &str) {
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+
+    fn subroutine(&mut self, subroutine: &Subroutine) -> Result<(), Error> {
+        try!(self.symbols.start_subroutine(self.class, subroutine));
+        self.ifs = 0;
+        self.whiles = 0;
+
+        let locals = self.symbols.count(Kind::Local);
+        let header = format!("function {}.{} {}", self.class.name.name, subroutine.name.name, locals);
+        self.emit(&header);
+
+        match subroutine.kind {
+            SubroutineKind::Constructor => {
+                let fields = format!("push constant {}", self.symbols.count(Kind::Field));
+                self.emit(&fields);
+                self.emit("call Memory.alloc 1");
+                self.emit("pop pointer 0");
+            }
+            SubroutineKind::Method => {
+                self.emit("push argument 0");
+                self.emit("pop pointer 0");
+            }
+            SubroutineKind::Function => {}
+        }
+
+        self.statements(&subroutine.body)
+    }
+
+    fn statements(&mut self, statements: &[Statement]) -> Result<(), Error> {
+        for statement in statements {
+            try!(self.statement(statement));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), Error> {
+        match statement.kind {
+            StatementKind::Let { ref target, index: None, ref value } => {
+                try!(self.expression(value));
+                let variable = try!(self.variable(target));
+                self.emit(&format!("pop {}", variable));
+            }
+            StatementKind::Let { ref target, index: Some(ref index), ref value } => {
+                // The address is computed first, but the value may use
+                // `that` too, so it waits in temp 0
+                try!(self.element_address(target, index));
+                try!(self.expression(value));
+                self.emit("pop temp 0");
+                self.emit("pop pointer 1");
+                self.emit("push temp 0");
+                self.emit("pop that 0");
+            }
+            StatementKind::If { ref condition, ref then, ref otherwise } => {
+                let n = self.ifs;
+                self.ifs += 1;
+                try!(self.expression(condition));
+                self.emit(&format!("if-goto IF_TRUE{}", n));
+                self.emit(&format!("goto IF_FALSE{}", n));
+                self.emit(&format!("label IF_TRUE{}", n));
+                try!(self.statements(then));
+                match *otherwise {
+                    Some(ref otherwise) => {
+                        self.emit(&format!("goto IF_END{}", n));
+                        self.emit(&format!("label IF_FALSE{}", n));
+                        try!(self.statements(otherwise));
+                        self.emit(&format!("label IF_END{}", n));
+                    }
+                    None => self.emit(&format!("label IF_FALSE{}", n)),
+                }
+            }
+            StatementKind::While { ref condition, ref body } => {
+                let n = self.whiles;
+                self.whiles += 1;
+                self.emit(&format!("label WHILE_EXP{}", n));
+                try!(self.expression(condition));
+                self.emit("not");
+                self.emit(&format!("if-goto WHILE_END{}", n));
+                try!(self.statements(body));
+                self.emit(&format!("goto WHILE_EXP{}", n));
+                self.emit(&format!("label WHILE_END{}", n));
+            }
+            StatementKind::Do(ref call) => {
+                try!(self.call(call));
+                self.emit("pop temp 0");
+            }
+            StatementKind::Return(ref value) => {
+                match *value {
+                    Some(ref value) => try!(self.expression(value)),
+                    None => self.emit("push constant 0"),
+                }
+                self.emit("return");
+            }
+        }
+        Ok(())
+    }
+
+    // The segment and index of a variable, like `local 2`
+    fn variable(&self, name: &Ident) -> Result<String, Error> {
+        match self.symbols.lookup(&name.name) {
+            Some(symbol) => Ok(format!("{} {}", symbol.kind.segment(), symbol.index)),
+            None => Err(Error::new(name.span, format!("Undeclared variable '{}'", name.name))),
+        }
+    }
+
+    fn element_address(&mut self, array: &Ident, index: &Expr) -> Result<(), Error> {
+        let variable = try!(self.variable(array));
+        self.emit(&format!("push {}", variable));
+        try!(self.expression(index));
+        self.emit("add");
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<(), Error> {
+        try!(self.term(&expr.term));
+        for &(op, ref term) in &expr.rest {
+            try!(self.term(term));
+            self.emit(match op {
+                BinaryOp::Add => "add",
+                BinaryOp::Sub => "sub",
+                BinaryOp::Mul => "call Math.multiply 2",
+                BinaryOp::Div => "call Math.divide 2",
+                BinaryOp::And => "and",
+                BinaryOp::Or => "or",
+                BinaryOp::Lt => "lt",
+                BinaryOp::Gt => "gt",
+                BinaryOp::Eq => "eq",
+            });
+        }
+        Ok(())
+    }
+
+    fn term(&mut self, term: &Term) -> Result<(), Error> {
+        match term.kind {
+            TermKind::Int(value) => self.emit(&format!("push constant {}", value)),
+            TermKind::Str(ref s) => {
+                self.emit(&format!("push constant {}", s.chars().count()));
+                self.emit("call String.new 1");
+                for c in s.chars() {
+                    self.emit(&format!("push constant {}", c as u32));
+                    self.emit("call String.appendChar 2");
+                }
+            }
+            TermKind::True => {
+                self.emit("push constant 0");
+                self.emit("not");
+            }
+            TermKind::False | TermKind::Null => self.emit("push constant 0"),
+            TermKind::This => self.emit("push pointer 0"),
+            TermKind::Var(ref name) => {
+                let variable = try!(self.variable(&Ident { name: name.clone(), span: term.span }));
+                self.emit(&format!("push {}", variable));
+            }
+            TermKind::Index(ref array, ref index) => {
+                try!(self.element_address(array, index));
+                self.emit("pop pointer 1");
+                self.emit("push that 0");
+            }
+            TermKind::Call(ref call) => try!(self.call(call)),
+            TermKind::Paren(ref expr) => try!(self.expression(expr)),
+            TermKind::Unary(op, ref term) => {
+                try!(self.term(term));
+                self.emit(if op == UnaryOp::Neg { "neg" } else { "not" });
+            }
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, call: &Call) -> Result<(), Error> {
+        // A method's object is pushed before its arguments
+        let (class, object) = match call.receiver {
+            None => {
+                self.emit("push pointer 0");
+                (self.class.name.name.clone(), 1)
+            }
+            Some(ref receiver) => match self.symbols.lookup(&receiver.name).cloned() {
+                Some(symbol) => match symbol.ty {
+                    Type::Class(ref class) => {
+                        self.emit(&format!("push {} {}", symbol.kind.segment(), symbol.index));
+                        (class.clone(), 1)
+                    }
+                    ref ty => {
+                        return Err(Error::new(receiver.span, format!("'{}' is {} {}, which has no methods",
+                                                                     receiver.name, article(ty), ty)))
+                    }
+                },
+                // Otherwise it names a class
+                None => (receiver.name.clone(), 0),
+            },
+        };
+
+        for arg in &call.args {
+            try!(self.expression(arg));
+        }
+        self.emit(&format!("call {}.{} {}", class, call.name.name, call.args.len() + object));
+        Ok(())
+    }
+}
+
+fn article(ty: &Type) -> &'static str {
+    match *ty {
+        Type::Int => "an",
+        _ => "a",
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+
+/// Where something is in a source file. Lines and columns are counted from
+/// 1, and `len` is the number of characters on the first line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+impl Span {
+    /// A span from the start of this one to the end of `other`, when it's on
+    /// the same line
+    pub fn to(&self, other: Span) -> Span {
+        if other.line != self.line || other.column < self.column {
+            return *self
+        }
+        Span { len: other.column + other.len - self.column, ..*self }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new<S: Into<String>>(span: Span, message: S) -> Error {
+        Error { span: span, message: message.into() }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.span.line, self.span.column, self.message)
+    }
+}
+
+impl Error {
+    /// The error with the line of `source` it's on, and its span underlined:
+    ///
+    /// ```text
+    /// error: Undeclared variable 'x'
+    ///  --> Main.jack:2:7
+    ///   |
+    /// 2 |   let x = 1;
+    ///   |       ^
+    /// ```
+    pub fn render(&self, file: &str, source: &str) -> String {
+        let line = source.lines().nth(self.span.line - 1).unwrap_or("");
+        let gutter = " ".repeat(self.span.line.to_string().len());
+        // Keep tabs so the marker lines up with the code above it
+        let indent: String = line.chars().take(self.span.column - 1)
+                                 .map(|c| if c == '\t' { '\t' } else { ' ' })
+                                 .collect();
+        let len = self.span.len.max(1).min(line.chars().count().saturating_sub(self.span.column - 1).max(1));
+
+        format!("error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+                self.message, gutter, file, self.span.line, self.span.column,
+                gutter, self.span.line, line, gutter, indent, "^".repeat(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let error = Error::new(Span { line: 2, column: 10, len: 5 }, "Undeclared variable 'count'");
+        assert_eq!(error.render("Main.jack", "class Main {\n\tlet x = count + 1;\n}"),
+                   "error: Undeclared variable 'count'\n\
+                    \x20--> Main.jack:2:10\n\
+                    \x20 |\n\
+                    2 | \tlet x = count + 1;\n\
+                    \x20 | \t        ^^^^^\n");
+
+        // At the end of the file
+        let error = Error::new(Span { line: 10, column: 1, len: 1 }, "Expected '}'");
+        assert_eq!(error.render("Main.jack", ""),
+                   "error: Expected '}'\n  --> Main.jack:10:1\n   |\n10 | \n   | ^\n");
+    }
+}
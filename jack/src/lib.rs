@@ -0,0 +1,126 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub use checker::check;
+pub use code_gen::compile_class;
+pub use error::{Error, Span};
+pub use parser::parse;
+pub use symbols::{Kind, Symbol, SymbolTable};
+pub use tokenizer::{tokenize, Keyword, Token, TokenKind};
+pub use xml::{tokens_xml, tree_xml};
+
+pub mod ast;
+mod checker;
+mod code_gen;
+mod error;
+mod parser;
+mod symbols;
+mod tokenizer;
+mod xml;
+
+/// What to produce from Jack source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// VM code, as in project 11
+    Vm,
+    /// The tokens as XML, as in project 10
+    Tokens,
+    /// The parse tree as XML, as in project 10
+    Tree,
+}
+
+impl Mode {
+    /// The file the output for `jack` goes to: `Main.vm`, `MainT.xml` or `Main.xml`
+    pub fn output_path(&self, jack: &Path) -> PathBuf {
+        let name = jack.file_stem().unwrap().to_string_lossy();
+        match *self {
+            Mode::Vm => jack.with_file_name(format!("{}.vm", name)),
+            Mode::Tokens => jack.with_file_name(format!("{}T.xml", name)),
+            Mode::Tree => jack.with_file_name(format!("{}.xml", name)),
+        }
+    }
+}
+
+/// Compile the source of a Jack class, checking its code on its own, so
+/// only calls to itself and the standard library are checked
+pub fn compile(source: &str, mode: Mode) -> Result<String, Error> {
+    match mode {
+        Mode::Vm => {
+            let classes = [try!(parse(source))];
+            if let Some(error) = check(&classes).remove(0).into_iter().next() {
+                return Err(error)
+            }
+            compile_class(&classes[0])
+        }
+        Mode::Tokens => Ok(tokens_xml(&try!(tokenize(source)))),
+        Mode::Tree => Ok(tree_xml(&try!(parse(source)))),
+    }
+}
+
+/// Compile a .jack file, or every .jack file in a directory, giving the
+/// path of each output and its contents. The classes are checked together,
+/// and every error is reported with the code it's in.
+pub fn compile_path<P: AsRef<Path>>(path: P, mode: Mode) -> Result<Vec<(PathBuf, String)>, String> {
+    let mut sources = vec![];
+    for file in try!(jack_files(path.as_ref())) {
+        let mut source = String::new();
+        try!(File::open(&file).and_then(|mut f| f.read_to_string(&mut source))
+                              .map_err(|e| format!("Failed to read '{}': {}", file.display(), e)));
+        sources.push((file, source));
+    }
+
+    if mode != Mode::Vm {
+        return sources.iter().map(|&(ref file, ref source)| {
+            let output = try!(compile(source, mode).map_err(|e| e.render(&file.to_string_lossy(), source)));
+            Ok((mode.output_path(file), output))
+        }).collect()
+    }
+
+    let (mut parsed, mut classes, mut errors) = (vec![], vec![], vec![]);
+    for &(ref file, ref source) in &sources {
+        match parse(source) {
+            Ok(class) => {
+                parsed.push((file, source));
+                classes.push(class);
+            }
+            Err(e) => errors.push(e.render(&file.to_string_lossy(), source)),
+        }
+    }
+
+    let mut outputs = vec![];
+    for (((file, source), class), class_errors) in parsed.into_iter().zip(&classes).zip(check(&classes)) {
+        let render = |e: &Error| e.render(&file.to_string_lossy(), source);
+        if !class_errors.is_empty() {
+            errors.extend(class_errors.iter().map(&render));
+            continue
+        }
+        match compile_class(class) {
+            Ok(output) => outputs.push((mode.output_path(file), output)),
+            Err(e) => errors.push(render(&e)),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("\n"))
+    }
+    Ok(outputs)
+}
+
+/// The .jack files at `path`, a file or a directory of them
+pub fn jack_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()])
+    }
+
+    let entries = try!(fs::read_dir(path).map_err(|e| format!("Failed to read '{}': {}", path.display(), e)));
+    let mut files: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|e| e.path()))
+                                         .filter(|p| p.extension().map_or(false, |ext| ext == "jack"))
+                                         .collect();
+    if files.is_empty() {
+        return Err(format!("No .jack files in '{}'", path.display()))
+    }
+
+    files.sort();
+    Ok(files)
+}
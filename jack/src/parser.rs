@@ -0,0 +1,434 @@
+use ast::*;
+use error::{Error, Span};
+use tokenizer::{tokenize, Keyword, Token, TokenKind};
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    // Where the source ends, for errors about missing tokens
+    end: Span,
+}
+
+/// Parse the source of a Jack file, which holds a single class
+pub fn parse(source: &str) -> Result<Class, Error> {
+    let tokens = try!(tokenize(source));
+    let lines = source.lines().count().max(1);
+    let end = Span { line: lines, column: source.lines().last().map_or(0, |l| l.chars().count()) + 1, len: 1 };
+
+    let mut parser = Parser { tokens: tokens, next: 0, end: end };
+    let class = try!(parser.class());
+    match parser.peek() {
+        Some(token) => Err(Error::new(token.span, format!("Unexpected {} after the class", token.kind))),
+        None => Ok(class),
+    }
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn peek_kind(&self) -> Option<&TokenKind> {
+        self.peek().map(|t| &t.kind)
+    }
+
+    fn peek_is_symbol(&self, symbol: char) -> bool {
+        self.peek_kind() == Some(&TokenKind::Symbol(symbol))
+    }
+
+    fn peek_is_keyword(&self, keyword: Keyword) -> bool {
+        self.peek_kind() == Some(&TokenKind::Keyword(keyword))
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn unexpected<T>(&self, what: &str) -> Result<T, Error> {
+        match self.peek() {
+            Some(token) => Err(Error::new(token.span, format!("Expected {}, found {}", what, token.kind))),
+            None => Err(Error::new(self.end, format!("Expected {}, found the end of the file", what))),
+        }
+    }
+
+    fn symbol(&mut self, symbol: char) -> Result<Span, Error> {
+        if !self.peek_is_symbol(symbol) {
+            return self.unexpected(&format!("'{}'", symbol))
+        }
+        Ok(self.advance().unwrap().span)
+    }
+
+    fn keyword(&mut self, keyword: Keyword) -> Result<Span, Error> {
+        if !self.peek_is_keyword(keyword) {
+            return self.unexpected(&format!("'{}'", keyword.as_str()))
+        }
+        Ok(self.advance().unwrap().span)
+    }
+
+    fn identifier(&mut self, what: &str) -> Result<Ident, Error> {
+        let name = match self.peek_kind() {
+            Some(&TokenKind::Identifier(ref name)) => name.clone(),
+            _ => return self.unexpected(what),
+        };
+        let span = self.advance().unwrap().span;
+        Ok(Ident { name: name, span: span })
+    }
+
+    fn class(&mut self) -> Result<Class, Error> {
+        try!(self.keyword(Keyword::Class));
+        let name = try!(self.identifier("a class name"));
+        try!(self.symbol('{'));
+
+        let mut vars = vec![];
+        while self.peek_is_keyword(Keyword::Static) || self.peek_is_keyword(Keyword::Field) {
+            vars.push(try!(self.class_var_dec()));
+        }
+
+        let mut subroutines = vec![];
+        while !self.peek_is_symbol('}') {
+            subroutines.push(try!(self.subroutine()));
+        }
+        try!(self.symbol('}'));
+
+        Ok(Class { name: name, vars: vars, subroutines: subroutines })
+    }
+
+    fn class_var_dec(&mut self) -> Result<ClassVarDec, Error> {
+        let kind = match self.advance().map(|t| t.kind) {
+            Some(TokenKind::Keyword(Keyword::Static)) => ClassVarKind::Static,
+            _ => ClassVarKind::Field,
+        };
+        let (ty, names) = try!(self.declaration());
+        Ok(ClassVarDec { kind: kind, ty: ty, names: names })
+    }
+
+    // A type and the comma separated names declared with it, up to ';'
+    fn declaration(&mut self) -> Result<(Type, Vec<Ident>), Error> {
+        let ty = try!(self.ty());
+        let mut names = vec![try!(self.identifier("a variable name"))];
+        while self.peek_is_symbol(',') {
+            self.advance();
+            names.push(try!(self.identifier("a variable name")));
+        }
+        try!(self.symbol(';'));
+        Ok((ty, names))
+    }
+
+    fn ty(&mut self) -> Result<Type, Error> {
+        let ty = match self.peek_kind() {
+            Some(&TokenKind::Keyword(Keyword::Int)) => Type::Int,
+            Some(&TokenKind::Keyword(Keyword::Char)) => Type::Char,
+            Some(&TokenKind::Keyword(Keyword::Boolean)) => Type::Boolean,
+            Some(&TokenKind::Identifier(ref name)) => Type::Class(name.clone()),
+            _ => return self.unexpected("a type"),
+        };
+        self.advance();
+        Ok(ty)
+    }
+
+    fn subroutine(&mut self) -> Result<Subroutine, Error> {
+        let kind = match self.peek_kind() {
+            Some(&TokenKind::Keyword(Keyword::Constructor)) => SubroutineKind::Constructor,
+            Some(&TokenKind::Keyword(Keyword::Function)) => SubroutineKind::Function,
+            Some(&TokenKind::Keyword(Keyword::Method)) => SubroutineKind::Method,
+            _ => return self.unexpected("'constructor', 'function', 'method' or '}'"),
+        };
+        self.advance();
+
+        let return_type = if self.peek_is_keyword(Keyword::Void) {
+            self.advance();
+            None
+        } else {
+            Some(try!(self.ty()))
+        };
+        let name = try!(self.identifier("a subroutine name"));
+
+        try!(self.symbol('('));
+        let mut params = vec![];
+        if !self.peek_is_symbol(')') {
+            loop {
+                let ty = try!(self.ty());
+                params.push((ty, try!(self.identifier("a parameter name"))));
+                if !self.peek_is_symbol(',') {
+                    break
+                }
+                self.advance();
+            }
+        }
+        try!(self.symbol(')'));
+
+        try!(self.symbol('{'));
+        let mut locals = vec![];
+        while self.peek_is_keyword(Keyword::Var) {
+            self.advance();
+            let (ty, names) = try!(self.declaration());
+            locals.push(VarDec { ty: ty, names: names });
+        }
+        let body = try!(self.statements());
+        let end = try!(self.symbol('}'));
+
+        Ok(Subroutine {
+            kind: kind,
+            return_type: return_type,
+            name: name,
+            params: params,
+            locals: locals,
+            body: body,
+            end: end,
+        })
+    }
+
+    // Statements up to a closing brace
+    fn statements(&mut self) -> Result<Vec<Statement>, Error> {
+        let mut statements = vec![];
+        while !self.peek_is_symbol('}') {
+            statements.push(try!(self.statement()));
+        }
+        Ok(statements)
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, Error> {
+        try!(self.symbol('{'));
+        let statements = try!(self.statements());
+        try!(self.symbol('}'));
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, Error> {
+        let keyword = match self.peek_kind() {
+            Some(&TokenKind::Keyword(keyword)) => keyword,
+            _ => return self.unexpected("a statement"),
+        };
+        let span = match keyword {
+            Keyword::Let | Keyword::If | Keyword::While | Keyword::Do | Keyword::Return => {
+                self.advance().unwrap().span
+            }
+            _ => return self.unexpected("a statement"),
+        };
+
+        let kind = match keyword {
+            Keyword::Let => {
+                let target = try!(self.identifier("a variable name"));
+                let index = if self.peek_is_symbol('[') {
+                    self.advance();
+                    let index = try!(self.expression());
+                    try!(self.symbol(']'));
+                    Some(index)
+                } else {
+                    None
+                };
+                try!(self.symbol('='));
+                let value = try!(self.expression());
+                try!(self.symbol(';'));
+                StatementKind::Let { target: target, index: index, value: value }
+            }
+            Keyword::If => {
+                let condition = try!(self.condition());
+                let then = try!(self.block());
+                let otherwise = if self.peek_is_keyword(Keyword::Else) {
+                    self.advance();
+                    Some(try!(self.block()))
+                } else {
+                    None
+                };
+                StatementKind::If { condition: condition, then: then, otherwise: otherwise }
+            }
+            Keyword::While => {
+                let condition = try!(self.condition());
+                StatementKind::While { condition: condition, body: try!(self.block()) }
+            }
+            Keyword::Do => {
+                let name = try!(self.identifier("a subroutine call"));
+                let call = try!(self.call(name));
+                try!(self.symbol(';'));
+                StatementKind::Do(call)
+            }
+            _ => {
+                let value = if self.peek_is_symbol(';') { None } else { Some(try!(self.expression())) };
+                try!(self.symbol(';'));
+                StatementKind::Return(value)
+            }
+        };
+
+        Ok(Statement { kind: kind, span: span })
+    }
+
+    fn condition(&mut self) -> Result<Expr, Error> {
+        try!(self.symbol('('));
+        let condition = try!(self.expression());
+        try!(self.symbol(')'));
+        Ok(condition)
+    }
+
+    fn expression(&mut self) -> Result<Expr, Error> {
+        let term = try!(self.term());
+        let mut rest = vec![];
+        loop {
+            let op = match self.peek_kind() {
+                Some(&TokenKind::Symbol(symbol)) => BinaryOp::parse(symbol),
+                _ => None,
+            };
+            match op {
+                Some(op) => {
+                    self.advance();
+                    rest.push((op, try!(self.term())));
+                }
+                None => return Ok(Expr { term: term, rest: rest }),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Term, Error> {
+        let token = match self.advance() {
+            Some(token) => token,
+            None => {
+                self.next -= 1;
+                return self.unexpected("an expression")
+            }
+        };
+
+        let kind = match token.kind {
+            TokenKind::Int(value) => TermKind::Int(value),
+            TokenKind::Str(ref s) => TermKind::Str(s.clone()),
+            TokenKind::Keyword(Keyword::True) => TermKind::True,
+            TokenKind::Keyword(Keyword::False) => TermKind::False,
+            TokenKind::Keyword(Keyword::Null) => TermKind::Null,
+            TokenKind::Keyword(Keyword::This) => TermKind::This,
+            TokenKind::Symbol('(') => {
+                let expr = try!(self.expression());
+                let close = try!(self.symbol(')'));
+                return Ok(Term { kind: TermKind::Paren(Box::new(expr)), span: token.span.to(close) })
+            }
+            TokenKind::Symbol('-') | TokenKind::Symbol('~') => {
+                let op = if token.kind == TokenKind::Symbol('-') { UnaryOp::Neg } else { UnaryOp::Not };
+                let term = try!(self.term());
+                let span = token.span.to(term.span);
+                return Ok(Term { kind: TermKind::Unary(op, Box::new(term)), span: span })
+            }
+            TokenKind::Identifier(ref name) => {
+                let ident = Ident { name: name.clone(), span: token.span };
+                if self.peek_is_symbol('[') {
+                    self.advance();
+                    let index = try!(self.expression());
+                    let close = try!(self.symbol(']'));
+                    return Ok(Term { kind: TermKind::Index(ident, Box::new(index)), span: token.span.to(close) })
+                } else if self.peek_is_symbol('(') || self.peek_is_symbol('.') {
+                    let call = try!(self.call(ident));
+                    let span = call.span();
+                    return Ok(Term { kind: TermKind::Call(call), span: span })
+                }
+                TermKind::Var(name.clone())
+            }
+            _ => {
+                self.next -= 1;
+                return self.unexpected("an expression")
+            }
+        };
+
+        Ok(Term { kind: kind, span: token.span })
+    }
+
+    // The rest of a call starting with `first`, the receiver or the name
+    fn call(&mut self, first: Ident) -> Result<Call, Error> {
+        let (receiver, name) = if self.peek_is_symbol('.') {
+            self.advance();
+            (Some(first), try!(self.identifier("a subroutine name")))
+        } else {
+            (None, first)
+        };
+
+        try!(self.symbol('('));
+        let mut args = vec![];
+        if !self.peek_is_symbol(')') {
+            loop {
+                args.push(try!(self.expression()));
+                if !self.peek_is_symbol(',') {
+                    break
+                }
+                self.advance();
+            }
+        }
+        try!(self.symbol(')'));
+
+        Ok(Call { receiver: receiver, name: name, args: args })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_err(source: &str) -> String {
+        parse(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn class() {
+        let class = parse("class Point {\n\
+                             field int x, y;\n\
+                             static Point origin;\n\
+                             method int dx(Point other) { var int d; let d = x - other.getX(); return d; }\n\
+                           }").unwrap();
+        assert_eq!(class.name.name, "Point");
+        assert_eq!(class.vars[0].names.iter().map(|n| &n.name[..]).collect::<Vec<_>>(), ["x", "y"]);
+        assert_eq!(class.vars[1].ty, Type::Class("Point".to_string()));
+
+        let method = &class.subroutines[0];
+        assert_eq!((method.kind, method.return_type.clone()), (SubroutineKind::Method, Some(Type::Int)));
+        assert_eq!(method.params[0].0, Type::Class("Point".to_string()));
+        assert_eq!(method.body.len(), 2);
+        match method.body[0].kind {
+            StatementKind::Let { ref value, .. } => {
+                assert_eq!(value.rest[0].0, BinaryOp::Sub);
+                match value.rest[0].1.kind {
+                    TermKind::Call(ref call) => {
+                        assert_eq!(call.receiver.as_ref().map(|r| &r.name[..]), Some("other"));
+                        assert_eq!(call.name.name, "getX");
+                    }
+                    ref term => panic!("Unexpected term: {:?}", term),
+                }
+            }
+            ref statement => panic!("Unexpected statement: {:?}", statement),
+        }
+        assert_eq!(method.end, Span { line: 4, column: 77, len: 1 });
+    }
+
+    #[test]
+    fn terms() {
+        let class = parse("class A { function void f() { return -a[i + 1] * (~b); } }").unwrap();
+        let value = match class.subroutines[0].body[0].kind {
+            StatementKind::Return(Some(ref value)) => value.clone(),
+            ref statement => panic!("Unexpected statement: {:?}", statement),
+        };
+        assert_eq!(value.span(), Span { line: 1, column: 38, len: 16 });
+        match value.term.kind {
+            TermKind::Unary(UnaryOp::Neg, ref term) => match term.kind {
+                TermKind::Index(ref array, _) => assert_eq!(array.name, "a"),
+                ref term => panic!("Unexpected term: {:?}", term),
+            },
+            ref term => panic!("Unexpected term: {:?}", term),
+        }
+        match value.rest[0] {
+            (BinaryOp::Mul, Term { kind: TermKind::Paren(_), .. }) => {}
+            ref rest => panic!("Unexpected term: {:?}", rest),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse_err("class Main {\n  function void main() {\n    let x = 1\n  }\n}"),
+                   "line 4, column 3: Expected ';', found '}'");
+        assert_eq!(parse_err("class Main {\n  function void main() {\n    let x = ;"),
+                   "line 3, column 13: Expected an expression, found ';'");
+        assert_eq!(parse_err("class Main { function void main() { x = 1; } }"),
+                   "line 1, column 37: Expected a statement, found 'x'");
+        assert_eq!(parse_err("class Main { var int x; }"),
+                   "line 1, column 14: Expected 'constructor', 'function', 'method' or '}', found 'var'");
+        assert_eq!(parse_err("class Main {"),
+                   "line 1, column 13: Expected 'constructor', 'function', 'method' or '}', \
+                    found the end of the file");
+        assert_eq!(parse_err("class Main { } class"), "line 1, column 16: Unexpected 'class' after the class");
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use ast::{Class, ClassVarKind, Ident, Subroutine, SubroutineKind, Type};
+use error::{Error, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind { Static, Field, Argument, Local }
+
+impl Kind {
+    /// The VM segment the variables live in
+    pub fn segment(&self) -> &'static str {
+        match *self {
+            Kind::Static => "static",
+            Kind::Field => "this",
+            Kind::Argument => "argument",
+            Kind::Local => "local",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub kind: Kind,
+    pub ty: Type,
+    pub index: u16,
+    /// Where it's declared, or the subroutine's name for a method's `this`
+    pub span: Span,
+}
+
+/// The variables in scope, the class's and those of the subroutine being
+/// compiled, which hide them
+pub struct SymbolTable {
+    class: HashMap<String, Symbol>,
+    subroutine: HashMap<String, Symbol>,
+    counts: [u16; 4],
+}
+
+impl SymbolTable {
+    pub fn for_class(class: &Class) -> Result<SymbolTable, Error> {
+        let mut table = SymbolTable { class: HashMap::new(), subroutine: HashMap::new(), counts: [0; 4] };
+        for dec in &class.vars {
+            let kind = if dec.kind == ClassVarKind::Static { Kind::Static } else { Kind::Field };
+            for name in &dec.names {
+                try!(table.define(name, &dec.ty, kind));
+            }
+        }
+        Ok(table)
+    }
+
+    /// Replace the subroutine scope with the arguments and locals of
+    /// `subroutine`. A method's object is its argument 0.
+    pub fn start_subroutine(&mut self, class: &Class, subroutine: &Subroutine) -> Result<(), Error> {
+        self.subroutine.clear();
+        self.counts[Kind::Argument as usize] = 0;
+        self.counts[Kind::Local as usize] = 0;
+
+        if subroutine.kind == SubroutineKind::Method {
+            let this = Ident { name: "this".to_string(), span: subroutine.name.span };
+            try!(self.define(&this, &Type::Class(class.name.name.clone()), Kind::Argument));
+        }
+        for &(ref ty, ref name) in &subroutine.params {
+            try!(self.define(name, ty, Kind::Argument));
+        }
+        for dec in &subroutine.locals {
+            for name in &dec.names {
+                try!(self.define(name, &dec.ty, Kind::Local));
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &Ident, ty: &Type, kind: Kind) -> Result<(), Error> {
+        let scope = match kind {
+            Kind::Static | Kind::Field => &mut self.class,
+            Kind::Argument | Kind::Local => &mut self.subroutine,
+        };
+        if let Some(previous) = scope.get(&name.name) {
+            return Err(Error::new(name.span, format!("'{}' is already declared on line {}",
+                                                     name.name, previous.span.line)))
+        }
+
+        let index = self.counts[kind as usize];
+        self.counts[kind as usize] += 1;
+        scope.insert(name.name.clone(), Symbol { kind: kind, ty: ty.clone(), index: index, span: name.span });
+        Ok(())
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.subroutine.get(name).or_else(|| self.class.get(name))
+    }
+
+    /// How many variables of `kind` are declared
+    pub fn count(&self, kind: Kind) -> u16 {
+        self.counts[kind as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parse;
+
+    #[test]
+    fn scopes() {
+        let class = parse("class P { field int x, y; static int n;\n\
+                           method void f(int a, P x) { var char c; return; } }").unwrap();
+        let mut table = SymbolTable::for_class(&class).unwrap();
+        assert_eq!(table.lookup("y").map(|s| (s.kind, s.index)), Some((Kind::Field, 1)));
+        assert_eq!(table.count(Kind::Field), 2);
+
+        table.start_subroutine(&class, &class.subroutines[0]).unwrap();
+        assert_eq!(table.lookup("this").map(|s| (s.kind, s.index)), Some((Kind::Argument, 0)));
+        assert_eq!(table.lookup("a").map(|s| (s.kind, s.index)), Some((Kind::Argument, 1)));
+        // Parameters hide fields
+        assert_eq!(table.lookup("x").map(|s| (s.kind, s.ty.clone())),
+                   Some((Kind::Argument, Type::Class("P".to_string()))));
+        assert_eq!(table.lookup("c").map(|s| (s.kind, s.index)), Some((Kind::Local, 0)));
+        assert_eq!(table.lookup("n").map(|s| s.kind), Some(Kind::Static));
+        assert!(table.lookup("z").is_none());
+    }
+
+    #[test]
+    fn declared_twice() {
+        let class = parse("class P {\n  field int x;\n  static boolean x;\n}").unwrap();
+        assert_eq!(SymbolTable::for_class(&class).err().map(|e| e.to_string()),
+                   Some("line 3, column 18: 'x' is already declared on line 2".to_string()));
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt;
+
+use error::{Error, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keyword {
+    Class, Constructor, Function, Method, Field, Static, Var, Int, Char, Boolean, Void,
+    True, False, Null, This, Let, Do, If, Else, While, Return,
+}
+
+static KEYWORDS: &'static [(&'static str, Keyword)] = &[
+    ("class", Keyword::Class),
+    ("constructor", Keyword::Constructor),
+    ("function", Keyword::Function),
+    ("method", Keyword::Method),
+    ("field", Keyword::Field),
+    ("static", Keyword::Static),
+    ("var", Keyword::Var),
+    ("int", Keyword::Int),
+    ("char", Keyword::Char),
+    ("boolean", Keyword::Boolean),
+    ("void", Keyword::Void),
+    ("true", Keyword::True),
+    ("false", Keyword::False),
+    ("null", Keyword::Null),
+    ("this", Keyword::This),
+    ("let", Keyword::Let),
+    ("do", Keyword::Do),
+    ("if", Keyword::If),
+    ("else", Keyword::Else),
+    ("while", Keyword::While),
+    ("return", Keyword::Return),
+];
+
+const SYMBOLS: &'static str = "{}()[].,;+-*/&|<>=~";
+
+impl Keyword {
+    fn parse(s: &str) -> Option<Keyword> {
+        KEYWORDS.iter().find(|&&(name, _)| name == s).map(|&(_, keyword)| keyword)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        KEYWORDS.iter().find(|&&(_, keyword)| keyword == *self).unwrap().0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Keyword(Keyword),
+    Symbol(char),
+    Int(u16),
+    /// A string constant, without its quotes
+    Str(String),
+    Identifier(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TokenKind::Keyword(keyword) => write!(f, "'{}'", keyword.as_str()),
+            TokenKind::Symbol(symbol) => write!(f, "'{}'", symbol),
+            TokenKind::Int(value) => write!(f, "'{}'", value),
+            TokenKind::Str(ref s) => write!(f, "\"{}\"", s),
+            TokenKind::Identifier(ref name) => write!(f, "'{}'", name),
+        }
+    }
+}
+
+/// Split Jack source into tokens, skipping whitespace and comments
+pub fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+    let (mut line, mut line_start) = (1, 0);
+    // Columns count characters, not bytes
+    let column = |offset: usize, line_start: usize| source[line_start..offset].chars().count() + 1;
+
+    while let Some((offset, c)) = chars.next() {
+        let span = Span { line: line, column: column(offset, line_start), len: 1 };
+        let rest = &source[offset..];
+
+        if c == '\n' {
+            line += 1;
+            line_start = offset + 1;
+        } else if c.is_whitespace() {
+            continue
+        } else if rest.starts_with("//") {
+            while chars.peek().map_or(false, |&(_, c)| c != '\n') {
+                chars.next();
+            }
+        } else if rest.starts_with("/*") {
+            chars.next();
+            let mut closed = false;
+            while let Some((offset, c)) = chars.next() {
+                if c == '\n' {
+                    line += 1;
+                    line_start = offset + 1;
+                } else if c == '*' && chars.peek().map_or(false, |&(_, c)| c == '/') {
+                    chars.next();
+                    closed = true;
+                    break
+                }
+            }
+            if !closed {
+                return Err(Error::new(span, "Unterminated comment"))
+            }
+        } else if c == '"' {
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\n')) | None => return Err(Error::new(span, "Unterminated string constant")),
+                    Some((_, c)) => text.push(c),
+                }
+            }
+            let len = text.chars().count() + 2;
+            tokens.push(Token { kind: TokenKind::Str(text), span: Span { len: len, ..span } });
+        } else if SYMBOLS.contains(c) {
+            tokens.push(Token { kind: TokenKind::Symbol(c), span: span });
+        } else if c.is_digit(10) || c.is_alphabetic() || c == '_' {
+            let mut end = offset + c.len_utf8();
+            while let Some(&(offset, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break
+                }
+                end = offset + c.len_utf8();
+                chars.next();
+            }
+            let word = &source[offset..end];
+            let span = Span { len: word.chars().count(), ..span };
+
+            let kind = if c.is_digit(10) {
+                match word.parse::<u16>() {
+                    Ok(value) if value <= 32767 => TokenKind::Int(value),
+                    _ if word.chars().all(|c| c.is_digit(10)) => {
+                        return Err(Error::new(span, format!("Integer constant out of range: {}", word)))
+                    }
+                    _ => return Err(Error::new(span, format!("Invalid integer constant: '{}'", word))),
+                }
+            } else {
+                match Keyword::parse(word) {
+                    Some(keyword) => TokenKind::Keyword(keyword),
+                    None => TokenKind::Identifier(word.to_string()),
+                }
+            };
+            tokens.push(Token { kind: kind, span: span });
+        } else {
+            return Err(Error::new(span, format!("Unexpected character: '{}'", c)))
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::TokenKind::*;
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        tokenize(source).unwrap().into_iter().map(|t| t.kind).collect()
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(kinds("let x = x + 32767; // comment\n/** doc\n */ do Output.printString(\"a, b\");"),
+                   vec![Keyword(super::Keyword::Let), Identifier("x".to_string()), Symbol('='),
+                        Identifier("x".to_string()), Symbol('+'), Int(32767), Symbol(';'),
+                        Keyword(super::Keyword::Do), Identifier("Output".to_string()), Symbol('.'),
+                        Identifier("printString".to_string()), Symbol('('), Str("a, b".to_string()),
+                        Symbol(')'), Symbol(';')]);
+    }
+
+    #[test]
+    fn spans() {
+        let tokens = tokenize("class Main {\n  field int _x2;\n}").unwrap();
+        assert_eq!(tokens[5].kind, Identifier("_x2".to_string()));
+        assert_eq!(tokens[5].span, Span { line: 2, column: 13, len: 3 });
+        assert_eq!(tokens[7].span, Span { line: 3, column: 1, len: 1 });
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(tokenize("let x = 32768;").unwrap_err().to_string(),
+                   "line 1, column 9: Integer constant out of range: 32768");
+        assert_eq!(tokenize("\n  \"open").unwrap_err().to_string(),
+                   "line 2, column 3: Unterminated string constant");
+        assert_eq!(tokenize("/* never closed").unwrap_err().to_string(),
+                   "line 1, column 1: Unterminated comment");
+        assert_eq!(tokenize("let x = 1 ? 2;").unwrap_err().to_string(),
+                   "line 1, column 11: Unexpected character: '?'");
+        assert_eq!(tokenize("let 2x = 1;").unwrap_err().to_string(),
+                   "line 1, column 5: Invalid integer constant: '2x'");
+    }
+}
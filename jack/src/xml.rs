@@ -0,0 +1,277 @@
+use ast::*;
+use tokenizer::{Keyword, Token, TokenKind};
+
+// Writes elements in the layout of the nand2tetris comparison files
+struct Xml {
+    output: String,
+    depth: usize,
+}
+
+impl Xml {
+    fn open(&mut self, tag: &str) {
+        self.line(&format!("<{}>", tag));
+        self.depth += 1;
+    }
+
+    fn close(&mut self, tag: &str) {
+        self.depth -= 1;
+        self.line(&format!("</{}>", tag));
+    }
+
+    fn token(&mut self, tag: &str, text: &str) {
+        self.line(&format!("<{}> {} </{}>", tag, escape(text), tag));
+    }
+
+    fn keyword(&mut self, keyword: Keyword) {
+        self.token("keyword", keyword.as_str());
+    }
+
+    fn symbol(&mut self, symbol: char) {
+        self.token("symbol", &symbol.to_string());
+    }
+
+    fn identifier(&mut self, ident: &Ident) {
+        self.token("identifier", &ident.name);
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.output.push_str("  ");
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The tokens as in the project 10 `T.xml` files
+pub fn tokens_xml(tokens: &[Token]) -> String {
+    let mut xml = Xml { output: String::new(), depth: 0 };
+    xml.line("<tokens>");
+    for token in tokens {
+        match token.kind {
+            TokenKind::Keyword(keyword) => xml.keyword(keyword),
+            TokenKind::Symbol(symbol) => xml.symbol(symbol),
+            TokenKind::Int(value) => xml.token("integerConstant", &value.to_string()),
+            TokenKind::Str(ref s) => xml.token("stringConstant", s),
+            TokenKind::Identifier(ref name) => xml.token("identifier", name),
+        }
+    }
+    xml.line("</tokens>");
+    xml.output
+}
+
+/// The parse tree as in the project 10 `.xml` files
+pub fn tree_xml(class: &Class) -> String {
+    let mut xml = Xml { output: String::new(), depth: 0 };
+    xml.open("class");
+    xml.keyword(Keyword::Class);
+    xml.identifier(&class.name);
+    xml.symbol('{');
+    for dec in &class.vars {
+        xml.open("classVarDec");
+        xml.keyword(if dec.kind == ClassVarKind::Static { Keyword::Static } else { Keyword::Field });
+        write_names(&mut xml, &dec.ty, &dec.names);
+        xml.close("classVarDec");
+    }
+    for subroutine in &class.subroutines {
+        write_subroutine(&mut xml, subroutine);
+    }
+    xml.symbol('}');
+    xml.close("class");
+    xml.output
+}
+
+fn write_type(xml: &mut Xml, ty: &Type) {
+    match *ty {
+        Type::Int => xml.keyword(Keyword::Int),
+        Type::Char => xml.keyword(Keyword::Char),
+        Type::Boolean => xml.keyword(Keyword::Boolean),
+        Type::Class(ref name) => xml.token("identifier", name),
+    }
+}
+
+// The type and names of a declaration, up to its ';'
+fn write_names(xml: &mut Xml, ty: &Type, names: &[Ident]) {
+    write_type(xml, ty);
+    for (i, name) in names.iter().enumerate() {
+        if i > 0 {
+            xml.symbol(',');
+        }
+        xml.identifier(name);
+    }
+    xml.symbol(';');
+}
+
+fn write_subroutine(xml: &mut Xml, subroutine: &Subroutine) {
+    xml.open("subroutineDec");
+    xml.keyword(match subroutine.kind {
+        SubroutineKind::Constructor => Keyword::Constructor,
+        SubroutineKind::Function => Keyword::Function,
+        SubroutineKind::Method => Keyword::Method,
+    });
+    match subroutine.return_type {
+        Some(ref ty) => write_type(xml, ty),
+        None => xml.keyword(Keyword::Void),
+    }
+    xml.identifier(&subroutine.name);
+
+    xml.symbol('(');
+    xml.open("parameterList");
+    for (i, &(ref ty, ref name)) in subroutine.params.iter().enumerate() {
+        if i > 0 {
+            xml.symbol(',');
+        }
+        write_type(xml, ty);
+        xml.identifier(name);
+    }
+    xml.close("parameterList");
+    xml.symbol(')');
+
+    xml.open("subroutineBody");
+    xml.symbol('{');
+    for dec in &subroutine.locals {
+        xml.open("varDec");
+        xml.keyword(Keyword::Var);
+        write_names(xml, &dec.ty, &dec.names);
+        xml.close("varDec");
+    }
+    write_statements(xml, &subroutine.body);
+    xml.symbol('}');
+    xml.close("subroutineBody");
+    xml.close("subroutineDec");
+}
+
+fn write_statements(xml: &mut Xml, statements: &[Statement]) {
+    xml.open("statements");
+    for statement in statements {
+        write_statement(xml, statement);
+    }
+    xml.close("statements");
+}
+
+fn write_block(xml: &mut Xml, statements: &[Statement]) {
+    xml.symbol('{');
+    write_statements(xml, statements);
+    xml.symbol('}');
+}
+
+fn write_statement(xml: &mut Xml, statement: &Statement) {
+    match statement.kind {
+        StatementKind::Let { ref target, ref index, ref value } => {
+            xml.open("letStatement");
+            xml.keyword(Keyword::Let);
+            xml.identifier(target);
+            if let Some(ref index) = *index {
+                xml.symbol('[');
+                write_expression(xml, index);
+                xml.symbol(']');
+            }
+            xml.symbol('=');
+            write_expression(xml, value);
+            xml.symbol(';');
+            xml.close("letStatement");
+        }
+        StatementKind::If { ref condition, ref then, ref otherwise } => {
+            xml.open("ifStatement");
+            xml.keyword(Keyword::If);
+            write_condition(xml, condition);
+            write_block(xml, then);
+            if let Some(ref otherwise) = *otherwise {
+                xml.keyword(Keyword::Else);
+                write_block(xml, otherwise);
+            }
+            xml.close("ifStatement");
+        }
+        StatementKind::While { ref condition, ref body } => {
+            xml.open("whileStatement");
+            xml.keyword(Keyword::While);
+            write_condition(xml, condition);
+            write_block(xml, body);
+            xml.close("whileStatement");
+        }
+        StatementKind::Do(ref call) => {
+            xml.open("doStatement");
+            xml.keyword(Keyword::Do);
+            write_call(xml, call);
+            xml.symbol(';');
+            xml.close("doStatement");
+        }
+        StatementKind::Return(ref value) => {
+            xml.open("returnStatement");
+            xml.keyword(Keyword::Return);
+            if let Some(ref value) = *value {
+                write_expression(xml, value);
+            }
+            xml.symbol(';');
+            xml.close("returnStatement");
+        }
+    }
+}
+
+fn write_condition(xml: &mut Xml, condition: &Expr) {
+    xml.symbol('(');
+    write_expression(xml, condition);
+    xml.symbol(')');
+}
+
+fn write_expression(xml: &mut Xml, expr: &Expr) {
+    xml.open("expression");
+    write_term(xml, &expr.term);
+    for &(op, ref term) in &expr.rest {
+        xml.symbol(op.symbol());
+        write_term(xml, term);
+    }
+    xml.close("expression");
+}
+
+fn write_term(xml: &mut Xml, term: &Term) {
+    xml.open("term");
+    match term.kind {
+        TermKind::Int(value) => xml.token("integerConstant", &value.to_string()),
+        TermKind::Str(ref s) => xml.token("stringConstant", s),
+        TermKind::True => xml.keyword(Keyword::True),
+        TermKind::False => xml.keyword(Keyword::False),
+        TermKind::Null => xml.keyword(Keyword::Null),
+        TermKind::This => xml.keyword(Keyword::This),
+        TermKind::Var(ref name) => xml.token("identifier", name),
+        TermKind::Index(ref array, ref index) => {
+            xml.identifier(array);
+            xml.symbol('[');
+            write_expression(xml, index);
+            xml.symbol(']');
+        }
+        TermKind::Call(ref call) => write_call(xml, call),
+        TermKind::Paren(ref expr) => {
+            xml.symbol('(');
+            write_expression(xml, expr);
+            xml.symbol(')');
+        }
+        TermKind::Unary(op, ref term) => {
+            xml.symbol(op.symbol());
+            write_term(xml, term);
+        }
+    }
+    xml.close("term");
+}
+
+fn write_call(xml: &mut Xml, call: &Call) {
+    if let Some(ref receiver) = call.receiver {
+        xml.identifier(receiver);
+        xml.symbol('.');
+    }
+    xml.identifier(&call.name);
+    xml.symbol('(');
+    xml.open("expressionList");
+    for (i, arg) in call.args.iter().enumerate() {
+        if i > 0 {
+            xml.symbol(',');
+        }
+        write_expression(xml, arg);
+    }
+    xml.close("expressionList");
+    xml.symbol(')');
+}
@@ -0,0 +1,107 @@
+extern crate hack_jack;
+extern crate hack_vm;
+
+use hack_jack::*;
+use hack_vm::Vm;
+
+#[macro_use] mod macros;
+
+// Just enough of the OS to run the test programs
+static OS: &'static [(&'static str, &'static str)] = &[
+    ("Sys", "function Sys.init 0\ncall Main.main 0\npop temp 0\nlabel HALT\ngoto HALT\n"),
+    ("Memory", "function Memory.alloc 0\npush static 0\nif-goto ALLOC\npush constant 2048\npop static 0\n\
+                label ALLOC\npush static 0\npush static 0\npush argument 0\nadd\npop static 0\nreturn\n"),
+];
+
+fn run(path: &str, steps: u64) -> Vm {
+    let compiled = compile_path(format!("tests/data/{}", path), Mode::Vm).unwrap();
+    let names: Vec<String> = compiled.iter()
+                                     .map(|&(ref path, _)| path.file_stem().unwrap().to_string_lossy().into_owned())
+                                     .collect();
+    let mut files: Vec<(&str, &str)> = OS.to_vec();
+    files.extend(names.iter().zip(&compiled).map(|(name, &(_, ref vm))| (&name[..], &vm[..])));
+
+    let mut vm = Vm::new(&files).unwrap();
+    vm.ram[0] = 256;
+    vm.run(steps).unwrap();
+    assert_eq!(vm.current_function(), Some("Sys.init"));
+    vm
+}
+
+check! {
+    programs for {
+        fib         ("Fib", 20000) => &[0, 1, 1, 2, 3, 5, 8, 13, 21, 34],
+        points      ("Points", 2000) => &[13, 16, 3, -1]
+    } do |program, expected| {
+        let (path, steps) = program;
+        let vm = run(path, steps);
+        let expected: &[i16] = expected;
+        let actual: Vec<i16> = vm.ram[8000..8000 + expected.len()].iter().map(|&v| v as i16).collect();
+        assert_eq!(actual, expected);
+    }
+}
+
+#[test]
+fn builtin_os() {
+    let vm = compile("class Main {\n  function void main() {\n    var Array a;\n    var String s;\n\
+                      let a = Array.new(2);\n    let a[0] = Math.sqrt(144) * 3;\n    let s = \"Hi\";\n\
+                      do Output.printString(s);\n    do Memory.poke(8000, a[0] + s.length());\n\
+                      return;\n  }\n}\n", Mode::Vm).unwrap();
+    let mut vm = Vm::new(&[("Main", &vm)]).unwrap();
+    vm.run(1000).unwrap();
+    assert!(vm.halted());
+    assert_eq!(vm.ram[8000], 38);
+    // The top rows of 'H' and 'i'
+    assert_eq!(vm.ram[16384], 51 | 12 << 8);
+}
+
+#[test]
+fn reference_output() {
+    // Seven/Main.vm from project 11
+    let vm = compile("class Main {\n   function void main() {\n      do Output.printInt(1 + (2 * 3));\n\
+                      return;\n   }\n}\n", Mode::Vm).unwrap();
+    assert_eq!(vm, "function Main.main 0\npush constant 1\npush constant 2\npush constant 3\n\
+                    call Math.multiply 2\nadd\ncall Output.printInt 1\npop temp 0\npush constant 0\nreturn\n");
+}
+
+#[test]
+fn strings_and_labels() {
+    let vm = compile("class Main { function void main() { var String s;\n\
+                      if (true) { let s = \"Hi\"; } else { while (false) { } }\n return; } }", Mode::Vm).unwrap();
+    assert_eq!(vm, "function Main.main 1\npush constant 0\nnot\nif-goto IF_TRUE0\ngoto IF_FALSE0\n\
+                    label IF_TRUE0\npush constant 2\ncall String.new 1\npush constant 72\n\
+                    call String.appendChar 2\npush constant 105\ncall String.appendChar 2\npop local 0\n\
+                    goto IF_END0\nlabel IF_FALSE0\nlabel WHILE_EXP0\npush constant 0\nnot\n\
+                    if-goto WHILE_END0\ngoto WHILE_EXP0\nlabel WHILE_END0\nlabel IF_END0\n\
+                    push constant 0\nreturn\n");
+}
+
+#[test]
+fn xml() {
+    let source = "class Main {\n  field Array a;\n  method void set(int i) {\n    let a[i] = -i < \"x\";\n\
+                  \x20   do a.dispose();\n    return;\n  }\n}\n";
+
+    assert_eq!(compile(source, Mode::Tokens).unwrap(), include_str!("data/XmlT.xml"));
+    assert_eq!(compile(source, Mode::Tree).unwrap(), include_str!("data/Xml.xml"));
+}
+
+#[test]
+fn errors() {
+    let error = |source: &str| compile(source, Mode::Vm).unwrap_err().to_string();
+    assert_eq!(error("class Main { function void main() {\n  let x = 1;\n  return;\n} }"),
+               "line 2, column 7: Assignment to undeclared variable 'x', declare it with 'var', 'field' or 'static'");
+    assert_eq!(error("class Main { function void main() { var int n;\n  do n.print();\n  return;\n} }"),
+               "line 2, column 6: 'n' is an int, which has no methods");
+    assert_eq!(error("class Main { function void main() {\n  do Output.printInt(1, 2);\n} }"),
+               "line 2, column 13: 'Output.printInt' takes 1 argument, but 2 were given");
+
+    // Classes in the same directory are checked together
+    let errors = compile_path("tests/data/Broken", Mode::Vm).unwrap_err();
+    assert_eq!(errors, include_str!("data/Broken/errors.txt"));
+
+    let outputs = compile_path("tests/data/Points", Mode::Tokens).unwrap();
+    let paths: Vec<_> = outputs.iter().map(|&(ref path, _)| path.file_name().unwrap().to_string_lossy().into_owned()).collect();
+    assert_eq!(paths, ["MainT.xml", "PointT.xml"]);
+    assert_eq!(compile_path("tests/data/Missing.jack", Mode::Vm).unwrap_err(),
+               "Failed to read 'tests/data/Missing.jack': No such file or directory (os error 2)");
+}
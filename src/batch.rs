@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::io::Write;
+use std::time::Instant;
+
+use hack_interpreter::{find_scripts, run_tests, run_test_with_limits, junit_report, json_report};
+use hack_interpreter::{Limits, TestFailure, TestRun};
+use Verbosity;
+
+pub fn run_script(path: &str, limits: &Limits, verbosity: Verbosity) -> Result<bool, String> {
+    if verbosity >= Verbosity::Normal {
+        println!("Running test file: '{}'", path);
+    }
+    let run = run_test_with_limits(path, limits);
+    if verbosity >= Verbosity::Normal {
+        print_messages(&run);
+    }
+    if let Some(ref failure) = run.failure {
+        print_failure(failure, verbosity);
+    }
+    println!("{}", run);
+    Ok(run.passed())
+}
+
+pub fn run_batch(paths: &[String], jobs: usize, limits: &Limits, junit: Option<&String>,
+                 json: Option<&String>, verbosity: Verbosity) -> Result<bool, String> {
+    let scripts = try!(find_scripts(paths));
+
+    if verbosity >= Verbosity::Normal {
+        println!("Running {} test files", scripts.len());
+    }
+    let started = Instant::now();
+    let runs = run_tests(&scripts, jobs, limits);
+    let elapsed = started.elapsed();
+
+    if verbosity >= Verbosity::Normal {
+        for run in &runs {
+            println!("{}", run);
+            print_messages(run);
+        }
+    }
+    let failed: Vec<&TestRun> = runs.iter().filter(|r| !r.passed()).collect();
+    for run in &failed {
+        if let Some(ref failure) = run.failure {
+            println!("\n{}:", run.script.to_string_lossy());
+            print_failure(failure, verbosity);
+        }
+    }
+    println!("\n{} passed, {} failed in {}.{:03}s",
+             runs.len() - failed.len(), failed.len(), elapsed.as_secs(), elapsed.subsec_nanos() / 1_000_000);
+
+    if let Some(path) = junit {
+        try!(write_report(path, &junit_report(&runs), verbosity));
+    }
+    if let Some(path) = json {
+        try!(write_report(path, &json_report(&runs), verbosity));
+    }
+    Ok(failed.is_empty())
+}
+
+// What the script echoed and the breakpoints it reached
+fn print_messages(run: &TestRun) {
+    for message in &run.messages {
+        println!("    {}", message);
+    }
+}
+
+// A failure, with the output and comparison file side by side if verbose
+fn print_failure(failure: &TestFailure, verbosity: Verbosity) {
+    if let (&TestFailure::Comparison(ref failure), Verbosity::Verbose) = (failure, verbosity) {
+        println!("{}", failure.table_diff(true));
+    }
+    println!("{}", failure);
+}
+
+fn write_report(path: &str, report: &str, verbosity: Verbosity) -> Result<(), String> {
+    try!(File::create(path).and_then(|mut f| f.write_all(report.as_bytes()))
+                           .map_err(|e| format!("Failed to write report '{}': {}", path, e)));
+    if verbosity >= Verbosity::Normal {
+        println!("Report written to: '{}'", path);
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use hack_interpreter::{Machine, InputScript, Screen, ScreenRecorder, Limits};
+use hack_interpreter::limits::Watchdog;
+use {load_program, Verbosity};
+
+pub fn run_headless(input: &str, cycles: u64, replay: Option<&String>, screenshot: Option<&String>,
+                    capture: Option<(&String, u64)>, limits: &Limits, verbosity: Verbosity) -> Result<bool, String> {
+    let mut machine = Machine::new(try!(load_program(input)));
+
+    if let Some(script) = replay {
+        machine.replay(try!(InputScript::from_file(script)));
+    }
+
+    if verbosity >= Verbosity::Normal {
+        println!("Running program file: '{}' for {} cycles", input, cycles);
+    }
+    let mut watchdog = Watchdog::new(*limits);
+    let result = if let Some((path, fps)) = capture {
+        let mut recorder = try!(ScreenRecorder::new(path, fps));
+        let mut result = Ok(());
+        loop {
+            try!(recorder.update(&machine));
+            if machine.cycles() >= cycles || result.is_err() || machine.fault().is_some() { break }
+            let n = recorder.cycles_until_frame(&machine).min(cycles - machine.cycles());
+            result = machine.run_watched(n, &mut watchdog);
+        }
+        if verbosity >= Verbosity::Normal {
+            println!("Captured {} frames to: '{}'", recorder.frames(), path);
+        }
+        try!(recorder.finish());
+        result
+    } else {
+        machine.run_watched(cycles, &mut watchdog)
+    };
+    if verbosity >= Verbosity::Verbose {
+        println!("Stopped at PC: {}, A: {}, D: {}", machine.cpu.pc, machine.cpu.ra as i16, machine.cpu.rd as i16);
+    } else if verbosity >= Verbosity::Normal {
+        println!("Stopped at PC: {}", machine.cpu.pc);
+    }
+
+    if let Some(path) = screenshot {
+        try!(Screen::from_ram(&machine.cpu.ram).save(path));
+        if verbosity >= Verbosity::Normal {
+            println!("Screen saved to: '{}'", path);
+        }
+    }
+
+    if let Err(exceeded) = result {
+        println!("{}", exceeded);
+        return Ok(false)
+    }
+    if let Some(fault) = machine.fault() {
+        println!("{}", fault);
+        return Ok(false)
+    }
+    Ok(true)
+}
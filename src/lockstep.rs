@@ -0,0 +1,21 @@
+use hack_hdl::load_path;
+use hack_interpreter::lockstep;
+use {load_program, Verbosity};
+
+pub fn run_lockstep(input: &str, hdl: &str, cycles: u64, verbosity: Verbosity) -> Result<bool, String> {
+    let program = try!(load_program(input));
+    if verbosity >= Verbosity::Normal {
+        println!("Running program file: '{}' on '{}' for {} cycles", input, hdl, cycles);
+    }
+    let mut chip = try!(load_path(hdl));
+    match try!(lockstep(&mut chip, program, cycles)) {
+        None => {
+            println!("The chip matches the CPU emulator");
+            Ok(true)
+        }
+        Some(divergence) => {
+            println!("{}", divergence);
+            Ok(false)
+        }
+    }
+}
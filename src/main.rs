@@ -1,42 +1,266 @@
 #![cfg(not(test))]
 
 extern crate hack_interpreter;
+extern crate hack_assembler;
+extern crate hack_hdl;
+extern crate hack_vm;
+extern crate hack_jack;
 extern crate rustc_serialize;
 extern crate docopt;
 extern crate piston_window;
 extern crate image;
+extern crate termion;
 
-use hack_interpreter::runner;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+use std::time::Duration;
+
+use hack_assembler::assemble;
+use hack_interpreter::{Display, Limits, Rom};
+use hack_interpreter::display::parse_color;
+use hack_jack::Mode;
 use docopt::Docopt;
 use simulator::run_simulator;
+use headless::run_headless;
+use tui::run_tui;
+use batch::{run_batch, run_script};
+use lockstep::run_lockstep;
+use trace::run_trace;
+use translate::{run_asm, run_disasm, run_vm, run_jack};
 
 mod simulator;
+mod headless;
+mod tui;
+mod batch;
+mod lockstep;
+mod trace;
+mod translate;
 
 static USAGE: &'static str = "
-Usage: hack-interpreter [-r] <input>
+Usage: hack asm [options] <input>
+       hack disasm [options] <input>
+       hack vm [options] <input>
+       hack jack [options] <input>
+       hack run [options] <input> <cycles>
+       hack trace [options] <input> <cycles>
+       hack sim [options] <input>
+       hack debug [options] <input>
+       hack test [options] <paths>...
+       hack (-h | --help)
+
+Commands:
+    asm                    Assemble a .asm file into <name>.hack
+    disasm                 Disassemble a .hack file into <name>.dis.asm
+    vm                     Translate a .vm file, or a directory of them, into a .asm file
+    jack                   Compile a .jack file, or a directory of them, into .vm files
+    run                    Run a program for <cycles> cycles without a window
+    trace                  Run a program for <cycles> cycles, printing every instruction
+    sim                    Run a program in a window
+    debug                  Run a program in a window, next to its registers and memory
+    test                   Run a .tst file, or every .tst file in directories or globs
+
+Programs to run are .hack or .asm files. Commands exit with status 1 when a
+test, limit or lockstep check fails, and 2 on errors.
 
 Options:
-    -r, --runner  Run a .tst file
+    -o, --output <path>    Where asm, disasm and vm write their output, - for standard output
+    -q, --quiet            Only print results and failures
+    -v, --verbose          Print more details, like the table of a failed test
+    -h, --help             Show this message
+
+Jack options:
+    --tokens               Write the tokens as <name>T.xml, as in project 10
+    --tree                 Write the parse tree as <name>.xml, as in project 10
+
+Run options:
+    --lockstep <hdl>       Run the program on the computer chip in <hdl> too, stopping
+                           where it differs from the CPU emulator
+    --replay <script>      Replay keyboard input from an input script
+    --screenshot <image>   Save the screen to <image> after the run
+    --capture <output>     Record the screen to an animated GIF, or to numbered PNGs
+                           in the directory <output> if it doesn't end in .gif
+    --fps <fps>            Frames per emulated second to capture [default: 10]
+
+Simulator options, with --replay and --capture:
+    --record <script>      Record keyboard input to an input script
+    --scale <n>            Size in window pixels of a screen pixel [default: 2]
+    --fg <color>           Color of set pixels, as #rrggbb or a name like amber
+    --bg <color>           Color of clear pixels
+    --invert               Swap the pixel colors
+    --scanlines            Darken the gaps between screen lines like a CRT
+    --tui                  Run the simulator in the terminal
+    --half-blocks          Draw the terminal screen with half blocks instead of braille
+
+Limits, for test files and runs:
+    --max-cycles <n>       Stop after <n> clock cycles
+    --timeout <seconds>    Stop after running for <seconds>
+    --max-output <bytes>   Stop a test file once its output reaches <bytes>
+
+Test options:
+    --jobs <n>             Test files to run at once [default: 4]
+    --junit <report>       Write a JUnit XML report to <report>
+    --json <report>        Write a JSON report to <report>
 ";
 
 #[derive(RustcDecodable, Debug)]
 struct Args {
+    cmd_asm: bool,
+    cmd_disasm: bool,
+    cmd_vm: bool,
+    cmd_jack: bool,
+    cmd_run: bool,
+    cmd_trace: bool,
+    cmd_debug: bool,
+    cmd_test: bool,
     arg_input: String,
-    flag_runner: bool,
+    arg_cycles: Option<u64>,
+    arg_paths: Vec<String>,
+    flag_output: Option<String>,
+    flag_quiet: bool,
+    flag_verbose: bool,
+    flag_tokens: bool,
+    flag_tree: bool,
+    flag_lockstep: Option<String>,
+    flag_replay: Option<String>,
+    flag_record: Option<String>,
+    flag_screenshot: Option<String>,
+    flag_capture: Option<String>,
+    flag_fps: u64,
+    flag_scale: usize,
+    flag_fg: Option<String>,
+    flag_bg: Option<String>,
+    flag_invert: bool,
+    flag_scanlines: bool,
+    flag_tui: bool,
+    flag_half_blocks: bool,
+    flag_jobs: usize,
+    flag_max_cycles: Option<u64>,
+    flag_timeout: Option<u64>,
+    flag_max_output: Option<usize>,
+    flag_junit: Option<String>,
+    flag_json: Option<String>,
+}
+
+/// How much a command prints besides its results and failures
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
 }
 
 fn main() {
     let args: Args = Docopt::new(USAGE).and_then(|d| d.decode())
-                                       .unwrap_or_else(|e| e.exit());
+                                       .unwrap_or_else(|e| {
+        if !e.fatal() {
+            e.exit()
+        }
+        eprintln!("{}", e);
+        process::exit(2)
+    });
+
+    match run(&args) {
+        Ok(true) => {},
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2)
+        }
+    }
+}
+
+// Run the command, giving whether it passed
+fn run(args: &Args) -> Result<bool, String> {
     let input = &args.arg_input;
+    let output = args.flag_output.as_ref();
+    let verbosity = match (args.flag_quiet, args.flag_verbose) {
+        (true, _) => Verbosity::Quiet,
+        (_, true) => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    };
+    let capture = args.flag_capture.as_ref().map(|path| (path, args.flag_fps));
+    let limits = Limits {
+        max_cycles: args.flag_max_cycles,
+        timeout: args.flag_timeout.map(Duration::from_secs),
+        max_output: args.flag_max_output,
+    };
+    let cycles = args.arg_cycles.unwrap_or(0);
 
-    if args.flag_runner {
-        println!("Running test file: '{}'", input);
-        match runner(input) {
-            Ok(()) => println!("Test completed successfully"),
-            Err(e) => panic!("{}", e),
+    if args.cmd_asm {
+        run_asm(input, output, verbosity)
+    } else if args.cmd_disasm {
+        run_disasm(input, output, verbosity)
+    } else if args.cmd_vm {
+        run_vm(input, output, verbosity)
+    } else if args.cmd_jack {
+        let mode = match (args.flag_tokens, args.flag_tree) {
+            (true, _) => Mode::Tokens,
+            (_, true) => Mode::Tree,
+            _ => Mode::Vm,
+        };
+        run_jack(input, mode, verbosity)
+    } else if args.cmd_run {
+        match args.flag_lockstep {
+            Some(ref hdl) => run_lockstep(input, hdl, cycles, verbosity),
+            None => run_headless(input, cycles, args.flag_replay.as_ref(), args.flag_screenshot.as_ref(),
+                                 capture, &limits, verbosity),
+        }
+    } else if args.cmd_trace {
+        run_trace(input, cycles)
+    } else if args.cmd_test {
+        let paths = &args.arg_paths;
+        if paths.len() == 1 && paths[0].ends_with(".tst") {
+            run_script(&paths[0], &limits, verbosity)
+        } else {
+            run_batch(paths, args.flag_jobs, &limits, args.flag_junit.as_ref(), args.flag_json.as_ref(),
+                      verbosity)
         }
+    } else if args.flag_tui {
+        run_tui(input, args.flag_replay.as_ref(), args.flag_record.as_ref(), args.flag_half_blocks,
+                verbosity)
     } else {
-        run_simulator(input);
+        // sim or debug
+        let display = try!(display(args));
+        run_simulator(input, args.flag_replay.as_ref(), args.flag_record.as_ref(), capture,
+                      &display, args.cmd_debug, verbosity)
+    }
+}
+
+fn display(args: &Args) -> Result<Display, String> {
+    if args.flag_scale == 0 {
+        return Err("Scale must be at least 1".to_string())
+    }
+
+    let mut display = Display { scale: args.flag_scale, ..Display::default() };
+    if let Some(ref color) = args.flag_fg {
+        display.foreground = try!(parse_color(color));
     }
+    if let Some(ref color) = args.flag_bg {
+        display.background = try!(parse_color(color));
+    }
+    if args.flag_invert {
+        display.invert();
+    }
+    display.scanlines = args.flag_scanlines;
+    Ok(display)
+}
+
+/// Read a file, with its name in the error if that fails
+pub fn read_file(path: &str) -> Result<String, String> {
+    let mut contents = String::new();
+    try!(File::open(path).and_then(|mut f| f.read_to_string(&mut contents))
+                         .map_err(|e| format!("Failed to read '{}': {}", path, e)));
+    Ok(contents)
+}
+
+/// Load a program to run from a .hack file, or assemble it from a .asm file
+pub fn load_program(path: &str) -> Result<Rom, String> {
+    let rom = if path.ends_with(".asm") {
+        let program = try!(assemble(&try!(read_file(path))).map_err(|e| format!("{}: {}", path, e)));
+        Rom::from_str(&program)
+    } else {
+        Rom::from_file(&path)
+    };
+    rom.map_err(|e| format!("Failed to load '{}': {}", path, e))
 }
@@ -1,97 +1,267 @@
-use hack_interpreter::{Rom, Cpu};
+use hack_interpreter::{Machine, InputScript, Screen, ScreenRecorder, Display};
+use hack_interpreter::machine::{Control, UPDATES_PER_SEC};
+use hack_interpreter::keyboard::{KeyTracker, SpecialKey};
+use hack_interpreter::debug::{self, heading, Line, Style};
+use hack_interpreter::font::{self, GLYPH_WIDTH, GLYPH_HEIGHT};
 
 use image::{Rgba, ImageBuffer, GenericImage};
 use piston_window::{
-    WindowSettings, OpenGL, Texture, Key, Button, TextureSettings, PistonWindow,
-    EventLoop, PressEvent, ReleaseEvent, UpdateEvent, image as draw_image,
+    WindowSettings, OpenGL, Texture, Key, Button, TextureSettings, PistonWindow, Filter, Transformed,
+    Window, AdvancedWindow, EventLoop, PressEvent, ReleaseEvent, TextEvent, UpdateEvent, image as draw_image, clear,
 };
+use {load_program, Verbosity};
 
-const WIDTH: usize = 512;
-const HEIGHT: usize = 256;
-const SCALE: usize = 2;
-const SCREEN_ADDR: usize = 16384;
-const SCREEN_MEMORY_LEN: usize = WIDTH * HEIGHT / 16;
-const KEYBOARD_ADDR: usize = SCREEN_ADDR + SCREEN_MEMORY_LEN;
-
-// TODO: MATH - Decide the MHz of the Cpu, partition it between frames
-const CYCLES_PER_UPDATE: usize = 40000;
 const MAX_FPS: u64 = 30;
-const UPDATES_PER_SEC: u64 = 60;
 
-pub fn run_simulator(input: &str) {
-    let program = Rom::from_file(&input).unwrap();
-    let ref mut cpu = Cpu::new(program);
-    println!("Running program file: '{}'", input);
+// Debugger panels, measured in character cells of the scaled font
+const FONT_SCALE: usize = 2;
+const CELL_WIDTH: usize = (GLYPH_WIDTH + 1) * FONT_SCALE;
+const CELL_HEIGHT: usize = (GLYPH_HEIGHT + 2) * FONT_SCALE;
+const PANEL_COLUMNS: usize = 40;
+const RAM_ROWS: usize = 14;
+const RAM_WORDS_PER_ROW: usize = 8;
+
+const BACKGROUND: [u8; 3] = [32, 32, 32];
+
+pub fn run_simulator(input: &str, replay: Option<&String>, record: Option<&String>,
+                     capture: Option<(&String, u64)>, display: &Display, debug: bool,
+                     verbosity: Verbosity) -> Result<bool, String> {
+    let ref mut machine = Machine::new(try!(load_program(input)));
+    if verbosity >= Verbosity::Normal {
+        println!("Running program file: '{}'", input);
+        println!("Controls: {}", Control::help());
+    }
+
+    if let Some(script) = replay {
+        machine.replay(try!(InputScript::from_file(script)));
+    }
+    if record.is_some() {
+        machine.record();
+    }
+    let mut recorder = match capture {
+        Some((path, fps)) => Some(try!(ScreenRecorder::new(path, fps))),
+        None => None,
+    };
+
+    let (screen_width, screen_height) = display.size();
+    let (width, height) = if debug {
+        (screen_width + (PANEL_COLUMNS * CELL_WIDTH) as u32, screen_height + (RAM_ROWS * CELL_HEIGHT) as u32)
+    } else {
+        (screen_width, screen_height)
+    };
 
     let window: PistonWindow =
         WindowSettings::new(
-            format!("hack-interpreter: {}", input),
-            [(WIDTH * SCALE) as u32, (HEIGHT * SCALE) as u32]
+            format!("hack: {}", input),
+            [width, height]
         )
         .opengl(OpenGL::V3_2)
-        .exit_on_esc(true)
         .build()
         .expect("Failed to build PistonWindow");
 
     let mut window = window.ups(UPDATES_PER_SEC)
                        .max_fps(MAX_FPS);
 
-    let ref mut image = ImageBuffer::new((WIDTH * SCALE) as u32, (HEIGHT * SCALE) as u32);
+    let ref mut image = ImageBuffer::new(width, height);
+    // Keep pixels sharp when the window is resized
     let mut texture = Texture::from_image(
         &mut window.factory,
         image,
-        &TextureSettings::new()
+        &TextureSettings::new().filter(Filter::Nearest)
     ).expect("Failed to create texture");
 
+    let mut keys = KeyTracker::new();
+    let mut ctrl_held = false;
+    let mut status = String::new();
+
     while let Some(e) = window.next() {
         if let Some(Button::Keyboard(key)) = e.press_args() {
-            // HACK: Pong is expecting 'ASCII' keycodes of
-            // 130 and 132 for left and right movement even
-            // even though 130 and 132 are not actually ASCII.
-            // Will probably need to remap a bunch of other keys.
-            cpu.ram[KEYBOARD_ADDR] = match key {
-                Key::Left => 130,
-                Key::Up => 131,
-                Key::Right => 132,
-                Key::Down => 133,
-                key => key as u16
-            };
+            let control = if ctrl_held { control_key(key) } else { None };
+
+            match (control, special_key(key)) {
+                (Some(control), _) => machine.control(control),
+                // Esc is a Hack key, so quitting is Ctrl+C as in the terminal
+                (None, _) if ctrl_held && key == Key::C => window.set_should_close(true),
+                (None, _) if key == Key::LCtrl || key == Key::RCtrl => ctrl_held = true,
+                (None, Some(special)) => machine.press(keys.press_special(key as u32, special)),
+                (None, None) => keys.press(key as u32),
+            }
+        }
+
+        // Printable keys register once their text arrives, so Shift and
+        // Caps Lock are already applied
+        if let Some(code) = e.text_args().and_then(|text| keys.text(&text)) {
+            machine.press(code);
         }
 
-        if let Some(Button::Keyboard(_)) = e.release_args() {
-            cpu.ram[KEYBOARD_ADDR] = 0;
+        if let Some(Button::Keyboard(key)) = e.release_args() {
+            if key == Key::LCtrl || key == Key::RCtrl {
+                ctrl_held = false;
+            }
+            if keys.release(key as u32) {
+                machine.release();
+            }
         }
 
         window.draw_2d(&e, |c, g| {
-            draw_image(&texture, c.transform, g)
+            // Fit the image to the window, keeping its aspect ratio
+            let view = c.get_view_size();
+            let zoom = (view[0] / width as f64).min(view[1] / height as f64);
+            let x = (view[0] - width as f64 * zoom) / 2.0;
+            let y = (view[1] - height as f64 * zoom) / 2.0;
+
+            clear([0.0, 0.0, 0.0, 1.0], g);
+            draw_image(&texture, c.transform.trans(x, y).zoom(zoom), g)
         });
 
         e.update(|_| {
-            render_screen(image, &cpu);
+            display.render(&Screen::from_ram(&machine.cpu.ram), image);
+            if debug {
+                render_panels(image, machine, (screen_width, screen_height));
+            }
             texture.update(&mut window.encoder, image)
                    .expect("Failed to write frame");
-            for _ in 0..CYCLES_PER_UPDATE { cpu.step() }
+            machine.update();
+            if let Some(ref mut recorder) = recorder {
+                recorder.update(machine).expect("Failed to capture frame");
+            }
         });
+
+        if machine.status() != status {
+            status = machine.status();
+            window.set_title(format!("hack: {} ({})", input, status));
+        }
     }
+
+    if let (Some(path), Some(recording)) = (record, machine.recording()) {
+        try!(recording.save(path));
+        if verbosity >= Verbosity::Normal {
+            println!("Input recorded to: '{}'", path);
+        }
+    }
+    if let (Some((path, _)), Some(recorder)) = (capture, recorder) {
+        if verbosity >= Verbosity::Normal {
+            println!("Captured {} frames to: '{}'", recorder.frames(), path);
+        }
+        try!(recorder.finish());
+    }
+    Ok(true)
 }
 
-fn render_screen(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, cpu: &Cpu) {
-    let screen = &cpu.ram[SCREEN_ADDR..SCREEN_ADDR + SCREEN_MEMORY_LEN];
-
-    for (idx, word) in screen.iter().enumerate() {
-        // For each word of memory, draw 16 pixels
-        let idx = idx * 16;
-        for (bit, i) in (idx..idx + 16).enumerate() {
-            let (x, y) = ((i % WIDTH) * SCALE, (i / WIDTH) * SCALE);
-            let color = if word & (1 << bit) != 0 { 0 } else { 255 };
-
-            for i in 0..SCALE {
-                for j in 0..SCALE {
-                    image.put_pixel((x + i) as u32,
-                                    (y + j) as u32,
-                                    Rgba([color, color, color, 255]));
-                }
-            }
+fn control_key(key: Key) -> Option<Control> {
+    // Letter keys are their lowercase ASCII value
+    match key as u32 {
+        code @ 0x61...0x7A => Control::from_ctrl_key(code as u8 as char),
+        _ => None
+    }
+}
+
+fn special_key(key: Key) -> Option<SpecialKey> {
+    let special = match key {
+        Key::Return | Key::Return2 | Key::NumPadEnter => SpecialKey::Newline,
+        Key::Backspace | Key::NumPadBackspace => SpecialKey::Backspace,
+        Key::Left => SpecialKey::Left,
+        Key::Up => SpecialKey::Up,
+        Key::Right => SpecialKey::Right,
+        Key::Down => SpecialKey::Down,
+        Key::Home => SpecialKey::Home,
+        Key::End => SpecialKey::End,
+        Key::PageUp => SpecialKey::PageUp,
+        Key::PageDown => SpecialKey::PageDown,
+        Key::Insert => SpecialKey::Insert,
+        Key::Delete => SpecialKey::Delete,
+        Key::Escape => SpecialKey::Esc,
+        Key::F1 => SpecialKey::F(1),
+        Key::F2 => SpecialKey::F(2),
+        Key::F3 => SpecialKey::F(3),
+        Key::F4 => SpecialKey::F(4),
+        Key::F5 => SpecialKey::F(5),
+        Key::F6 => SpecialKey::F(6),
+        Key::F7 => SpecialKey::F(7),
+        Key::F8 => SpecialKey::F(8),
+        Key::F9 => SpecialKey::F(9),
+        Key::F10 => SpecialKey::F(10),
+        Key::F11 => SpecialKey::F(11),
+        Key::F12 => SpecialKey::F(12),
+        _ => return None
+    };
+
+    Some(special)
+}
+
+fn render_panels(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, machine: &Machine, screen: (u32, u32)) {
+    let height = image.height();
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if x >= screen.0 || y >= screen.1 {
+            *pixel = rgba(BACKGROUND);
         }
     }
+
+    // Registers, disassembly and stack to the right of the screen, with the
+    // rows below the registers split between the listings
+    let rows = height as usize / CELL_HEIGHT;
+    let mut side = debug::registers(machine);
+    let listing_rows = rows.saturating_sub(side.len() + 4) / 2;
+    side.push(vec![]);
+    side.push(heading("Disassembly"));
+    side.extend(debug::disassembly(machine, listing_rows));
+    side.push(vec![]);
+    side.push(heading("Stack"));
+    let stack_rows = rows.saturating_sub(side.len());
+    side.extend(debug::stack(machine, stack_rows));
+
+    let left = screen.0 as usize + CELL_WIDTH / 2;
+    for (row, line) in side.iter().take(rows).enumerate() {
+        draw_line(image, left, row * CELL_HEIGHT, line);
+    }
+
+    // RAM below the screen, following the latest write
+    let follow = debug::last_written(machine).unwrap_or(0);
+    let start = follow.saturating_sub((RAM_ROWS / 2) * RAM_WORDS_PER_ROW);
+    let mut bottom = vec![heading("RAM")];
+    bottom.extend(debug::ram(machine, start, RAM_ROWS - 1, RAM_WORDS_PER_ROW));
+
+    let top = screen.1 as usize;
+    for (row, line) in bottom.iter().enumerate() {
+        if top + (row + 1) * CELL_HEIGHT > height as usize { break }
+        draw_line(image, CELL_WIDTH / 2, top + row * CELL_HEIGHT, line);
+    }
+}
+
+fn draw_line(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, x: usize, y: usize, line: &Line) {
+    let mut column = 0;
+    for span in line {
+        let (foreground, background) = match span.style {
+            Style::Normal => ([220, 220, 220], BACKGROUND),
+            Style::Heading => ([240, 200, 80], BACKGROUND),
+            Style::Current => ([0, 0, 0], [120, 180, 255]),
+            Style::Recent => ([255, 90, 90], BACKGROUND),
+        };
+
+        for c in span.text.chars() {
+            draw_char(image, x + column * CELL_WIDTH, y, c, foreground, background);
+            column += 1;
+        }
+    }
+}
+
+fn draw_char(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, x: usize, y: usize, c: char,
+             foreground: [u8; 3], background: [u8; 3]) {
+    let (width, height) = image.dimensions();
+
+    for i in 0..CELL_WIDTH {
+        for j in 0..CELL_HEIGHT {
+            let (px, py) = ((x + i) as u32, (y + j) as u32);
+            if px >= width || py >= height { continue }
+
+            // Glyphs sit one scaled pixel below the top of their cell
+            let (gx, gy) = (i / FONT_SCALE, (j / FONT_SCALE).wrapping_sub(1));
+            let set = gx < GLYPH_WIDTH && gy < GLYPH_HEIGHT && font::pixel(c, gx, gy);
+            image.put_pixel(px, py, rgba(if set { foreground } else { background }));
+        }
+    }
+}
+
+fn rgba(color: [u8; 3]) -> Rgba<u8> {
+    Rgba([color[0], color[1], color[2], 255])
 }
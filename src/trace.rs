@@ -0,0 +1,23 @@
+use hack_assembler::disassemble;
+use hack_interpreter::Cpu;
+use load_program;
+
+pub fn run_trace(input: &str, cycles: u64) -> Result<bool, String> {
+    let mut cpu = Cpu::new(try!(load_program(input)));
+    println!("{:>8}  {:>5}  {:<16}  {:>6}  {:>6}  Write", "Cycle", "PC", "Instruction", "A", "D");
+    for cycle in 1..cycles + 1 {
+        let pc = cpu.pc;
+        let instruction = cpu.rom().get(pc as usize).cloned().unwrap_or(0);
+        if let Err(fault) = cpu.step() {
+            println!("{}", fault);
+            return Ok(false)
+        }
+        let write = cpu.last_write().map_or(String::new(), |address| {
+            format!("RAM[{}] = {}", address, cpu.ram[address as usize] as i16)
+        });
+        let line = format!("{:>8}  {:>5}  {:<16}  {:>6}  {:>6}  {}", cycle, pc, disassemble(instruction),
+                           cpu.ra as i16, cpu.rd as i16, write);
+        println!("{}", line.trim_right());
+    }
+    Ok(true)
+}
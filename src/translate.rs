@@ -0,0 +1,69 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use hack_assembler::{assemble, disassemble};
+use hack_jack::{compile_path, Mode};
+use hack_vm::translate_path;
+use {read_file, Verbosity};
+
+pub fn run_asm(input: &str, output: Option<&String>, verbosity: Verbosity) -> Result<bool, String> {
+    let program = try!(assemble(&try!(read_file(input))).map_err(|e| format!("{}: {}", input, e)));
+    if verbosity >= Verbosity::Verbose {
+        eprintln!("Assembled {} instructions", program.lines().count());
+    }
+    try!(write(&output_path(Path::new(input), output, "hack"), &program, verbosity));
+    Ok(true)
+}
+
+pub fn run_disasm(input: &str, output: Option<&String>, verbosity: Verbosity) -> Result<bool, String> {
+    let mut assembly = String::new();
+    for (i, line) in try!(read_file(input)).lines().enumerate().filter(|&(_, l)| !l.trim().is_empty()) {
+        let instruction = try!(u16::from_str_radix(line.trim(), 2).map_err(|_| {
+            format!("{}: line {}: Invalid instruction '{}'", input, i + 1, line.trim())
+        }));
+        assembly.push_str(&disassemble(instruction));
+        assembly.push('\n');
+    }
+    try!(write(&output_path(Path::new(input), output, "dis.asm"), &assembly, verbosity));
+    Ok(true)
+}
+
+pub fn run_vm(input: &str, output: Option<&String>, verbosity: Verbosity) -> Result<bool, String> {
+    let assembly = try!(translate_path(input));
+    // A directory's code goes in the directory, named after it
+    let path = Path::new(input);
+    let path = if path.is_dir() {
+        let name = try!(fs::canonicalize(path).map_err(|e| format!("Failed to read '{}': {}", input, e)));
+        path.join(name.file_name().unwrap_or_else(|| "Main".as_ref()))
+    } else {
+        path.to_path_buf()
+    };
+    try!(write(&output_path(&path, output, "asm"), &assembly, verbosity));
+    Ok(true)
+}
+
+pub fn run_jack(input: &str, mode: Mode, verbosity: Verbosity) -> Result<bool, String> {
+    for (path, contents) in try!(compile_path(input, mode)) {
+        try!(write(&path, &contents, verbosity));
+    }
+    Ok(true)
+}
+
+// `output` if one was given, otherwise `input` with a new extension
+fn output_path(input: &Path, output: Option<&String>, extension: &str) -> PathBuf {
+    output.map_or_else(|| input.with_extension(extension), PathBuf::from)
+}
+
+fn write(path: &Path, contents: &str, verbosity: Verbosity) -> Result<(), String> {
+    if path == Path::new("-") {
+        print!("{}", contents);
+        return Ok(())
+    }
+    try!(File::create(path).and_then(|mut f| f.write_all(contents.as_bytes()))
+                           .map_err(|e| format!("Failed to write '{}': {}", path.display(), e)));
+    if verbosity >= Verbosity::Normal {
+        println!("Wrote '{}'", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hack_interpreter::{Machine, InputScript, Screen};
+use hack_interpreter::debug::{self, heading, Line, Style};
+use hack_interpreter::keyboard::{self, SpecialKey};
+use hack_interpreter::machine::{Control, UPDATES_PER_SEC};
+use termion::{async_stdin, clear, color, cursor, style};
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::screen::AlternateScreen;
+use {load_program, Verbosity};
+
+const UPDATES_PER_FRAME: u64 = 2;
+const DISASSEMBLY_ROWS: usize = 24;
+const STACK_ROWS: usize = 12;
+const RAM_WORDS_PER_ROW: usize = 4;
+
+// Terminals only report key presses, so a key counts as held until it
+// stops repeating. The first repeat takes longer to arrive than the rest.
+const INITIAL_HOLD_MS: u64 = 500;
+const REPEAT_HOLD_MS: u64 = 100;
+
+pub fn run_tui(input: &str, replay: Option<&String>, record: Option<&String>, half_blocks: bool,
+               verbosity: Verbosity) -> Result<bool, String> {
+    let ref mut machine = Machine::new(try!(load_program(input)));
+
+    if let Some(script) = replay {
+        machine.replay(try!(InputScript::from_file(script)));
+    }
+    if record.is_some() {
+        machine.record();
+    }
+
+    {
+        let stdout = io::stdout().into_raw_mode().expect("Failed to enter raw mode");
+        let mut stdout = AlternateScreen::from(stdout);
+        let mut keys = async_stdin().keys();
+        let update_interval = Duration::from_millis(1000 / UPDATES_PER_SEC);
+
+        // The key currently held down and when it should be released
+        let mut held: Option<(Key, Instant)> = None;
+
+        write!(stdout, "{}{}", clear::All, cursor::Hide).unwrap();
+
+        'running: for update in 0.. {
+            let started = Instant::now();
+
+            while let Some(Ok(key)) = keys.next() {
+                match key {
+                    Key::Ctrl('c') => break 'running,
+                    Key::Ctrl(c) => {
+                        if let Some(control) = Control::from_ctrl_key(c) {
+                            machine.control(control);
+                        }
+                        continue
+                    }
+                    _ => {}
+                }
+
+                let code = match hack_code(&key) {
+                    Some(code) => code,
+                    None => continue
+                };
+
+                let hold = match held {
+                    Some((ref k, _)) if *k == key => REPEAT_HOLD_MS,
+                    _ => INITIAL_HOLD_MS
+                };
+                held = Some((key, started + Duration::from_millis(hold)));
+                machine.press(code);
+            }
+
+            if held.as_ref().map_or(false, |&(_, until)| until <= started) {
+                held = None;
+                machine.release();
+            }
+
+            machine.update();
+
+            if update % UPDATES_PER_FRAME == 0 {
+                draw(&mut stdout, machine, half_blocks).expect("Failed to draw screen");
+            }
+
+            let elapsed = started.elapsed();
+            if elapsed < update_interval {
+                thread::sleep(update_interval - elapsed);
+            }
+        }
+
+        write!(stdout, "{}", cursor::Show).unwrap();
+    }
+
+    if let (Some(path), Some(recording)) = (record, machine.recording()) {
+        try!(recording.save(path));
+        if verbosity >= Verbosity::Normal {
+            println!("Input recorded to: '{}'", path);
+        }
+    }
+    Ok(true)
+}
+
+fn hack_code(key: &Key) -> Option<u16> {
+    let special = match *key {
+        Key::Char('\n') => SpecialKey::Newline,
+        Key::Char(c) => return keyboard::char_code(c),
+        Key::Backspace => SpecialKey::Backspace,
+        Key::Left => SpecialKey::Left,
+        Key::Up => SpecialKey::Up,
+        Key::Right => SpecialKey::Right,
+        Key::Down => SpecialKey::Down,
+        Key::Home => SpecialKey::Home,
+        Key::End => SpecialKey::End,
+        Key::PageUp => SpecialKey::PageUp,
+        Key::PageDown => SpecialKey::PageDown,
+        Key::Insert => SpecialKey::Insert,
+        Key::Delete => SpecialKey::Delete,
+        Key::Esc => SpecialKey::Esc,
+        Key::F(n) if n >= 1 && n <= 12 => SpecialKey::F(n),
+        _ => return None
+    };
+
+    Some(special.code())
+}
+
+fn draw<W: Write>(out: &mut W, machine: &Machine, half_blocks: bool) -> io::Result<()> {
+    let screen = Screen::from_ram(&machine.cpu.ram);
+    let lines = if half_blocks { screen.to_half_blocks(2) } else { screen.to_braille() };
+    let panel = side_panel(machine, lines.len());
+
+    for (row, line) in lines.iter().enumerate() {
+        try!(write!(out, "{}{}│ ", cursor::Goto(1, row as u16 + 1), line));
+        if let Some(info) = panel.get(row) {
+            try!(write_line(out, info));
+        }
+        try!(write!(out, "{}", clear::UntilNewline));
+    }
+    try!(write!(out, "{}{}{}", cursor::Goto(1, lines.len() as u16 + 1),
+                               machine.status(), clear::UntilNewline));
+    try!(write!(out, "{}^C quit  {}{}", cursor::Goto(1, lines.len() as u16 + 2),
+                                        Control::help(), clear::UntilNewline));
+    out.flush()
+}
+
+fn write_line<W: Write>(out: &mut W, line: &Line) -> io::Result<()> {
+    for span in line {
+        try!(match span.style {
+            Style::Normal => write!(out, "{}", span.text),
+            Style::Heading => write!(out, "{}{}{}", color::Fg(color::Yellow), span.text, style::Reset),
+            Style::Current => write!(out, "{}{}{}", style::Invert, span.text, style::Reset),
+            Style::Recent => write!(out, "{}{}{}", color::Fg(color::Red), span.text, style::Reset),
+        });
+    }
+    Ok(())
+}
+
+fn side_panel(machine: &Machine, rows: usize) -> Vec<Line> {
+    let mut panel = debug::registers(machine);
+    panel.push(vec![]);
+    panel.push(heading("Disassembly"));
+    panel.extend(debug::disassembly(machine, DISASSEMBLY_ROWS));
+    panel.push(vec![]);
+    panel.push(heading("Stack"));
+    panel.extend(debug::stack(machine, STACK_ROWS));
+    panel.push(vec![]);
+    panel.push(heading("RAM"));
+
+    // Fill the rest with RAM around the latest write
+    let ram_rows = rows.saturating_sub(panel.len());
+    let follow = debug::last_written(machine).unwrap_or(0);
+    let start = follow.saturating_sub((ram_rows / 2) * RAM_WORDS_PER_ROW);
+    panel.extend(debug::ram(machine, start, ram_rows, RAM_WORDS_PER_ROW));
+
+    panel
+}
@@ -0,0 +1,222 @@
+use parser::{Command, Op, Segment};
+
+// Where the stack starts, set up by the bootstrap code
+const STACK_BASE: u16 = 256;
+const POINTER_BASE: u16 = 3;
+const TEMP_BASE: u16 = 5;
+
+/// Writes the Hack assembly for VM commands
+pub struct CodeWriter {
+    output: String,
+    // Static variables are named after the file they're declared in
+    file: String,
+    // Labels are local to the function they're declared in
+    function: String,
+    // Makes the labels of comparisons and return addresses unique
+    next_label: usize,
+}
+
+impl CodeWriter {
+    pub fn new() -> CodeWriter {
+        CodeWriter { output: String::new(), file: String::new(), function: String::new(), next_label: 0 }
+    }
+
+    /// Start translating the file called `name`, without its extension
+    pub fn set_file_name(&mut self, name: &str) {
+        self.file = name.to_string();
+        self.function = String::new();
+    }
+
+    /// Set the stack pointer up and call `Sys.init`
+    pub fn write_init(&mut self) {
+        self.emit(&["// bootstrap", &format!("@{}", STACK_BASE), "D=A", "@SP", "M=D"]);
+        self.write_call("Sys.init", 0);
+    }
+
+    pub fn write(&mut self, command: &Command) {
+        self.output.push_str(&format!("// {}\n", describe(command)));
+
+        match *command {
+            Command::Arithmetic(op) => self.write_arithmetic(op),
+            Command::Push(segment, index) => self.write_push(segment, index),
+            Command::Pop(segment, index) => self.write_pop(segment, index),
+            Command::Label(label) => {
+                let label = self.label(label);
+                self.emit(&[&format!("({})", label)])
+            }
+            Command::Goto(label) => {
+                let label = self.label(label);
+                self.emit(&[&format!("@{}", label), "0;JMP"])
+            }
+            Command::IfGoto(label) => {
+                let label = self.label(label);
+                self.emit(&["@SP", "AM=M-1", "D=M", &format!("@{}", label), "D;JNE"])
+            }
+            Command::Function(name, locals) => {
+                self.function = name.to_string();
+                self.emit(&[&format!("({})", name)]);
+                for _ in 0..locals {
+                    self.emit(&["@SP", "A=M", "M=0", "@SP", "M=M+1"]);
+                }
+            }
+            Command::Call(name, args) => self.write_call(name, args),
+            Command::Return => self.write_return(),
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.output
+    }
+
+    fn write_arithmetic(&mut self, op: Op) {
+        match op {
+            Op::Neg => self.emit(&["@SP", "A=M-1", "M=-M"]),
+            Op::Not => self.emit(&["@SP", "A=M-1", "M=!M"]),
+            Op::Add => self.binary("M=D+M"),
+            Op::Sub => self.binary("M=M-D"),
+            Op::And => self.binary("M=D&M"),
+            Op::Or => self.binary("M=D|M"),
+            Op::Eq => self.compare("JEQ"),
+            Op::Gt => self.compare("JGT"),
+            Op::Lt => self.compare("JLT"),
+        }
+    }
+
+    // Pop y into D and point A at x, then combine them into x
+    fn binary(&mut self, combine: &str) {
+        self.emit(&["@SP", "AM=M-1", "D=M", "A=A-1", combine]);
+    }
+
+    // Compare x with y, leaving true (-1) or false (0) in place of x
+    fn compare(&mut self, jump: &str) {
+        let done = self.unique_label("CMP");
+        self.emit(&["@SP", "AM=M-1", "D=M", "A=A-1", "D=M-D", "M=-1",
+                    &format!("@{}", done), &format!("D;{}", jump),
+                    "@SP", "A=M-1", "M=0",
+                    &format!("({})", done)]);
+    }
+
+    fn write_push(&mut self, segment: Segment, index: u16) {
+        match segment {
+            Segment::Constant => self.emit(&[&format!("@{}", index), "D=A"]),
+            Segment::Local | Segment::Argument | Segment::This | Segment::That => {
+                self.emit(&[&format!("@{}", index), "D=A", &format!("@{}", base(segment)), "A=D+M", "D=M"])
+            }
+            _ => {
+                let address = self.address(segment, index);
+                self.emit(&[&format!("@{}", address), "D=M"])
+            }
+        }
+        self.push_d();
+    }
+
+    fn write_pop(&mut self, segment: Segment, index: u16) {
+        match segment {
+            Segment::Local | Segment::Argument | Segment::This | Segment::That => {
+                // Keep the target address in R13 while popping
+                self.emit(&[&format!("@{}", index), "D=A", &format!("@{}", base(segment)), "D=D+M",
+                            "@R13", "M=D"]);
+                self.pop_d();
+                self.emit(&["@R13", "A=M", "M=D"]);
+            }
+            _ => {
+                let address = self.address(segment, index);
+                self.pop_d();
+                self.emit(&[&format!("@{}", address), "M=D"]);
+            }
+        }
+    }
+
+    fn write_call(&mut self, name: &str, args: u16) {
+        let return_address = self.unique_label("ret");
+
+        self.emit(&[&format!("@{}", return_address), "D=A"]);
+        self.push_d();
+        for register in &["LCL", "ARG", "THIS", "THAT"] {
+            self.emit(&[&format!("@{}", register), "D=M"]);
+            self.push_d();
+        }
+
+        // ARG = SP - args - 5, LCL = SP
+        self.emit(&["@SP", "D=M", &format!("@{}", args + 5), "D=D-A", "@ARG", "M=D",
+                    "@SP", "D=M", "@LCL", "M=D",
+                    &format!("@{}", name), "0;JMP",
+                    &format!("({})", return_address)]);
+    }
+
+    fn write_return(&mut self) {
+        // The frame in R13 and the return address in R14, before the
+        // return value may overwrite it
+        self.emit(&["@LCL", "D=M", "@R13", "M=D", "@5", "A=D-A", "D=M", "@R14", "M=D"]);
+        self.pop_d();
+        self.emit(&["@ARG", "A=M", "M=D", "@ARG", "D=M+1", "@SP", "M=D"]);
+        for register in &["THAT", "THIS", "ARG", "LCL"] {
+            self.emit(&["@R13", "AM=M-1", "D=M", &format!("@{}", register), "M=D"]);
+        }
+        self.emit(&["@R14", "A=M", "0;JMP"]);
+    }
+
+    fn push_d(&mut self) {
+        self.emit(&["@SP", "A=M", "M=D", "@SP", "M=M+1"]);
+    }
+
+    fn pop_d(&mut self) {
+        self.emit(&["@SP", "AM=M-1", "D=M"]);
+    }
+
+    // The symbol for a segment entry at a fixed address
+    fn address(&self, segment: Segment, index: u16) -> String {
+        match segment {
+            Segment::Pointer => format!("{}", POINTER_BASE + index),
+            Segment::Temp => format!("{}", TEMP_BASE + index),
+            Segment::Static => format!("{}.{}", self.file, index),
+            _ => unreachable!(),
+        }
+    }
+
+    fn label(&self, label: &str) -> String {
+        if self.function.is_empty() {
+            label.to_string()
+        } else {
+            format!("{}${}", self.function, label)
+        }
+    }
+
+    fn unique_label(&mut self, kind: &str) -> String {
+        self.next_label += 1;
+        let scope = if self.function.is_empty() { &self.file } else { &self.function };
+        format!("{}${}.{}", scope, kind, self.next_label)
+    }
+
+    fn emit(&mut self, lines: &[&str]) {
+        for line in lines {
+            self.output.push_str(line);
+            self.output.push('\n');
+        }
+    }
+}
+
+fn base(segment: Segment) -> &'static str {
+    match segment {
+        Segment::Local => "LCL",
+        Segment::Argument => "ARG",
+        Segment::This => "THIS",
+        Segment::That => "THAT",
+        _ => unreachable!(),
+    }
+}
+
+/// A command as it's written in VM code
+pub fn describe(command: &Command) -> String {
+    match *command {
+        Command::Arithmetic(op) => format!("{:?}", op).to_lowercase(),
+        Command::Push(segment, index) => format!("push {} {}", segment, index),
+        Command::Pop(segment, index) => format!("pop {} {}", segment, index),
+        Command::Label(label) => format!("label {}", label),
+        Command::Goto(label) => format!("goto {}", label),
+        Command::IfGoto(label) => format!("if-goto {}", label),
+        Command::Function(name, locals) => format!("function {} {}", name, locals),
+        Command::Call(name, args) => format!("call {} {}", name, args),
+        Command::Return => "return".to_string(),
+    }
+}
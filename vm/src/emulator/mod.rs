@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+
+use code_writer::describe;
+use parser::{parse, Command, Op, Segment};
+use self::os::Os;
+
+mod os;
+
+pub type Word = u16;
+
+/// The Hack platform's RAM, up to and including the keyboard
+pub const RAM_SIZE: usize = 24577;
+const POINTER_BASE: usize = 3;
+const TEMP_BASE: usize = 5;
+// Static variables are allocated from here in the order files are loaded
+const STATIC_BASE: usize = 16;
+const STATIC_END: usize = 256;
+const STACK_BASE: Word = 256;
+
+const SP: usize = 0;
+const LCL: usize = 1;
+const ARG: usize = 2;
+const THIS: usize = 3;
+const THAT: usize = 4;
+
+// A command with its labels, calls and static variables resolved
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Instruction {
+    Arithmetic(Op),
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    PushStatic(usize),
+    PopStatic(usize),
+    Goto(usize),
+    IfGoto(usize),
+    Function(u16),
+    Call(usize, u16),
+    /// A call to an OS function implemented natively
+    Builtin(usize, u16),
+    Return,
+    /// Set the stack up and initialise the OS classes, as `Sys.init` does
+    Init,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    /// Index of the function's first command
+    pub start: usize,
+}
+
+/// Where a command came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub file: String,
+    pub line: usize,
+    pub text: String,
+}
+
+/// Executes VM code directly, keeping its stack and segments in the same
+/// RAM layout as translated code would
+pub struct Vm {
+    pub ram: Vec<Word>,
+    /// Index of the next command to execute
+    pub pc: usize,
+    program: Vec<Instruction>,
+    sources: Vec<Source>,
+    functions: Vec<Function>,
+    function_index: HashMap<String, usize>,
+    // Functions entered and not returned from, for debugging
+    calls: Vec<usize>,
+    start: usize,
+    steps: u64,
+    os: Os,
+}
+
+impl Vm {
+    /// Load VM files, given as their names without extension and their
+    /// source. Execution starts at `Sys.init` if there is one, otherwise at
+    /// the first command.
+    ///
+    /// Calls to OS classes none of the files declare run built-in versions
+    /// instead, and without a `Sys.init`, a program with a `Main.main`
+    /// starts with the bootstrap of the built-in `Sys.init`.
+    pub fn new(files: &[(&str, &str)]) -> Result<Vm, String> {
+        let mut program = vec![];
+        let mut sources = vec![];
+        let mut functions: Vec<Function> = vec![];
+        let mut function_index = HashMap::new();
+        let mut next_static = STATIC_BASE;
+        // Labels and calls are resolved once every function is known
+        let mut pending: Vec<(usize, Command, String)> = vec![];
+        let mut labels = HashMap::new();
+
+        for &(name, source) in files {
+            let lines = try!(parse(source).map_err(|e| format!("{}.vm: {}", name, e)));
+            let statics = next_static;
+            let mut scope = String::new();
+
+            for line in lines {
+                let error = |message: String| format!("{}.vm: line {}: {}", name, line.number, message);
+                let index = program.len();
+                let instruction = match line.command {
+                    Command::Arithmetic(op) => Instruction::Arithmetic(op),
+                    Command::Push(Segment::Static, i) | Command::Pop(Segment::Static, i) => {
+                        let address = statics + i as usize;
+                        if address >= STATIC_END {
+                            return Err(error(format!("Too many static variables: static {}", i)))
+                        }
+                        next_static = next_static.max(address + 1);
+                        match line.command {
+                            Command::Push(..) => Instruction::PushStatic(address),
+                            _ => Instruction::PopStatic(address),
+                        }
+                    }
+                    Command::Push(segment, i) => Instruction::Push(segment, i),
+                    Command::Pop(segment, i) => Instruction::Pop(segment, i),
+                    Command::Label(label) => {
+                        if labels.insert(format!("{}${}", scope, label), index).is_some() {
+                            return Err(error(format!("Label declared twice: '{}'", label)))
+                        }
+                        continue
+                    }
+                    Command::Function(function, locals) => {
+                        if function_index.insert(function.to_string(), functions.len()).is_some() {
+                            return Err(error(format!("Function declared twice: '{}'", function)))
+                        }
+                        functions.push(Function { name: function.to_string(), start: index });
+                        scope = function.to_string();
+                        Instruction::Function(locals)
+                    }
+                    Command::Return => Instruction::Return,
+                    Command::Goto(..) | Command::IfGoto(..) | Command::Call(..) => {
+                        pending.push((index, line.command.clone(), scope.clone()));
+                        // Replaced once the target is known
+                        Instruction::Return
+                    }
+                };
+
+                program.push(instruction);
+                sources.push(Source { file: name.to_string(), line: line.number, text: describe(&line.command) });
+            }
+        }
+
+        for (index, command, scope) in pending {
+            let source = &sources[index];
+            let error = |message: String| format!("{}.vm: line {}: {}", source.file, source.line, message);
+            let label = |label: &str| match labels.get(&format!("{}${}", scope, label)) {
+                Some(&target) => Ok(target),
+                None => Err(error(format!("Unknown label: '{}'", label))),
+            };
+
+            program[index] = match command {
+                Command::Goto(target) => Instruction::Goto(try!(label(target))),
+                Command::IfGoto(target) => Instruction::IfGoto(try!(label(target))),
+                Command::Call(name, args) => match function_index.get(name) {
+                    Some(&function) => Instruction::Call(function, args),
+                    None => match os::builtin(name) {
+                        Some((builtin, expected)) if !declares_class(&function_index, name) => {
+                            if args != expected {
+                                return Err(error(format!("'{}' takes {} arguments, but is called with {}",
+                                                         name, expected, args)))
+                            }
+                            Instruction::Builtin(builtin, args)
+                        }
+                        _ => return Err(error(format!("Unknown function: '{}'", name))),
+                    },
+                },
+                _ => unreachable!(),
+            };
+        }
+
+        let mut start = function_index.get("Sys.init").map_or(0, |&f| functions[f].start);
+        if !declares_class(&function_index, "Sys.init") && function_index.contains_key("Main.main") {
+            start = program.len();
+            let halt = os::builtin("Sys.halt").unwrap().0;
+            let bootstrap = [(Instruction::Init, "call Sys.init 0".to_string()),
+                             (Instruction::Call(function_index["Main.main"], 0), "call Main.main 0".to_string()),
+                             (Instruction::Pop(Segment::Temp, 0), "pop temp 0".to_string()),
+                             (Instruction::Builtin(halt, 0), "call Sys.halt 0".to_string())];
+            functions.push(Function { name: "Sys.init".to_string(), start: start });
+            for (line, (instruction, text)) in bootstrap.iter().cloned().enumerate() {
+                program.push(instruction);
+                sources.push(Source { file: "Sys".to_string(), line: line + 1, text: text });
+            }
+        }
+
+        Ok(Vm {
+            ram: vec![0; RAM_SIZE],
+            pc: start,
+            program: program,
+            sources: sources,
+            functions: functions,
+            function_index: function_index,
+            calls: vec![],
+            start: start,
+            steps: 0,
+            os: Os::default(),
+        })
+    }
+
+    /// Start over with cleared memory
+    pub fn reset(&mut self) {
+        self.ram = vec![0; RAM_SIZE];
+        self.pc = self.start;
+        self.calls.clear();
+        self.steps = 0;
+        self.os = Os::default();
+    }
+
+    /// Has execution run off the end of the program, or called `Sys.halt`?
+    pub fn halted(&self) -> bool {
+        self.pc >= self.program.len() || self.os.halted
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Execute the next command
+    pub fn step(&mut self) -> Result<(), String> {
+        if self.halted() {
+            return Ok(())
+        }
+
+        let index = self.pc;
+        let instruction = self.program[index];
+        self.pc += 1;
+        self.steps += 1;
+
+        let result = self.execute(instruction);
+        result.map_err(|e| {
+            let source = &self.sources[index];
+            format!("{}.vm: line {}: '{}': {}", source.file, source.line, source.text, e)
+        })
+    }
+
+    /// Execute up to `steps` commands
+    pub fn run(&mut self, steps: u64) -> Result<(), String> {
+        for _ in 0..steps {
+            try!(self.step());
+        }
+        Ok(())
+    }
+
+    /// Call a function, or the built-in version of an OS function, and run
+    /// it until it returns, giving its result. The stack must be set up.
+    pub fn call(&mut self, name: &str, args: &[Word]) -> Result<Word, String> {
+        if let Some(&function) = self.function_index.get(name) {
+            return self.call_function(function, args)
+        }
+        match os::builtin(name) {
+            Some((builtin, expected)) if !declares_class(&self.function_index, name) => {
+                if args.len() != expected as usize {
+                    return Err(format!("'{}' takes {} arguments, but is called with {}", name, expected, args.len()))
+                }
+                match try!(os::run(self, builtin, args)) {
+                    Some(value) => Ok(value),
+                    None => Err(format!("'{}' can't wait for input here", name)),
+                }
+            }
+            _ => Err(format!("Unknown function: '{}'", name)),
+        }
+    }
+
+    fn call_function(&mut self, function: usize, args: &[Word]) -> Result<Word, String> {
+        let (pc, depth) = (self.pc, self.calls.len());
+        for &arg in args {
+            try!(self.push(arg));
+        }
+        // Return past the end of the program, where nothing else returns to
+        self.pc = self.program.len();
+        try!(self.execute(Instruction::Call(function, args.len() as u16)));
+        while self.calls.len() > depth {
+            if self.os.halted {
+                return Ok(0)
+            }
+            try!(self.step());
+        }
+        self.pc = pc;
+        self.pop()
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<(), String> {
+        match instruction {
+            Instruction::Arithmetic(op) => return self.arithmetic(op),
+            Instruction::Push(segment, i) => {
+                let value = try!(self.segment(segment, i));
+                try!(self.push(value))
+            }
+            Instruction::Pop(segment, i) => {
+                let value = try!(self.pop());
+                try!(self.set_segment(segment, i, value))
+            }
+            Instruction::PushStatic(address) => {
+                let value = self.ram[address];
+                try!(self.push(value))
+            }
+            Instruction::PopStatic(address) => self.ram[address] = try!(self.pop()),
+            Instruction::Goto(target) => self.pc = target,
+            Instruction::IfGoto(target) => {
+                if try!(self.pop()) != 0 {
+                    self.pc = target
+                }
+            }
+            Instruction::Function(locals) => {
+                for _ in 0..locals {
+                    try!(self.push(0));
+                }
+            }
+            Instruction::Call(function, args) => {
+                let return_address = self.pc as Word;
+                try!(self.push(return_address));
+                for &register in &[LCL, ARG, THIS, THAT] {
+                    let value = self.ram[register];
+                    try!(self.push(value));
+                }
+                let sp = self.ram[SP];
+                self.ram[ARG] = sp.wrapping_sub(args + 5);
+                self.ram[LCL] = sp;
+                self.pc = self.functions[function].start;
+                self.calls.push(function);
+            }
+            Instruction::Builtin(builtin, args) => {
+                let sp = self.ram[SP] as usize;
+                let first = try!(sp.checked_sub(args as usize).ok_or("Stack underflow".to_string()));
+                let values = try!(self.ram.get(first..sp).ok_or(format!("Address out of range: {}", sp))).to_vec();
+                match try!(os::run(self, builtin, &values)) {
+                    Some(value) => {
+                        self.ram[SP] = first as Word;
+                        try!(self.push(value))
+                    }
+                    // Waiting for input or time to pass, so try again next step
+                    None => self.pc -= 1,
+                }
+            }
+            Instruction::Init => {
+                self.ram[SP] = STACK_BASE;
+                for class in &["Memory", "Math", "Screen", "Output", "Keyboard"] {
+                    let init = format!("{}.init", class);
+                    if self.function_index.contains_key(&init) || !declares_class(&self.function_index, &init) {
+                        try!(self.call(&init, &[]));
+                    }
+                }
+            }
+            Instruction::Return => {
+                let frame = self.ram[LCL] as usize;
+                let saved = |vm: &Vm, offset: usize| -> Result<Word, String> {
+                    let address = try!(frame.checked_sub(offset).ok_or("Return without a frame".to_string()));
+                    vm.read(address)
+                };
+                let return_address = try!(saved(self, 5));
+                let value = try!(self.pop());
+                let arg = self.ram[ARG] as usize;
+                try!(self.write(arg, value));
+                self.ram[SP] = (arg + 1) as Word;
+                self.ram[THAT] = try!(saved(self, 1));
+                self.ram[THIS] = try!(saved(self, 2));
+                self.ram[ARG] = try!(saved(self, 3));
+                self.ram[LCL] = try!(saved(self, 4));
+                self.pc = return_address as usize;
+                self.calls.pop();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn arithmetic(&mut self, op: Op) -> Result<(), String> {
+        let y = try!(self.pop());
+        let result = match op {
+            Op::Neg => y.wrapping_neg(),
+            Op::Not => !y,
+            _ => {
+                let x = try!(self.pop());
+                let truth = |b: bool| if b { 0xFFFF } else { 0 };
+                match op {
+                    Op::Add => x.wrapping_add(y),
+                    Op::Sub => x.wrapping_sub(y),
+                    Op::And => x & y,
+                    Op::Or => x | y,
+                    Op::Eq => truth(x == y),
+                    Op::Gt => truth((x as i16) > (y as i16)),
+                    Op::Lt => truth((x as i16) < (y as i16)),
+                    Op::Neg | Op::Not => unreachable!(),
+                }
+            }
+        };
+        self.push(result)
+    }
+
+    fn push(&mut self, value: Word) -> Result<(), String> {
+        let sp = self.ram[SP] as usize;
+        try!(self.write(sp, value));
+        self.ram[SP] += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Word, String> {
+        let sp = self.ram[SP].wrapping_sub(1);
+        let value = try!(self.read(sp as usize));
+        self.ram[SP] = sp;
+        Ok(value)
+    }
+
+    /// The address of an entry of a segment, other than `constant` and `static`
+    pub fn address(&self, segment: Segment, i: u16) -> Result<usize, String> {
+        let i = i as usize;
+        let address = match segment {
+            Segment::Local => self.ram[LCL] as usize + i,
+            Segment::Argument => self.ram[ARG] as usize + i,
+            Segment::This => self.ram[THIS] as usize + i,
+            Segment::That => self.ram[THAT] as usize + i,
+            Segment::Pointer => POINTER_BASE + i,
+            Segment::Temp => TEMP_BASE + i,
+            Segment::Constant | Segment::Static => {
+                return Err(format!("The {} segment has no address", segment))
+            }
+        };
+
+        if address >= RAM_SIZE {
+            return Err(format!("{} {} is outside of RAM, at {}", segment, i, address))
+        }
+        Ok(address)
+    }
+
+    /// Read an entry of a segment, other than `static`
+    pub fn segment(&self, segment: Segment, i: u16) -> Result<Word, String> {
+        match segment {
+            Segment::Constant => Ok(i),
+            _ => self.address(segment, i).map(|address| self.ram[address]),
+        }
+    }
+
+    /// Write an entry of a segment, other than `constant` and `static`
+    pub fn set_segment(&mut self, segment: Segment, i: u16, value: Word) -> Result<(), String> {
+        let address = try!(self.address(segment, i));
+        self.ram[address] = value;
+        Ok(())
+    }
+
+    fn read(&self, address: usize) -> Result<Word, String> {
+        self.ram.get(address).cloned().ok_or(format!("Address out of range: {}", address))
+    }
+
+    fn write(&mut self, address: usize, value: Word) -> Result<(), String> {
+        match self.ram.get_mut(address) {
+            Some(word) => { *word = value; Ok(()) }
+            None => Err(format!("Address out of range: {}", address)),
+        }
+    }
+
+    /// The function the next command belongs to
+    pub fn current_function(&self) -> Option<&str> {
+        self.functions.iter().rev().find(|f| f.start <= self.pc).map(|f| &f.name[..])
+    }
+
+    /// Names of the functions called and not yet returned from, outermost first
+    pub fn call_stack(&self) -> Vec<&str> {
+        self.calls.iter().map(|&f| &self.functions[f].name[..]).collect()
+    }
+
+    /// Where the next command came from
+    pub fn source(&self) -> Option<&Source> {
+        self.sources.get(self.pc)
+    }
+}
+
+// Whether the class of a function is declared by the loaded files
+fn declares_class(functions: &HashMap<String, usize>, name: &str) -> bool {
+    let class = name.split('.').next().unwrap();
+    functions.keys().any(|f| f.split('.').next() == Some(class))
+}
@@ -0,0 +1,750 @@
+//! Native versions of the Jack OS classes. They keep their data in the same
+//! RAM as VM code would: objects on the heap, text and drawings in the screen
+//! memory and the pressed key read from the keyboard register. Calls between
+//! classes go through `Vm::call`, so a class loaded from a .vm file replaces
+//! the built-in one for the other built-ins too.
+
+use super::{Vm, Word};
+
+const HEAP_BASE: usize = 2048;
+const HEAP_END: usize = 16384;
+const SCREEN: usize = 16384;
+const KEYBOARD: usize = 24576;
+const SCREEN_WIDTH: i32 = 512;
+const SCREEN_HEIGHT: i32 = 256;
+// Text is laid out in cells of 8x11 pixels
+const ROWS: usize = 23;
+const COLUMNS: usize = 64;
+const LINE_LENGTH: Word = 80;
+// `Sys.wait` counts VM steps rather than wall time
+const STEPS_PER_MILLISECOND: u64 = 100;
+
+const NEW_LINE: Word = 128;
+const BACKSPACE: Word = 129;
+const DOUBLE_QUOTE: Word = 34;
+
+type Native = fn(&mut Vm, &[Word]) -> Result<Option<Word>, String>;
+
+static BUILTINS: &'static [(&'static str, u16, Native)] = &[
+    ("Math.init", 0, Vm::nothing),
+    ("Math.abs", 1, Vm::math_abs),
+    ("Math.multiply", 2, Vm::math_multiply),
+    ("Math.divide", 2, Vm::math_divide),
+    ("Math.min", 2, Vm::math_min),
+    ("Math.max", 2, Vm::math_max),
+    ("Math.sqrt", 1, Vm::math_sqrt),
+    ("String.new", 1, Vm::string_new),
+    ("String.dispose", 1, Vm::dispose),
+    ("String.length", 1, Vm::string_length),
+    ("String.charAt", 2, Vm::string_char_at),
+    ("String.setCharAt", 3, Vm::string_set_char_at),
+    ("String.appendChar", 2, Vm::string_append_char),
+    ("String.eraseLastChar", 1, Vm::string_erase_last_char),
+    ("String.intValue", 1, Vm::string_int_value),
+    ("String.setInt", 2, Vm::string_set_int),
+    ("String.backSpace", 0, Vm::string_backspace),
+    ("String.doubleQuote", 0, Vm::string_double_quote),
+    ("String.newLine", 0, Vm::string_new_line),
+    ("Array.new", 1, Vm::array_new),
+    ("Array.dispose", 1, Vm::dispose),
+    ("Output.init", 0, Vm::output_init),
+    ("Output.moveCursor", 2, Vm::output_move_cursor),
+    ("Output.printChar", 1, Vm::output_print_char),
+    ("Output.printString", 1, Vm::output_print_string),
+    ("Output.printInt", 1, Vm::output_print_int),
+    ("Output.println", 0, Vm::output_println),
+    ("Output.backSpace", 0, Vm::output_backspace),
+    ("Screen.init", 0, Vm::screen_init),
+    ("Screen.clearScreen", 0, Vm::screen_clear),
+    ("Screen.setColor", 1, Vm::screen_set_color),
+    ("Screen.drawPixel", 2, Vm::screen_draw_pixel),
+    ("Screen.drawLine", 4, Vm::screen_draw_line),
+    ("Screen.drawRectangle", 4, Vm::screen_draw_rectangle),
+    ("Screen.drawCircle", 3, Vm::screen_draw_circle),
+    ("Keyboard.init", 0, Vm::nothing),
+    ("Keyboard.keyPressed", 0, Vm::keyboard_key_pressed),
+    ("Keyboard.readChar", 0, Vm::keyboard_read_char),
+    ("Keyboard.readLine", 1, Vm::keyboard_read_line),
+    ("Keyboard.readInt", 1, Vm::keyboard_read_int),
+    ("Memory.init", 0, Vm::memory_init),
+    ("Memory.peek", 1, Vm::memory_peek),
+    ("Memory.poke", 2, Vm::memory_poke),
+    ("Memory.alloc", 1, Vm::memory_alloc),
+    ("Memory.deAlloc", 1, Vm::memory_dealloc),
+    ("Sys.halt", 0, Vm::sys_halt),
+    ("Sys.error", 1, Vm::sys_error),
+    ("Sys.wait", 1, Vm::sys_wait),
+];
+
+// The error codes of `Sys.error`
+static ERRORS: &'static [(Word, &'static str)] = &[
+    (1, "Duration must be positive"),
+    (2, "Array size must be positive"),
+    (3, "Division by zero"),
+    (4, "Cannot compute square root of a negative number"),
+    (5, "Allocated memory size must be positive"),
+    (6, "Heap overflow"),
+    (7, "Illegal pixel coordinates"),
+    (8, "Illegal line coordinates"),
+    (9, "Illegal rectangle coordinates"),
+    (12, "Illegal center coordinates"),
+    (13, "Illegal radius"),
+    (14, "Maximum length must be non-negative"),
+    (15, "String index out of bounds"),
+    (16, "String index out of bounds"),
+    (17, "String is full"),
+    (18, "String is empty"),
+    (19, "Insufficient string capacity"),
+    (20, "Illegal cursor location"),
+];
+
+// The font of the official Output class, one byte per row with the
+// leftmost pixel in the least significant bit, for characters 32 to 126
+static FONT: [[u8; 11]; 95] = [
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [12, 30, 30, 30, 12, 12, 0, 12, 12, 0, 0],
+    [54, 54, 20, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 18, 18, 63, 18, 18, 63, 18, 18, 0, 0],
+    [12, 30, 51, 3, 30, 48, 51, 30, 12, 12, 0],
+    [0, 0, 35, 51, 24, 12, 6, 51, 49, 0, 0],
+    [12, 30, 30, 12, 54, 27, 27, 27, 54, 0, 0],
+    [12, 12, 6, 0, 0, 0, 0, 0, 0, 0, 0],
+    [24, 12, 6, 6, 6, 6, 6, 12, 24, 0, 0],
+    [6, 12, 24, 24, 24, 24, 24, 12, 6, 0, 0],
+    [0, 0, 0, 51, 30, 63, 30, 51, 0, 0, 0],
+    [0, 0, 0, 12, 12, 63, 12, 12, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 12, 12, 6, 0],
+    [0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 12, 12, 0, 0],
+    [0, 0, 32, 48, 24, 12, 6, 3, 1, 0, 0],
+    [12, 30, 51, 51, 51, 51, 51, 30, 12, 0, 0],
+    [12, 14, 15, 12, 12, 12, 12, 12, 63, 0, 0],
+    [30, 51, 48, 24, 12, 6, 3, 51, 63, 0, 0],
+    [30, 51, 48, 48, 28, 48, 48, 51, 30, 0, 0],
+    [16, 24, 28, 26, 25, 63, 24, 24, 60, 0, 0],
+    [63, 3, 3, 31, 48, 48, 48, 51, 30, 0, 0],
+    [28, 6, 3, 3, 31, 51, 51, 51, 30, 0, 0],
+    [63, 49, 48, 48, 24, 12, 12, 12, 12, 0, 0],
+    [30, 51, 51, 51, 30, 51, 51, 51, 30, 0, 0],
+    [30, 51, 51, 51, 62, 48, 48, 24, 14, 0, 0],
+    [0, 0, 12, 12, 0, 0, 12, 12, 0, 0, 0],
+    [0, 0, 12, 12, 0, 0, 12, 12, 6, 0, 0],
+    [0, 0, 24, 12, 6, 3, 6, 12, 24, 0, 0],
+    [0, 0, 0, 63, 0, 0, 63, 0, 0, 0, 0],
+    [0, 0, 3, 6, 12, 24, 12, 6, 3, 0, 0],
+    [30, 51, 51, 24, 12, 12, 0, 12, 12, 0, 0],
+    [30, 51, 51, 59, 59, 59, 27, 3, 30, 0, 0],
+    [12, 30, 51, 51, 63, 51, 51, 51, 51, 0, 0],
+    [31, 51, 51, 51, 31, 51, 51, 51, 31, 0, 0],
+    [28, 54, 35, 3, 3, 3, 35, 54, 28, 0, 0],
+    [15, 27, 51, 51, 51, 51, 51, 27, 15, 0, 0],
+    [63, 51, 35, 11, 15, 11, 35, 51, 63, 0, 0],
+    [63, 51, 35, 11, 15, 11, 3, 3, 3, 0, 0],
+    [28, 54, 35, 3, 59, 51, 51, 54, 44, 0, 0],
+    [51, 51, 51, 51, 63, 51, 51, 51, 51, 0, 0],
+    [30, 12, 12, 12, 12, 12, 12, 12, 30, 0, 0],
+    [60, 24, 24, 24, 24, 24, 27, 27, 14, 0, 0],
+    [51, 51, 51, 27, 15, 27, 51, 51, 51, 0, 0],
+    [3, 3, 3, 3, 3, 3, 35, 51, 63, 0, 0],
+    [33, 51, 63, 63, 51, 51, 51, 51, 51, 0, 0],
+    [51, 51, 55, 55, 63, 59, 59, 51, 51, 0, 0],
+    [30, 51, 51, 51, 51, 51, 51, 51, 30, 0, 0],
+    [31, 51, 51, 51, 31, 3, 3, 3, 3, 0, 0],
+    [30, 51, 51, 51, 51, 51, 63, 59, 30, 48, 0],
+    [31, 51, 51, 51, 31, 27, 51, 51, 51, 0, 0],
+    [30, 51, 51, 6, 28, 48, 51, 51, 30, 0, 0],
+    [63, 63, 45, 12, 12, 12, 12, 12, 30, 0, 0],
+    [51, 51, 51, 51, 51, 51, 51, 51, 30, 0, 0],
+    [51, 51, 51, 51, 51, 30, 30, 12, 12, 0, 0],
+    [51, 51, 51, 51, 51, 63, 63, 63, 18, 0, 0],
+    [51, 51, 30, 30, 12, 30, 30, 51, 51, 0, 0],
+    [51, 51, 51, 51, 30, 12, 12, 12, 30, 0, 0],
+    [63, 51, 49, 24, 12, 6, 35, 51, 63, 0, 0],
+    [30, 6, 6, 6, 6, 6, 6, 6, 30, 0, 0],
+    [0, 0, 1, 3, 6, 12, 24, 48, 32, 0, 0],
+    [30, 24, 24, 24, 24, 24, 24, 24, 30, 0, 0],
+    [8, 28, 54, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 63, 0],
+    [6, 12, 24, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 14, 24, 30, 27, 27, 54, 0, 0],
+    [3, 3, 3, 15, 27, 51, 51, 51, 30, 0, 0],
+    [0, 0, 0, 30, 51, 3, 3, 51, 30, 0, 0],
+    [48, 48, 48, 60, 54, 51, 51, 51, 30, 0, 0],
+    [0, 0, 0, 30, 51, 63, 3, 51, 30, 0, 0],
+    [28, 54, 38, 6, 15, 6, 6, 6, 15, 0, 0],
+    [0, 0, 30, 51, 51, 51, 62, 48, 51, 30, 0],
+    [3, 3, 3, 27, 55, 51, 51, 51, 51, 0, 0],
+    [12, 12, 0, 14, 12, 12, 12, 12, 30, 0, 0],
+    [48, 48, 0, 56, 48, 48, 48, 48, 51, 30, 0],
+    [3, 3, 3, 51, 27, 15, 15, 27, 51, 0, 0],
+    [14, 12, 12, 12, 12, 12, 12, 12, 30, 0, 0],
+    [0, 0, 0, 29, 63, 43, 43, 43, 43, 0, 0],
+    [0, 0, 0, 29, 51, 51, 51, 51, 51, 0, 0],
+    [0, 0, 0, 30, 51, 51, 51, 51, 30, 0, 0],
+    [0, 0, 0, 30, 51, 51, 51, 31, 3, 3, 0],
+    [0, 0, 0, 30, 51, 51, 51, 62, 48, 48, 0],
+    [0, 0, 0, 29, 55, 51, 3, 3, 7, 0, 0],
+    [0, 0, 0, 30, 51, 6, 24, 51, 30, 0, 0],
+    [4, 6, 6, 15, 6, 6, 6, 54, 28, 0, 0],
+    [0, 0, 0, 27, 27, 27, 27, 27, 54, 0, 0],
+    [0, 0, 0, 51, 51, 51, 51, 30, 12, 0, 0],
+    [0, 0, 0, 51, 51, 51, 63, 63, 18, 0, 0],
+    [0, 0, 0, 51, 30, 12, 12, 30, 51, 0, 0],
+    [0, 0, 0, 51, 51, 51, 62, 48, 24, 15, 0],
+    [0, 0, 0, 63, 27, 12, 6, 51, 63, 0, 0],
+    [56, 12, 12, 12, 7, 12, 12, 12, 56, 0, 0],
+    [12, 12, 12, 12, 12, 12, 12, 12, 12, 0, 0],
+    [7, 12, 12, 12, 56, 12, 12, 12, 7, 0, 0],
+    [38, 45, 25, 0, 0, 0, 0, 0, 0, 0, 0],
+];
+// Drawn for characters the font doesn't have, and as the input cursor
+const BLOCK: [u8; 11] = [0, 63, 63, 63, 63, 63, 63, 63, 63, 63, 0];
+
+/// What the built-in classes keep outside of RAM, like the static
+/// variables of the official OS
+#[derive(Debug, Default)]
+pub struct Os {
+    pub halted: bool,
+    heap_ready: bool,
+    // The first block of the heap's free list, 0 when there's none
+    free: usize,
+    row: usize,
+    column: usize,
+    white: bool,
+    // `Keyboard.readChar` shows a cursor, waits for a key and then for its release
+    cursor_shown: bool,
+    key: Option<Word>,
+    // The string `Keyboard.readLine` is reading into
+    line: Option<Word>,
+    wait_until: Option<u64>,
+}
+
+/// The index and argument count of the built-in version of an OS function
+pub fn builtin(name: &str) -> Option<(usize, u16)> {
+    BUILTINS.iter().position(|&(builtin, _, _)| builtin == name).map(|i| (i, BUILTINS[i].1))
+}
+
+/// Run a built-in function, giving `None` while it waits for input or time to pass
+pub fn run(vm: &mut Vm, builtin: usize, args: &[Word]) -> Result<Option<Word>, String> {
+    (BUILTINS[builtin].2)(vm, args)
+}
+
+fn signed(value: Word) -> i32 {
+    value as i16 as i32
+}
+
+fn done(value: Word) -> Result<Option<Word>, String> {
+    Ok(Some(value))
+}
+
+impl Vm {
+    // Stops the program like `Sys.error`, which shows the error code
+    fn os_error(&mut self, code: Word) -> Result<Option<Word>, String> {
+        self.os.halted = true;
+        let message = ERRORS.iter().find(|&&(c, _)| c == code).map_or("Error", |&(_, message)| message);
+        Err(format!("{} (error code {})", message, code))
+    }
+
+    fn nothing(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        done(0)
+    }
+
+    fn math_abs(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        done((args[0] as i16).wrapping_abs() as Word)
+    }
+
+    fn math_multiply(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        done(args[0].wrapping_mul(args[1]))
+    }
+
+    fn math_divide(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        if args[1] == 0 {
+            return self.os_error(3)
+        }
+        done((args[0] as i16).wrapping_div(args[1] as i16) as Word)
+    }
+
+    fn math_min(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        done(if signed(args[0]) < signed(args[1]) { args[0] } else { args[1] })
+    }
+
+    fn math_max(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        done(if signed(args[0]) > signed(args[1]) { args[0] } else { args[1] })
+    }
+
+    fn math_sqrt(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let x = signed(args[0]);
+        if x < 0 {
+            return self.os_error(4)
+        }
+        let mut root = 0;
+        while (root + 1) * (root + 1) <= x {
+            root += 1;
+        }
+        done(root as Word)
+    }
+
+    fn ram_at(&self, address: Word) -> Result<Word, String> {
+        self.read(address as usize)
+    }
+
+    // Strings are laid out as their maximum length, length and characters
+    fn string_new(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        if signed(args[0]) < 0 {
+            return self.os_error(14)
+        }
+        let string = try!(self.call("Memory.alloc", &[args[0] + 2]));
+        try!(self.write(string as usize, args[0]));
+        try!(self.write(string as usize + 1, 0));
+        done(string)
+    }
+
+    fn dispose(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        try!(self.call("Memory.deAlloc", &[args[0]]));
+        done(0)
+    }
+
+    fn string_length(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        self.ram_at(args[0].wrapping_add(1)).map(Some)
+    }
+
+    // The address of character `index`, or None when it's out of bounds
+    fn string_char(&self, string: Word, index: Word) -> Result<Option<usize>, String> {
+        let length = try!(self.ram_at(string.wrapping_add(1)));
+        if signed(index) < 0 || index >= length {
+            return Ok(None)
+        }
+        Ok(Some(string as usize + 2 + index as usize))
+    }
+
+    fn string_char_at(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        match try!(self.string_char(args[0], args[1])) {
+            Some(address) => self.read(address).map(Some),
+            None => self.os_error(15),
+        }
+    }
+
+    fn string_set_char_at(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        match try!(self.string_char(args[0], args[1])) {
+            Some(address) => try!(self.write(address, args[2])),
+            None => return self.os_error(16),
+        }
+        done(0)
+    }
+
+    fn string_append_char(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let string = args[0] as usize;
+        let (capacity, length) = (try!(self.read(string)), try!(self.read(string + 1)));
+        if length >= capacity {
+            return self.os_error(17)
+        }
+        try!(self.write(string + 2 + length as usize, args[1]));
+        try!(self.write(string + 1, length + 1));
+        done(args[0])
+    }
+
+    fn string_erase_last_char(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let length = try!(self.ram_at(args[0].wrapping_add(1)));
+        if length == 0 {
+            return self.os_error(18)
+        }
+        try!(self.write(args[0] as usize + 1, length - 1));
+        done(0)
+    }
+
+    fn string_int_value(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let string = args[0] as usize;
+        let length = try!(self.read(string + 1)) as usize;
+        let mut value: Word = 0;
+        let mut negative = false;
+        for i in 0..length {
+            let c = try!(self.read(string + 2 + i));
+            if i == 0 && c == '-' as Word {
+                negative = true;
+            } else if c >= '0' as Word && c <= '9' as Word {
+                value = value.wrapping_mul(10).wrapping_add(c - '0' as Word);
+            } else {
+                break
+            }
+        }
+        done(if negative { value.wrapping_neg() } else { value })
+    }
+
+    fn string_set_int(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let string = args[0] as usize;
+        let digits = signed(args[1]).to_string();
+        if digits.len() > try!(self.read(string)) as usize {
+            return self.os_error(19)
+        }
+        for (i, c) in digits.bytes().enumerate() {
+            try!(self.write(string + 2 + i, c as Word));
+        }
+        try!(self.write(string + 1, digits.len() as Word));
+        done(0)
+    }
+
+    fn string_backspace(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        done(BACKSPACE)
+    }
+
+    fn string_double_quote(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        done(DOUBLE_QUOTE)
+    }
+
+    fn string_new_line(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        done(NEW_LINE)
+    }
+
+    fn array_new(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        if signed(args[0]) <= 0 {
+            return self.os_error(2)
+        }
+        self.call("Memory.alloc", &[args[0]]).map(Some)
+    }
+
+    fn output_init(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        self.os.row = 0;
+        self.os.column = 0;
+        done(0)
+    }
+
+    fn output_move_cursor(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let (row, column) = (args[0] as usize, args[1] as usize);
+        if row >= ROWS || column >= COLUMNS {
+            return self.os_error(20)
+        }
+        self.os.row = row;
+        self.os.column = column;
+        done(0)
+    }
+
+    // Draw a character in the cell under the cursor, without moving it
+    fn draw_char(&mut self, glyph: &[u8; 11]) {
+        let (row, column) = (self.os.row, self.os.column);
+        for (i, &bits) in glyph.iter().enumerate() {
+            let address = SCREEN + (row * 11 + i) * 32 + column / 2;
+            self.ram[address] = if column % 2 == 0 {
+                (self.ram[address] & 0xFF00) | bits as Word
+            } else {
+                (self.ram[address] & 0x00FF) | (bits as Word) << 8
+            };
+        }
+    }
+
+    fn output_print_char(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        match args[0] {
+            NEW_LINE => return self.output_println(&[]),
+            BACKSPACE => return self.output_backspace(&[]),
+            c => {
+                let glyph = if c >= 32 && c < 127 { &FONT[c as usize - 32] } else { &BLOCK };
+                self.draw_char(glyph);
+            }
+        }
+        self.os.column += 1;
+        if self.os.column == COLUMNS {
+            return self.output_println(&[])
+        }
+        done(0)
+    }
+
+    fn output_print_string(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let length = try!(self.call("String.length", &[args[0]]));
+        for i in 0..length {
+            let c = try!(self.call("String.charAt", &[args[0], i]));
+            try!(self.output_print_char(&[c]));
+        }
+        done(0)
+    }
+
+    fn output_print_int(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        for c in signed(args[0]).to_string().bytes() {
+            try!(self.output_print_char(&[c as Word]));
+        }
+        done(0)
+    }
+
+    fn output_println(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        self.os.column = 0;
+        self.os.row = (self.os.row + 1) % ROWS;
+        done(0)
+    }
+
+    fn output_backspace(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        if self.os.column > 0 {
+            self.os.column -= 1;
+        } else if self.os.row > 0 {
+            self.os.row -= 1;
+            self.os.column = COLUMNS - 1;
+        }
+        self.draw_char(&FONT[0]);
+        done(0)
+    }
+
+    fn screen_init(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        self.os.white = false;
+        done(0)
+    }
+
+    fn screen_clear(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        for word in &mut self.ram[SCREEN..KEYBOARD] {
+            *word = 0;
+        }
+        done(0)
+    }
+
+    fn screen_set_color(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        self.os.white = args[0] == 0;
+        done(0)
+    }
+
+    fn on_screen(x: i32, y: i32) -> bool {
+        x >= 0 && x < SCREEN_WIDTH && y >= 0 && y < SCREEN_HEIGHT
+    }
+
+    // Pixels are drawn in the current color, with coordinates already checked
+    fn pixel(&mut self, x: i32, y: i32) {
+        let address = SCREEN + (y * 32 + x / 16) as usize;
+        let bit = 1 << (x % 16);
+        if self.os.white {
+            self.ram[address] &= !bit;
+        } else {
+            self.ram[address] |= bit;
+        }
+    }
+
+    fn line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32) {
+        // The algorithm of the book, stepping towards the end along whichever
+        // axis keeps the line closest
+        let (dx, dy) = ((x2 - x1).abs(), (y2 - y1).abs());
+        let (step_x, step_y) = (if x2 < x1 { -1 } else { 1 }, if y2 < y1 { -1 } else { 1 });
+        let (mut a, mut b, mut diff) = (0, 0, 0);
+        while a <= dx && b <= dy {
+            self.pixel(x1 + a * step_x, y1 + b * step_y);
+            if dx == 0 {
+                b += 1;
+            } else if dy == 0 || diff < 0 {
+                a += 1;
+                diff += dy;
+            } else {
+                b += 1;
+                diff -= dx;
+            }
+        }
+    }
+
+    fn screen_draw_pixel(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let (x, y) = (signed(args[0]), signed(args[1]));
+        if !Vm::on_screen(x, y) {
+            return self.os_error(7)
+        }
+        self.pixel(x, y);
+        done(0)
+    }
+
+    fn screen_draw_line(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let (x1, y1, x2, y2) = (signed(args[0]), signed(args[1]), signed(args[2]), signed(args[3]));
+        if !Vm::on_screen(x1, y1) || !Vm::on_screen(x2, y2) {
+            return self.os_error(8)
+        }
+        self.line(x1, y1, x2, y2);
+        done(0)
+    }
+
+    fn screen_draw_rectangle(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let (x1, y1, x2, y2) = (signed(args[0]), signed(args[1]), signed(args[2]), signed(args[3]));
+        if x1 > x2 || y1 > y2 || !Vm::on_screen(x1, y1) || !Vm::on_screen(x2, y2) {
+            return self.os_error(9)
+        }
+        for y in y1..y2 + 1 {
+            for x in x1..x2 + 1 {
+                self.pixel(x, y);
+            }
+        }
+        done(0)
+    }
+
+    fn screen_draw_circle(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let (x, y, r) = (signed(args[0]), signed(args[1]), signed(args[2]));
+        if !Vm::on_screen(x, y) {
+            return self.os_error(12)
+        }
+        if r < 0 || !Vm::on_screen(x - r, y - r) || !Vm::on_screen(x + r, y + r) {
+            return self.os_error(13)
+        }
+        for dy in -r..r + 1 {
+            let half = ((r * r - dy * dy) as f64).sqrt() as i32;
+            self.line(x - half, y + dy, x + half, y + dy);
+        }
+        done(0)
+    }
+
+    fn keyboard_key_pressed(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        done(self.ram[KEYBOARD])
+    }
+
+    fn keyboard_read_char(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        if !self.os.cursor_shown {
+            try!(self.call("Output.printChar", &[0]));
+            self.os.cursor_shown = true;
+        }
+        let pressed = self.ram[KEYBOARD];
+        let c = match self.os.key {
+            None if pressed != 0 => {
+                self.os.key = Some(pressed);
+                return Ok(None)
+            }
+            Some(c) if pressed == 0 => c,
+            _ => return Ok(None),
+        };
+
+        self.os.key = None;
+        self.os.cursor_shown = false;
+        try!(self.call("Output.backSpace", &[]));
+        try!(self.call("Output.printChar", &[c]));
+        done(c)
+    }
+
+    fn keyboard_read_line(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let line = match self.os.line {
+            Some(line) => line,
+            None => {
+                try!(self.call("Output.printString", &[args[0]]));
+                let line = try!(self.call("String.new", &[LINE_LENGTH]));
+                self.os.line = Some(line);
+                line
+            }
+        };
+
+        let c = match try!(self.keyboard_read_char(&[])) {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        if c == NEW_LINE {
+            self.os.line = None;
+            return done(line)
+        }
+        if c == BACKSPACE {
+            if try!(self.call("String.length", &[line])) > 0 {
+                try!(self.call("String.eraseLastChar", &[line]));
+            }
+        } else if try!(self.call("String.length", &[line])) < LINE_LENGTH {
+            try!(self.call("String.appendChar", &[line, c]));
+        }
+        Ok(None)
+    }
+
+    fn keyboard_read_int(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let line = match try!(self.keyboard_read_line(args)) {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        let value = try!(self.call("String.intValue", &[line]));
+        try!(self.call("String.dispose", &[line]));
+        done(value)
+    }
+
+    // The heap is a list of free blocks, each starting with its size,
+    // header included, and the address of the next one. An allocated block
+    // keeps its size in front of the object.
+    fn memory_init(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        self.os.heap_ready = true;
+        self.os.free = HEAP_BASE;
+        self.ram[HEAP_BASE] = (HEAP_END - HEAP_BASE) as Word;
+        self.ram[HEAP_BASE + 1] = 0;
+        done(0)
+    }
+
+    fn memory_peek(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        self.ram_at(args[0]).map(Some)
+    }
+
+    fn memory_poke(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        try!(self.write(args[0] as usize, args[1]));
+        done(0)
+    }
+
+    fn memory_alloc(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        if signed(args[0]) <= 0 {
+            return self.os_error(5)
+        }
+        if !self.os.heap_ready {
+            try!(self.memory_init(&[]));
+        }
+
+        let needed = args[0] as usize + 1;
+        let (mut previous, mut block) = (None, self.os.free);
+        while block != 0 {
+            let size = try!(self.heap_block(block));
+            let next = try!(self.read(block + 1)) as usize;
+            if next != 0 && (next < HEAP_BASE || next >= HEAP_END) {
+                return Err(format!("Corrupt heap: the free block at {} links to {}", block, next))
+            }
+            if size >= needed + 2 {
+                // Split the object off the end of the block
+                try!(self.write(block, (size - needed) as Word));
+                let object = block + size - needed;
+                try!(self.write(object, needed as Word));
+                return done(object as Word + 1)
+            }
+            if size >= needed {
+                match previous {
+                    Some(previous) => try!(self.write(previous + 1, next as Word)),
+                    None => self.os.free = next,
+                }
+                return done(block as Word + 1)
+            }
+            previous = Some(block);
+            block = next;
+        }
+        self.os_error(6)
+    }
+
+    fn memory_dealloc(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        let block = try!((args[0] as usize).checked_sub(1).ok_or("Can't free null".to_string()));
+        if block < HEAP_BASE || block >= HEAP_END {
+            return Err(format!("Not a heap object: {}", args[0]))
+        }
+        try!(self.heap_block(block));
+        try!(self.write(block + 1, self.os.free as Word));
+        self.os.free = block;
+        done(0)
+    }
+
+    // The size of the heap block at `block`, if its header is one that fits
+    // in the heap. Programs writing past the end of an array overwrite them.
+    fn heap_block(&self, block: usize) -> Result<usize, String> {
+        let size = try!(self.read(block)) as usize;
+        if size < 2 || block + size > HEAP_END {
+            return Err(format!("Corrupt heap: the block at {} has size {}", block, size))
+        }
+        Ok(size)
+    }
+
+    fn sys_halt(&mut self, _: &[Word]) -> Result<Option<Word>, String> {
+        self.os.halted = true;
+        done(0)
+    }
+
+    fn sys_error(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        self.os_error(args[0])
+    }
+
+    fn sys_wait(&mut self, args: &[Word]) -> Result<Option<Word>, String> {
+        if signed(args[0]) < 0 {
+            return self.os_error(1)
+        }
+        let until = match self.os.wait_until {
+            Some(until) => until,
+            None => {
+                let until = self.steps + args[0] as u64 * STEPS_PER_MILLISECOND;
+                self.os.wait_until = Some(until);
+                until
+            }
+        };
+        if self.steps < until {
+            return Ok(None)
+        }
+        self.os.wait_until = None;
+        done(0)
+    }
+}
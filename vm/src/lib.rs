@@ -0,0 +1,76 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub use code_writer::CodeWriter;
+pub use emulator::{Vm, Source, Function};
+pub use parser::{parse, Command, Line, Op, Segment};
+
+mod code_writer;
+pub mod emulator;
+mod parser;
+
+/// Translate VM files, given as their names without extension and their
+/// source, into Hack assembly. The bootstrap code calling `Sys.init` is
+/// added when one of the files is `Sys`.
+pub fn translate(files: &[(&str, &str)]) -> Result<String, String> {
+    let mut writer = CodeWriter::new();
+    if files.iter().any(|&(name, _)| name == "Sys") {
+        writer.write_init();
+    }
+
+    for &(name, source) in files {
+        let commands = try!(parse(source).map_err(|e| format!("{}.vm: {}", name, e)));
+        writer.set_file_name(name);
+        for line in &commands {
+            writer.write(&line.command);
+        }
+    }
+
+    Ok(writer.finish())
+}
+
+/// Translate a .vm file, or every .vm file in a directory
+pub fn translate_path<P: AsRef<Path>>(path: P) -> Result<String, String> {
+    let sources = try!(read_sources(path.as_ref()));
+    let files: Vec<(&str, &str)> = sources.iter().map(|&(ref name, ref source)| (&name[..], &source[..])).collect();
+    translate(&files)
+}
+
+/// Load a .vm file, or every .vm file in a directory, into the emulator
+pub fn load_path<P: AsRef<Path>>(path: P) -> Result<Vm, String> {
+    let sources = try!(read_sources(path.as_ref()));
+    let files: Vec<(&str, &str)> = sources.iter().map(|&(ref name, ref source)| (&name[..], &source[..])).collect();
+    Vm::new(&files)
+}
+
+// The names and sources of the .vm files at `path`
+fn read_sources(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let mut sources = vec![];
+    for file in try!(vm_files(path)) {
+        let mut source = String::new();
+        try!(File::open(&file).and_then(|mut f| f.read_to_string(&mut source))
+                              .map_err(|e| format!("Failed to read '{}': {}", file.display(), e)));
+        let name = file.file_stem().unwrap().to_string_lossy().into_owned();
+        sources.push((name, source));
+    }
+    Ok(sources)
+}
+
+/// The .vm files at `path`, a file or a directory of them
+pub fn vm_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()])
+    }
+
+    let entries = try!(fs::read_dir(path).map_err(|e| format!("Failed to read '{}': {}", path.display(), e)));
+    let mut files: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|e| e.path()))
+                                         .filter(|p| p.extension().map_or(false, |ext| ext == "vm"))
+                                         .collect();
+    if files.is_empty() {
+        return Err(format!("No .vm files in '{}'", path.display()))
+    }
+
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,153 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op { Add, Sub, Neg, Eq, Gt, Lt, And, Or, Not }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Segment { Argument, Local, Static, Constant, This, That, Pointer, Temp }
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command<'a> {
+    Arithmetic(Op),
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Label(&'a str),
+    Goto(&'a str),
+    IfGoto(&'a str),
+    /// A function's name and its number of local variables
+    Function(&'a str, u16),
+    /// A function's name and its number of arguments
+    Call(&'a str, u16),
+    Return,
+}
+
+impl Op {
+    fn parse(s: &str) -> Option<Op> {
+        let op = match s {
+            "add" => Op::Add,
+            "sub" => Op::Sub,
+            "neg" => Op::Neg,
+            "eq" => Op::Eq,
+            "gt" => Op::Gt,
+            "lt" => Op::Lt,
+            "and" => Op::And,
+            "or" => Op::Or,
+            "not" => Op::Not,
+            _ => return None
+        };
+
+        Some(op)
+    }
+}
+
+impl Segment {
+    fn parse(s: &str) -> Result<Segment, String> {
+        let segment = match s {
+            "argument" => Segment::Argument,
+            "local" => Segment::Local,
+            "static" => Segment::Static,
+            "constant" => Segment::Constant,
+            "this" => Segment::This,
+            "that" => Segment::That,
+            "pointer" => Segment::Pointer,
+            "temp" => Segment::Temp,
+            _ => return Err(format!("Unknown segment: '{}'", s))
+        };
+
+        Ok(segment)
+    }
+
+    /// The number of entries in the segment, for those of a fixed size
+    pub fn size(&self) -> Option<u16> {
+        match *self {
+            Segment::Pointer => Some(2),
+            Segment::Temp => Some(8),
+            Segment::Constant => Some(32768),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Segment::Argument => "argument",
+            Segment::Local => "local",
+            Segment::Static => "static",
+            Segment::Constant => "constant",
+            Segment::This => "this",
+            Segment::That => "that",
+            Segment::Pointer => "pointer",
+            Segment::Temp => "temp",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A command along with the line of the source it came from, counted from 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line<'a> {
+    pub number: usize,
+    pub command: Command<'a>,
+}
+
+/// Parse VM code into its commands
+pub fn parse(source: &str) -> Result<Vec<Line>, String> {
+    let mut commands = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let code = line.splitn(2, "//").next().unwrap().trim();
+        if code.is_empty() {
+            continue
+        }
+
+        let command = try!(parse_command(code).map_err(|e| format!("line {}: {}", i + 1, e)));
+        commands.push(Line { number: i + 1, command: command });
+    }
+
+    Ok(commands)
+}
+
+fn parse_command(code: &str) -> Result<Command, String> {
+    let words: Vec<&str> = code.split_whitespace().collect();
+    let arg = |i: usize, what: &str| -> Result<&str, String> {
+        words.get(i).cloned().ok_or(format!("Missing {} for '{}'", what, words[0]))
+    };
+    let number = |i: usize, what: &str| -> Result<u16, String> {
+        let word = try!(arg(i, what));
+        word.parse().map_err(|e| format!("Invalid {} '{}': {}", what, word, e))
+    };
+
+    let (command, used) = match words[0] {
+        "push" | "pop" => {
+            let segment = try!(Segment::parse(try!(arg(1, "segment"))));
+            let index = try!(number(2, "index"));
+            if segment.size().map_or(false, |size| index >= size) {
+                return Err(format!("Index out of range for the {} segment: {}", segment, index))
+            }
+
+            if words[0] == "push" {
+                (Command::Push(segment, index), 3)
+            } else if segment == Segment::Constant {
+                return Err("Cannot pop to the constant segment".to_string())
+            } else {
+                (Command::Pop(segment, index), 3)
+            }
+        }
+        "label" => (Command::Label(try!(arg(1, "label"))), 2),
+        "goto" => (Command::Goto(try!(arg(1, "label"))), 2),
+        "if-goto" => (Command::IfGoto(try!(arg(1, "label"))), 2),
+        "function" => (Command::Function(try!(arg(1, "name")), try!(number(2, "local count"))), 3),
+        "call" => (Command::Call(try!(arg(1, "name")), try!(number(2, "argument count"))), 3),
+        "return" => (Command::Return, 1),
+        word => match Op::parse(word) {
+            Some(op) => (Command::Arithmetic(op), 1),
+            None => return Err(format!("Unknown command: '{}'", word)),
+        }
+    };
+
+    if let Some(extra) = words.get(used) {
+        return Err(format!("Unexpected '{}' after '{}'", extra, words[..used].join(" ")))
+    }
+
+    Ok(command)
+}
@@ -0,0 +1,93 @@
+extern crate hack_vm;
+
+use hack_vm::*;
+
+#[macro_use] mod macros;
+
+// The segment pointers the official VM emulator scripts start with
+static SETUP: &'static [(usize, u16)] = &[(0, 256), (1, 300), (2, 400), (3, 3000), (4, 3010)];
+// For the programs starting at Sys.init, as if called by the bootstrap code
+static SYS_SETUP: &'static [(usize, u16)] = &[(0, 261), (1, 261), (2, 256), (3, 3000), (4, 4000)];
+
+fn run(path: &str, steps: u64) -> Vm {
+    let mut vm = load_path(format!("tests/data/{}", path)).unwrap();
+    let setup = if vm.current_function() == Some("Sys.init") { SYS_SETUP } else { SETUP };
+    for &(address, value) in setup {
+        vm.ram[address] = value;
+    }
+    vm.run(steps).unwrap();
+    vm
+}
+
+check! {
+    official_tests for {
+        simple_add          ("SimpleAdd.vm", 3) => &[(0, 257), (256, 15)],
+        stack_test          ("StackTest.vm", 38) => &[(0, 266), (256, -1), (257, 0), (258, 0), (259, 0),
+                                                     (260, -1), (261, 0), (262, -1), (263, 0), (264, 0),
+                                                     (265, -91)],
+        basic_test          ("BasicTest.vm", 25) => &[(256, 472), (300, 10), (401, 21), (402, 22),
+                                                    (3006, 36), (3012, 42), (3015, 45), (11, 510)],
+        pointer_test        ("PointerTest.vm", 15) => &[(256, 6084), (3, 3030), (4, 3040), (3032, 32),
+                                                      (3046, 46)],
+        statics_test        ("StaticsTest", 36) => &[(0, 263), (261, -2), (262, 8)],
+        fibonacci_element   ("FibonacciElement", 110) => &[(0, 262), (261, 3)]
+    } do |program, expected| {
+        let (path, steps) = program;
+        let vm = run(path, steps);
+        let expected: &[(usize, i16)] = expected;
+        for &(address, value) in expected {
+            assert_eq!((address, vm.ram[address] as i16), (address, value));
+        }
+    }
+}
+
+#[test]
+fn statics_are_allocated_per_file() {
+    let mut vm = Vm::new(&[("Foo", "push constant 7\npop static 1\npush constant 8\npop static 0\n"),
+                           ("Bar", "push constant 9\npop static 0\n")]).unwrap();
+    vm.ram[0] = 256;
+    vm.run(6).unwrap();
+    assert_eq!(&vm.ram[16..19], &[8, 7, 9]);
+}
+
+#[test]
+fn call_stack() {
+    let mut vm = Vm::new(&[("Main", "function Main.start 0\npush constant 2\ncall Main.double 1\nreturn\n\
+                                     function Main.double 0\npush argument 0\npush argument 0\nadd\nreturn\n")]).unwrap();
+    for &(address, value) in SYS_SETUP {
+        vm.ram[address] = value;
+    }
+    vm.run(4).unwrap();
+    assert_eq!(vm.current_function(), Some("Main.double"));
+    assert_eq!(vm.call_stack(), vec!["Main.double"]);
+    assert_eq!(vm.source().map(|s| (s.line, &s.text[..])), Some((6, "push argument 0")));
+
+    vm.run(4).unwrap();
+    assert!(vm.call_stack().is_empty());
+    assert_eq!(vm.ram[0], 262);
+    assert_eq!(vm.ram[261], 4);
+    assert_eq!(vm.ram[1], 261);
+}
+
+#[test]
+fn halts_at_the_end() {
+    let mut vm = Vm::new(&[("Main", "push constant 1\n")]).unwrap();
+    vm.ram[0] = 256;
+    vm.run(5).unwrap();
+    assert!(vm.halted());
+    assert_eq!((vm.ram[0], vm.ram[256], vm.steps()), (257, 1, 1));
+}
+
+#[test]
+fn errors() {
+    assert_eq!(Vm::new(&[("Main", "goto NOWHERE")]).err(),
+               Some("Main.vm: line 1: Unknown label: 'NOWHERE'".to_string()));
+    assert_eq!(Vm::new(&[("Main", "function Main.f 0\nlabel A\n\nfunction Main.g 0\ngoto A")]).err(),
+               Some("Main.vm: line 5: Unknown label: 'A'".to_string()));
+    assert_eq!(Vm::new(&[("Main", "call Math.power 2")]).err(),
+               Some("Main.vm: line 1: Unknown function: 'Math.power'".to_string()));
+
+    // Popping with SP still at 0
+    let mut vm = Vm::new(&[("Main", "label START\nneg\n")]).unwrap();
+    assert_eq!(vm.run(1), Err("Main.vm: line 2: 'neg': Address out of range: 65535".to_string()));
+}
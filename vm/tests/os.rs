@@ -0,0 +1,312 @@
+extern crate hack_vm;
+
+use hack_vm::*;
+
+const SCREEN: usize = 16384;
+const KEYBOARD: usize = 24576;
+
+fn load(files: &[(&str, &str)]) -> Vm {
+    let mut vm = Vm::new(files).unwrap();
+    vm.ram[0] = 256;
+    vm
+}
+
+// Results of calls, with errors from VM code stripped of where they happened
+fn call(vm: &mut Vm, function: &str, args: &[u16]) -> Result<u16, String> {
+    vm.call(function, args).map_err(|e| e.rsplit("': ").next().unwrap().to_string())
+}
+
+fn run_main(main: &str, steps: u64) -> Vm {
+    let mut vm = Vm::new(&[("Main", main)]).unwrap();
+    vm.run(steps).unwrap();
+    vm
+}
+
+#[test]
+fn math_matches_the_book_implementation() {
+    let mut builtin = load(&[]);
+    let mut user = load(&[("Math", include_str!("data/Os/Math.vm"))]);
+    user.call("Math.init", &[]).unwrap();
+
+    let values: &[i16] = &[0, 1, -1, 2, 7, -7, 13, 100, -100, 181, 182, 1000, -3000, 32767, -32767];
+    for &x in values {
+        for &y in values {
+            for &function in &["Math.multiply", "Math.divide", "Math.min", "Math.max"] {
+                if function == "Math.divide" && y == 0 {
+                    continue
+                }
+                let args = [x as u16, y as u16];
+                assert_eq!((function, x, y, call(&mut builtin, function, &args)),
+                           (function, x, y, call(&mut user, function, &args)));
+            }
+        }
+        for &function in &["Math.abs", "Math.sqrt"] {
+            if function == "Math.sqrt" && x < 0 {
+                continue
+            }
+            assert_eq!((function, x, call(&mut builtin, function, &[x as u16])),
+                       (function, x, call(&mut user, function, &[x as u16])));
+        }
+    }
+    // Errors halt the program
+    assert_eq!(call(&mut builtin, "Math.sqrt", &[-1i16 as u16]),
+               Err("Cannot compute square root of a negative number (error code 4)".to_string()));
+    assert_eq!(call(&mut user, "Math.divide", &[1, 0]), Err("Division by zero (error code 3)".to_string()));
+    assert!(builtin.halted() && user.halted());
+}
+
+#[test]
+fn memory_matches_the_book_implementation() {
+    let load_both = || {
+        let mut user = load(&[("Memory", include_str!("data/Os/Memory.vm"))]);
+        user.call("Memory.init", &[]).unwrap();
+        (load(&[]), user)
+    };
+    let (mut builtin, mut user) = load_both();
+
+    // Sizes to allocate, and which earlier allocation to free after each
+    let steps: &[(u16, Option<usize>)] = &[(10, None), (3, None), (1, None), (100, Some(1)), (2, None),
+                                          (3, Some(0)), (10, None), (9, Some(2)), (1, None), (2, Some(6)),
+                                          (5000, None), (4, Some(4)), (1, None), (8000, None)];
+    let mut objects = vec![];
+    for &(size, free) in steps {
+        let object = call(&mut builtin, "Memory.alloc", &[size]);
+        assert_eq!((size, &object), (size, &call(&mut user, "Memory.alloc", &[size])));
+        objects.push(object.unwrap());
+        if let Some(i) = free {
+            assert_eq!(call(&mut builtin, "Memory.deAlloc", &[objects[i]]),
+                       call(&mut user, "Memory.deAlloc", &[objects[i]]));
+        }
+    }
+    assert_eq!(call(&mut builtin, "Array.new", &[7]), call(&mut user, "Array.new", &[7]));
+    assert!(builtin.ram[2048..16384] == user.ram[2048..16384]);
+
+    // Errors halt the program
+    let overflow = call(&mut builtin, "Memory.alloc", &[1200]);
+    assert_eq!(overflow, Err("Heap overflow (error code 6)".to_string()));
+    assert_eq!(overflow, call(&mut user, "Memory.alloc", &[1200]));
+    for &size in &[0, -1i16 as u16] {
+        let (mut builtin, mut user) = load_both();
+        assert_eq!(call(&mut builtin, "Memory.alloc", &[size]),
+                   Err("Allocated memory size must be positive (error code 5)".to_string()));
+        assert_eq!(call(&mut user, "Memory.alloc", &[size]),
+                   Err("Allocated memory size must be positive (error code 5)".to_string()));
+    }
+}
+
+// The results of calling String methods on a new string, up to the first
+// error, and the characters it holds after them
+fn string_calls(vm: &mut Vm, capacity: i16, calls: &[(&str, &[u16])]) -> (Vec<Result<u16, String>>, Vec<u16>) {
+    let string = match call(vm, "String.new", &[capacity as u16]) {
+        Ok(string) => string,
+        Err(e) => return (vec![Err(e)], vec![]),
+    };
+    let mut results = vec![];
+    for &(function, args) in calls {
+        let args: Vec<u16> = Some(string).into_iter().chain(args.iter().cloned()).collect();
+        // appendChar gives back the string, which is at a different address in each
+        let result = call(vm, function, &args).map(|value| {
+            if function == "String.appendChar" && value == string { 0 } else { value }
+        });
+        results.push(result.clone());
+        if result.is_err() {
+            return (results, vec![])
+        }
+    }
+    let length = vm.call("String.length", &[string]).unwrap();
+    let chars = (0..length).map(|i| vm.call("String.charAt", &[string, i]).unwrap()).collect();
+    (results, chars)
+}
+
+#[test]
+fn string_matches_the_book_implementation() {
+    let compare = |capacity: i16, calls: &[(&str, &[u16])]| {
+        let mut user = load(&[("String", include_str!("data/Os/String.vm"))]);
+        let builtin = string_calls(&mut load(&[]), capacity, calls);
+        assert_eq!((capacity, calls, &builtin), (capacity, calls, &string_calls(&mut user, capacity, calls)));
+        builtin
+    };
+    let append = |s: &str| s.chars().map(|c| ("String.appendChar", vec![c as u16])).collect::<Vec<_>>();
+
+    for &value in &[0, 1, -1, 7, 42, -100, 1234, -9999, 32767, -32767, -32768i16] {
+        compare(6, &[("String.setInt", &[value as u16]), ("String.intValue", &[]), ("String.length", &[])]);
+    }
+    for &text in &["", "-", "12a4", "-56", "--3", "x9", "007", "65536"] {
+        let appended = append(text);
+        let mut calls: Vec<(&str, &[u16])> = appended.iter().map(|&(f, ref args)| (f, &args[..])).collect();
+        calls.push(("String.intValue", &[]));
+        compare(6, &calls);
+    }
+    let (results, chars) = compare(6, &[("String.setInt", &[-123i16 as u16]), ("String.eraseLastChar", &[]),
+                                        ("String.setCharAt", &[0, '4' as u16]), ("String.appendChar", &['x' as u16]),
+                                        ("String.charAt", &[2]), ("String.intValue", &[])]);
+    assert_eq!(results.last(), Some(&Ok(412)));
+    assert_eq!(chars, vec!['4' as u16, '1' as u16, '2' as u16, 'x' as u16]);
+
+    // Errors halt the program
+    let error = |capacity: i16, calls: &[(&str, &[u16])]| compare(capacity, calls).0.pop().unwrap().unwrap_err();
+    assert_eq!(error(-1, &[]), "Maximum length must be non-negative (error code 14)");
+    assert_eq!(error(0, &[("String.charAt", &[0])]), "String index out of bounds (error code 15)");
+    assert_eq!(error(2, &[("String.appendChar", &[65]), ("String.charAt", &[-1i16 as u16])]),
+               "String index out of bounds (error code 15)");
+    assert_eq!(error(2, &[("String.setCharAt", &[1, 65])]), "String index out of bounds (error code 16)");
+    assert_eq!(error(1, &[("String.appendChar", &[65]), ("String.appendChar", &[66])]),
+               "String is full (error code 17)");
+    assert_eq!(error(0, &[("String.eraseLastChar", &[])]), "String is empty (error code 18)");
+    assert_eq!(error(3, &[("String.setInt", &[1234])]), "Insufficient string capacity (error code 19)");
+    assert_eq!(error(5, &[("String.setInt", &[-32768i16 as u16])]), "Insufficient string capacity (error code 19)");
+
+    let mut user = load(&[("String", include_str!("data/Os/String.vm"))]);
+    for &function in &["String.newLine", "String.backSpace", "String.doubleQuote"] {
+        assert_eq!(call(&mut load(&[]), function, &[]), call(&mut user, function, &[]));
+    }
+}
+
+#[test]
+fn memory() {
+    let mut vm = load(&[]);
+    let a = vm.call("Memory.alloc", &[10]).unwrap();
+    let b = vm.call("Array.new", &[3]).unwrap();
+    assert!(a >= 2048 && b >= 2048 && (b + 3 <= a || a + 10 <= b));
+
+    vm.call("Memory.poke", &[a, 42]).unwrap();
+    assert_eq!(vm.call("Memory.peek", &[a]), Ok(42));
+    vm.call("Memory.deAlloc", &[a]).unwrap();
+    assert_eq!(vm.call("Memory.alloc", &[10]), Ok(a));
+
+    assert_eq!(vm.call("Memory.alloc", &[20000]), Err("Heap overflow (error code 6)".to_string()));
+    assert_eq!(vm.call("Array.new", &[0]), Err("Array size must be positive (error code 2)".to_string()));
+
+    // Writing past the end of an array overwrites the next block's header
+    let mut vm = load(&[]);
+    let array = vm.call("Array.new", &[2]).unwrap();
+    vm.call("Memory.poke", &[2048, 30000]).unwrap();
+    assert_eq!(vm.call("Array.new", &[5]), Err("Corrupt heap: the block at 2048 has size 30000".to_string()));
+    vm.call("Memory.poke", &[array - 1, 0]).unwrap();
+    assert_eq!(vm.call("Array.dispose", &[array]),
+               Err(format!("Corrupt heap: the block at {} has size 0", array - 1)));
+}
+
+#[test]
+fn strings() {
+    let mut vm = load(&[]);
+    let s = vm.call("String.new", &[6]).unwrap();
+    vm.call("String.setInt", &[s, -123i16 as u16]).unwrap();
+    assert_eq!(vm.call("String.length", &[s]), Ok(4));
+    assert_eq!(vm.call("String.charAt", &[s, 0]), Ok('-' as u16));
+    assert_eq!(vm.call("String.intValue", &[s]), Ok(-123i16 as u16));
+
+    vm.call("String.eraseLastChar", &[s]).unwrap();
+    vm.call("String.setCharAt", &[s, 0, '4' as u16]).unwrap();
+    assert_eq!(vm.call("String.appendChar", &[s, 'x' as u16]), Ok(s));
+    assert_eq!(vm.call("String.intValue", &[s]), Ok(412));
+
+    vm.call("String.appendChar", &[s, 'y' as u16]).unwrap();
+    vm.call("String.appendChar", &[s, 'z' as u16]).unwrap();
+    assert_eq!(vm.call("String.appendChar", &[s, '!' as u16]), Err("String is full (error code 17)".to_string()));
+    assert_eq!(vm.call("String.charAt", &[s, 6]), Err("String index out of bounds (error code 15)".to_string()));
+    assert_eq!(vm.call("String.newLine", &[]), Ok(128));
+}
+
+#[test]
+fn output() {
+    let mut vm = load(&[]);
+    vm.call("Output.printChar", &['A' as u16]).unwrap();
+    vm.call("Output.printChar", &['1' as u16]).unwrap();
+    let rows: Vec<u16> = (0..11).map(|row| vm.ram[SCREEN + row * 32]).collect();
+    // 'A' on the left byte, '1' on the right one
+    assert_eq!(rows, vec![12 | 12 << 8, 30 | 14 << 8, 51 | 15 << 8, 51 | 12 << 8, 63 | 12 << 8, 51 | 12 << 8,
+                          51 | 12 << 8, 51 | 12 << 8, 51 | 63 << 8, 0, 0]);
+
+    vm.call("Output.println", &[]).unwrap();
+    vm.call("Output.printInt", &[-5i16 as u16]).unwrap();
+    assert_eq!(vm.ram[SCREEN + (11 + 5) * 32], 63 | 48 << 8);
+
+    vm.call("Output.backSpace", &[]).unwrap();
+    assert_eq!(vm.ram[SCREEN + (11 + 5) * 32], 63);
+    assert_eq!(vm.call("Output.moveCursor", &[23, 0]), Err("Illegal cursor location (error code 20)".to_string()));
+}
+
+#[test]
+fn screen() {
+    let mut vm = load(&[]);
+    vm.call("Screen.drawLine", &[0, 0, 15, 0]).unwrap();
+    vm.call("Screen.drawRectangle", &[16, 1, 47, 2]).unwrap();
+    vm.call("Screen.drawLine", &[0, 3, 3, 6]).unwrap();
+    assert_eq!(&vm.ram[SCREEN..SCREEN + 3], &[0xFFFF, 0, 0]);
+    assert_eq!(&vm.ram[SCREEN + 32..SCREEN + 35], &[0, 0xFFFF, 0xFFFF]);
+    let diagonal: Vec<u16> = (3..7).map(|y| vm.ram[SCREEN + y * 32]).collect();
+    assert_eq!(diagonal, vec![1, 3, 6, 12]);
+
+    vm.call("Screen.setColor", &[0]).unwrap();
+    vm.call("Screen.drawPixel", &[17, 1]).unwrap();
+    assert_eq!(vm.ram[SCREEN + 33], 0xFFFD);
+
+    vm.call("Screen.setColor", &[0xFFFF]).unwrap();
+    vm.call("Screen.drawCircle", &[100, 100, 10]).unwrap();
+    assert_eq!(vm.ram[SCREEN + 90 * 32 + 6], 1 << 4);
+    assert_eq!(vm.call("Screen.drawCircle", &[5, 5, 10]), Err("Illegal radius (error code 13)".to_string()));
+    assert_eq!(vm.call("Screen.drawPixel", &[512, 0]), Err("Illegal pixel coordinates (error code 7)".to_string()));
+
+    vm.call("Screen.clearScreen", &[]).unwrap();
+    assert!(vm.ram[SCREEN..KEYBOARD].iter().all(|&word| word == 0));
+}
+
+#[test]
+fn programs_start_with_the_builtin_sys_init() {
+    let mut vm = run_main("function Main.main 0\npush constant 6\npush constant 7\ncall Math.multiply 2\n\
+                           pop static 0\npush constant 0\nreturn\n", 100);
+    assert!(vm.halted());
+    assert_eq!(vm.ram[16], 42);
+    assert_eq!(vm.ram[0], 257);
+    assert_eq!(vm.current_function(), Some("Sys.init"));
+
+    vm.reset();
+    assert!(!vm.halted());
+    vm.step().unwrap();
+    assert_eq!(vm.ram[0], 256);
+}
+
+#[test]
+fn keyboard() {
+    let mut vm = run_main("function Main.main 0\ncall Keyboard.readChar 0\npop static 0\npush constant 0\nreturn\n", 10);
+    assert!(!vm.halted());
+    // The cursor
+    assert_eq!(vm.ram[SCREEN + 32], 63);
+
+    vm.ram[KEYBOARD] = 'k' as u16;
+    vm.run(10).unwrap();
+    assert!(!vm.halted());
+    vm.ram[KEYBOARD] = 0;
+    vm.run(10).unwrap();
+    assert!(vm.halted());
+    assert_eq!(vm.ram[16], 'k' as u16);
+    assert_eq!(vm.ram[SCREEN + 3 * 32], 51);
+}
+
+#[test]
+fn sys() {
+    let mut vm = run_main("function Main.main 0\npush constant 2\ncall Sys.wait 1\npop temp 0\npush constant 0\nreturn\n", 100);
+    assert!(!vm.halted());
+    vm.run(200).unwrap();
+    assert!(vm.halted());
+
+    let mut vm = Vm::new(&[("Main", "function Main.main 0\npush constant 99\ncall Sys.error 1\nreturn\n")]).unwrap();
+    assert_eq!(vm.run(10), Err("Main.vm: line 3: 'call Sys.error 1': Error (error code 99)".to_string()));
+    assert!(vm.halted());
+}
+
+#[test]
+fn vm_classes_replace_builtin_ones() {
+    let memory = "function Memory.alloc 0\npush constant 5000\nreturn\n";
+    let main = "function Main.main 0\npush constant 3\ncall Array.new 1\npop static 0\npush constant 0\nreturn\n";
+    let mut vm = Vm::new(&[("Main", main), ("Memory", memory)]).unwrap();
+    vm.run(100).unwrap();
+    assert!(vm.halted());
+    assert_eq!(vm.ram[16], 5000);
+
+    let error = |main: &str| Vm::new(&[("Main", main), ("Memory", memory)]).err().unwrap();
+    assert_eq!(error("push constant 1\ncall Memory.peek 1\n"), "Main.vm: line 2: Unknown function: 'Memory.peek'");
+    assert_eq!(error("push constant 1\ncall Math.max 1\n"),
+               "Main.vm: line 2: 'Math.max' takes 2 arguments, but is called with 1");
+}
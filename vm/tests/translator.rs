@@ -0,0 +1,86 @@
+extern crate hack_vm;
+extern crate hack_assembler;
+extern crate hack_interpreter;
+
+use hack_vm::*;
+use hack_assembler::assemble;
+use hack_interpreter::{Cpu, Rom};
+
+#[macro_use] mod macros;
+
+// The segment pointers the official test scripts start with, for the
+// programs without bootstrap code
+static SETUP: &'static [(usize, u16)] = &[(0, 256), (1, 300), (2, 400), (3, 3000), (4, 3010)];
+
+fn run(path: &str, cycles: usize) -> Cpu {
+    let asm = translate_path(format!("tests/data/{}", path)).unwrap();
+    let mut cpu = Cpu::new(Rom::from_str(&assemble(&asm).unwrap()).unwrap());
+    for &(address, value) in SETUP {
+        cpu.ram[address] = value;
+    }
+    for _ in 0..cycles {
+        cpu.step();
+    }
+    cpu
+}
+
+check! {
+    official_tests for {
+        simple_add          ("SimpleAdd.vm", 60) => &[(0, 257), (256, 15)],
+        stack_test          ("StackTest.vm", 1000) => &[(0, 266), (256, -1), (257, 0), (258, 0), (259, 0),
+                                                       (260, -1), (261, 0), (262, -1), (263, 0), (264, 0),
+                                                       (265, -91)],
+        basic_test          ("BasicTest.vm", 600) => &[(256, 472), (300, 10), (401, 21), (402, 22),
+                                                     (3006, 36), (3012, 42), (3015, 45), (11, 510)],
+        pointer_test        ("PointerTest.vm", 450) => &[(256, 6084), (3, 3030), (4, 3040), (3032, 32),
+                                                       (3046, 46)],
+        statics_test        ("StaticsTest", 2500) => &[(0, 263), (261, -2), (262, 8)],
+        fibonacci_element   ("FibonacciElement", 6000) => &[(0, 262), (261, 3)]
+    } do |program, expected| {
+        let (path, cycles) = program;
+        let cpu = run(path, cycles);
+        let expected: &[(usize, i16)] = expected;
+        for &(address, value) in expected {
+            assert_eq!((address, cpu.ram[address] as i16), (address, value));
+        }
+    }
+}
+
+#[test]
+fn statics_are_named_per_file() {
+    let asm = translate(&[("Foo", "push static 3\npop static 0\n"), ("Bar", "push static 3\n")]).unwrap();
+    assert!(asm.contains("@Foo.3\n"));
+    assert!(asm.contains("@Foo.0\n"));
+    assert!(asm.contains("@Bar.3\n"));
+}
+
+#[test]
+fn labels_are_local_to_functions() {
+    let asm = translate(&[("Main", "function Main.f 0\nlabel LOOP\ngoto LOOP\n\
+                                    function Main.g 0\nlabel LOOP\nif-goto LOOP\n")]).unwrap();
+    assert!(asm.contains("(Main.f$LOOP)\n"));
+    assert!(asm.contains("@Main.f$LOOP\n0;JMP\n"));
+    assert!(asm.contains("(Main.g$LOOP)\n"));
+}
+
+#[test]
+fn bootstrap_only_with_sys() {
+    let asm = translate(&[("Main", "push constant 1\n")]).unwrap();
+    assert!(!asm.contains("Sys.init"));
+    let asm = translate(&[("Sys", "function Sys.init 0\nlabel END\ngoto END\n")]).unwrap();
+    assert!(asm.starts_with("// bootstrap\n@256\n"));
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!(parse("push constant 1\npush nowhere 2"), Err("line 2: Unknown segment: 'nowhere'".to_string()));
+    assert_eq!(parse("pop constant 1"), Err("line 1: Cannot pop to the constant segment".to_string()));
+    assert_eq!(parse("push temp 8"), Err("line 1: Index out of range for the temp segment: 8".to_string()));
+    assert_eq!(parse("add 3"), Err("line 1: Unexpected '3' after 'add'".to_string()));
+    assert_eq!(parse("call Foo.bar"), Err("line 1: Missing argument count for 'call'".to_string()));
+    assert_eq!(translate(&[("Main", "frobnicate")]), Err("Main.vm: line 1: Unknown command: 'frobnicate'".to_string()));
+
+    let lines = parse("// comment\n\n  push local 2 // trailing\nreturn").unwrap();
+    assert_eq!(lines, vec![Line { number: 3, command: Command::Push(Segment::Local, 2) },
+                           Line { number: 4, command: Command::Return }]);
+}